@@ -0,0 +1,117 @@
+#[macro_use]
+extern crate criterion;
+extern crate graphio_rs_data;
+extern crate num_traits;
+
+use criterion::{black_box, Criterion};
+use graphio_rs_data::parsing::parse_ratio;
+use graphio_rs_data::{parse_prototypes, Int, Ratio, TransformOptions};
+use num_traits::identities::Zero;
+
+const RATIOS: &[&str] = &[
+    // Integers
+    "0", "1", "60", "75000", "-5", "1000000",
+    // Simple decimals
+    "0.5", "1.25", "0.75", "-0.15", "2.5",
+    // Messy decimals, as produced by floating point arithmetic in the game
+    "0.30000000000000004", "1.1666666666666667", "0.016666666666666666",
+    "2.3333333333333335", "0.0001", "123.456789",
+];
+
+fn bench_parse_ratio(c: &mut Criterion) {
+    assert_eq!(parse_ratio("0.5").unwrap(), Ratio::new(Int::from(1), Int::from(2)));
+    assert_eq!(parse_ratio("-5").unwrap(), Ratio::from_integer(Int::from(-5)));
+    assert!(RATIOS.iter().all(|s| parse_ratio(s).is_ok()));
+
+    c.bench_function("parse_ratio", |b| {
+        b.iter(|| {
+            for s in RATIOS {
+                black_box(parse_ratio(black_box(s)).unwrap());
+            }
+        })
+    });
+}
+
+/// Creates the lines of an export with the given amount of machines, and
+/// ten times as many recipes and items, each recipe taking two items and
+/// producing another.
+fn synthetic_prototypes(machine_count: usize) -> Vec<String> {
+    const SEPARATOR: char = graphio_rs_data::parsing::delimiters::SEPARATOR;
+    let object_count = machine_count * 10;
+    let localised = |kind: &str, name: &str| format!("{}-name.{}{}{}", kind, name, SEPARATOR, name);
+
+    let mut lines = vec![format!(
+        "{1}{0}0{0}{2}{0}{2}{0}0",
+        SEPARATOR, machine_count, object_count
+    )];
+
+    lines.push("1".to_owned());
+    for machine in 0..machine_count {
+        let name = format!("machine-{}", machine);
+        lines.push(name.clone());
+        lines.push(localised("entity", &name));
+        lines.push(localised("entity", &name));
+        lines.push("1.25".to_owned());
+        lines.push("75000".to_owned());
+        lines.push("2500".to_owned());
+        lines.push("4".to_owned());
+        lines.push("1111".to_owned());
+    }
+
+    lines.push("1".to_owned());
+
+    lines.push("1".to_owned());
+    for recipe in 0..object_count {
+        let name = format!("recipe-{}", recipe);
+        lines.push(name.clone());
+        lines.push(localised("recipe", &name));
+        lines.push(localised("recipe", &name));
+        lines.push("0.5".to_owned());
+        lines.push("2".to_owned());
+        for ingredient in &[recipe, (recipe + 1) % object_count] {
+            lines.push("item".to_owned());
+            lines.push(format!("item-{}", ingredient));
+            lines.push("1".to_owned());
+            lines.push("0".to_owned());
+        }
+        lines.push("1".to_owned());
+        lines.push("item".to_owned());
+        lines.push(format!("item-{}", (recipe + 2) % object_count));
+        lines.push("fixed".to_owned());
+        lines.push("2".to_owned());
+        lines.push("0".to_owned());
+        lines.push("1".to_owned());
+        lines.push(format!("machine-{}", recipe % machine_count));
+    }
+
+    lines.push("1".to_owned());
+    for item in 0..object_count {
+        let name = format!("item-{}", item);
+        lines.push(name.clone());
+        lines.push(localised("item", &name));
+        lines.push(localised("item", &name));
+        lines.push("0".to_owned());
+    }
+
+    lines.push("1".to_owned());
+    lines
+}
+
+fn bench_parse_prototypes(c: &mut Criterion) {
+    let lines = synthetic_prototypes(100);
+    let game_data = parse_prototypes(lines.clone(), TransformOptions::default()).unwrap();
+    assert_eq!(game_data.machines.len(), 100);
+    assert_eq!(game_data.recipes.len(), 1000);
+    assert_eq!(game_data.items.len(), 1000);
+    assert!(game_data.recipes.iter().all(|recipe| !recipe.time.is_zero()));
+
+    c.bench_function("parse_prototypes", move |b| {
+        b.iter_with_setup(
+            || lines.clone(),
+            |lines| parse_prototypes(lines, TransformOptions::default()).unwrap(),
+        )
+    });
+}
+
+criterion_group!(benches, bench_parse_ratio, bench_parse_prototypes);
+criterion_main!(benches);
@@ -0,0 +1,278 @@
+//! Removing objects from game data after it has been extracted, such as
+//! prototypes that are hidden from the player, along with predicates to
+//! pick the objects to remove.
+
+use crate::{GameData, Metadata, MetadataObject, ProductResource, ID};
+use std::collections::HashSet;
+
+/// Whether the localised name of the object is empty.
+pub fn has_empty_name(_id: ID, metadata: &Metadata) -> bool {
+    metadata.localised_name.is_empty()
+}
+
+/// Whether the localised name of the object is its raw ID, or the key of
+/// its localisation, such as `item-name.<id>`, which is what the name falls
+/// back to when the game has no translation for it.
+pub fn has_raw_name(id: ID, metadata: &Metadata) -> bool {
+    let name = metadata.localised_name.str();
+    let id = id.str();
+    name == id
+        || name
+            .strip_suffix(id)
+            .map(|prefix| prefix.ends_with("-name."))
+            .unwrap_or(false)
+}
+
+/// Whether the object has no translation, being either not
+/// `Metadata::is_translated`, `has_empty_name`, or `has_raw_name`. Such
+/// objects are usually hidden from the player.
+pub fn is_untranslated(id: ID, metadata: &Metadata) -> bool {
+    !metadata.is_translated() || has_empty_name(id, metadata) || has_raw_name(id, metadata)
+}
+
+impl GameData {
+    /// Removes the objects in `ids`, and cleans up any references to them.
+    ///
+    /// Recipes that take or produce a removed item or fluid are removed as
+    /// well, as they can't be crafted as described anymore. Removed machines
+    /// and modules are taken out of the machines that recipes are crafted
+    /// in, and the modules that are supported.
+    ///
+    /// Returns the objects that were removed, including those recipes, sorted
+    /// by `ID::sort_key`.
+    pub fn remove_all(&mut self, ids: &HashSet<ID>) -> Vec<ID> {
+        let mut removed = self
+            .all_ids()
+            .filter(|id| ids.contains(id))
+            .collect::<Vec<_>>();
+
+        let recipes = std::mem::take(&mut self.recipes);
+        self.recipes = recipes
+            .into_iter()
+            .filter_map(|mut recipe| {
+                let id = ID::from(recipe.id);
+                if ids.contains(&id) {
+                    return None;
+                }
+                let ingredients = recipe.ingredients.iter().map(|ingredient| ingredient.resource.id());
+                let products = recipe.products.iter().map(|product| product.resource.id());
+                let main_product = recipe.main_product.iter().map(ProductResource::id);
+                if ingredients.chain(products).chain(main_product).any(|id| ids.contains(&id)) {
+                    removed.push(id);
+                    return None;
+                }
+                recipe.crafted_in.retain(|&machine| !ids.contains(&machine.into()));
+                recipe.supported_modules.retain(|&module| !ids.contains(&module.into()));
+                Some(recipe)
+            })
+            .collect();
+
+        self.items.retain(|item| !ids.contains(&item.id.into()));
+        self.fluids.retain(|fluid| !ids.contains(&fluid.id.into()));
+        self.modules.retain(|module| !ids.contains(&module.id.into()));
+        let machines = std::mem::take(&mut self.machines);
+        self.machines = machines
+            .into_iter()
+            .filter(|machine| !ids.contains(&machine.id.into()))
+            .map(|mut machine| {
+                machine.supported_modules.retain(|&module| !ids.contains(&module.into()));
+                machine
+            })
+            .collect();
+        let beacons = std::mem::take(&mut self.beacons);
+        self.beacons = beacons
+            .into_iter()
+            .filter(|beacon| !ids.contains(&beacon.id.into()))
+            .map(|mut beacon| {
+                beacon.supported_modules.retain(|&module| !ids.contains(&module.into()));
+                beacon
+            })
+            .collect();
+
+        removed.sort_by_key(ID::sort_key);
+        removed
+    }
+
+    /// Removes the object `id`, and cleans up any references to it, as
+    /// described by `remove_all`, so recipes that take or produce it are
+    /// removed as well.
+    ///
+    /// Returns whether the object was in the game data.
+    pub fn remove(&mut self, id: ID) -> bool {
+        let mut ids = HashSet::new();
+        ids.insert(id);
+        self.remove_all(&ids).contains(&id)
+    }
+
+    /// Keeps at most `max` objects of each kind, being the first ones when
+    /// sorted by their ID strings, and removes the others. References to the
+    /// removed objects are cleaned up, as described by `remove_all`, so recipes
+    /// may be removed beyond the limit, and fewer than `max` may remain.
+    ///
+    /// Modules are kept or removed along with their item.
+    pub fn truncate(&mut self, max: usize) -> Vec<ID> {
+        let mut sorted = self.all_ids().collect::<Vec<_>>();
+        sorted.sort_by_key(ID::sort_key);
+
+        let mut ids = HashSet::new();
+        let mut kind = None;
+        let mut kept = 0;
+        for id in sorted {
+            if kind != Some(id.kind()) {
+                kind = Some(id.kind());
+                kept = 0;
+            }
+            if kept < max {
+                kept += 1;
+            } else {
+                ids.insert(id);
+            }
+        }
+        self.remove_all(&ids)
+    }
+
+    /// Removes every object for which `predicate` returns true, such as one
+    /// of the predicates in this module, like `is_untranslated`. References
+    /// to the removed objects are cleaned up, as described by `remove_all`.
+    pub fn remove_hidden<F>(&mut self, predicate: F) -> Vec<ID>
+    where
+        F: Fn(ID, &Metadata) -> bool,
+    {
+        let ids = self
+            .all_ids()
+            .filter(|&id| predicate(id, id.metadata(self)))
+            .collect::<HashSet<_>>();
+        self.remove_all(&ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::{ItemID, MachineID, Str};
+
+    fn names(ids: &[ID]) -> Vec<&'static str> {
+        ids.iter().map(ID::str).collect()
+    }
+
+    #[test]
+    fn remove_hidden_cleans_up_references() {
+        let mut game_data = test_support::game_data();
+        let hidden = ["iron-plate", "assembling-machine-1", "speed-module"];
+        let removed = game_data.remove_hidden(|id, _| hidden.contains(&id.str()));
+
+        assert_eq!(
+            names(&removed),
+            vec![
+                "iron-plate",
+                "speed-module",
+                "electronic-circuit",
+                "iron-gear-wheel",
+                "sulfuric-acid",
+                "assembling-machine-1",
+            ]
+        );
+        assert!(game_data.item_by_str("iron-plate").is_none());
+        assert!(game_data.modules.iter().all(|module| module.id.str() != "speed-module"));
+        assert!(game_data.machine_by_str("assembling-machine-1").is_none());
+
+        // The recipes that took iron plates are gone, the others remain,
+        // without the removed machine and module.
+        let mut recipes = game_data.recipes.iter().map(|recipe| recipe.id.str()).collect::<Vec<_>>();
+        recipes.sort();
+        assert_eq!(recipes, vec!["copper-cable", "uranium-processing"]);
+        let cable = game_data.recipe_by_str("copper-cable").unwrap();
+        assert!(!cable.crafted_in_contains_str("assembling-machine-1"));
+        assert!(cable.crafted_in_contains_str("assembling-machine-2"));
+        assert!(!cable.supports_module_str("speed-module"));
+        assert!(cable.supports_module_str("productivity-module"));
+        for machine in &game_data.machines {
+            assert!(!machine.supports_module_str("speed-module"));
+        }
+        for beacon in &game_data.beacons {
+            assert!(beacon.supported_modules.is_empty());
+        }
+        assert!(game_data.resolve_all().is_empty());
+    }
+
+    #[test]
+    fn remove_hidden_with_a_built_in_predicate() {
+        let mut game_data = test_support::game_data();
+        game_data
+            .modify_metadata::<(), _>(|id, metadata| {
+                let mut metadata = metadata.clone();
+                match id.str() {
+                    "copper-plate" => metadata.localised_name = Str::new(""),
+                    "water" => metadata.localised_name = Str::new("fluid-name.water"),
+                    "sulfur" => metadata.untranslated_name = true,
+                    _ => {}
+                }
+                Ok(metadata)
+            })
+            .unwrap();
+
+        let removed = game_data.remove_hidden(is_untranslated);
+        assert_eq!(
+            names(&removed),
+            vec![
+                "copper-plate",
+                "sulfur",
+                "water",
+                "copper-cable",
+                "sulfuric-acid",
+            ]
+        );
+        assert_eq!(game_data.items.len(), 9);
+        assert_eq!(game_data.fluids.len(), 1);
+        assert_eq!(game_data.recipes.len(), 3);
+    }
+
+    #[test]
+    fn removing_a_machine_strips_its_references() {
+        let mut game_data = test_support::game_data();
+        assert!(game_data.check_all().is_empty());
+        let plant = ID::Machine(MachineID(Str::new("chemical-plant")));
+        assert!(game_data.recipe_by_str("sulfuric-acid").unwrap().crafted_in_contains_str("chemical-plant"));
+
+        assert!(game_data.remove(plant));
+        assert!(game_data.machine_by_str("chemical-plant").is_none());
+        assert_eq!(game_data.machines.len(), 2);
+        assert_eq!(game_data.recipes.len(), 5);
+        let acid = game_data.recipe_by_str("sulfuric-acid").unwrap();
+        assert!(!acid.crafted_in_contains_str("chemical-plant"));
+        assert!(game_data.check_all().is_empty());
+
+        // It's gone, so removing it again does nothing.
+        assert!(!game_data.remove(plant));
+        assert_eq!(game_data.machines.len(), 2);
+    }
+
+    #[test]
+    fn removing_an_item_removes_the_recipes_using_it() {
+        let mut game_data = test_support::game_data();
+        let cable = ID::Item(ItemID(Str::new("copper-cable")));
+
+        assert!(game_data.remove(cable));
+        assert!(game_data.item_by_str("copper-cable").is_none());
+        // Both the recipe producing the cable and the one consuming it.
+        assert!(game_data.recipe_by_str("copper-cable").is_none());
+        assert!(game_data.recipe_by_str("electronic-circuit").is_none());
+        assert_eq!(game_data.recipes.len(), 3);
+        assert_eq!(game_data.items.len(), 10);
+        assert!(game_data.check_all().is_empty());
+    }
+
+    #[test]
+    fn removing_a_module_strips_its_support() {
+        let mut game_data = test_support::game_data();
+        let speed = ID::Item(ItemID(Str::new("speed-module")));
+
+        assert!(game_data.remove(speed));
+        assert_eq!(game_data.modules.len(), 1);
+        assert!(game_data.recipes.iter().all(|recipe| !recipe.supports_module_str("speed-module")));
+        assert!(game_data.machines.iter().all(|machine| !machine.supports_module_str("speed-module")));
+        assert!(game_data.beacons.iter().all(|beacon| beacon.supported_modules.is_empty()));
+        assert!(game_data.check_all().is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{Int, Ratio};
+use num_traits::identities::Zero;
+use num_traits::{pow, Signed};
+
+/// How to write a `Ratio` out as text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatioFormat {
+    /// A (possibly improper) fraction, such as `3/2`, or just `3` for
+    /// whole numbers. This is what serialization uses.
+    Improper,
+    /// A whole part followed by a proper fraction, such as `1 1/2`.
+    Mixed,
+    /// A decimal number, rounded to the given amount of decimal places.
+    Decimal { places: usize },
+}
+
+/// Formats `r` according to `fmt`.
+pub fn format_ratio(r: &Ratio, fmt: RatioFormat) -> String {
+    match fmt {
+        RatioFormat::Improper => r.to_string(),
+        RatioFormat::Mixed => {
+            let whole = r.trunc();
+            let fract = (r - &whole).abs();
+            if fract.is_zero() {
+                whole.to_string()
+            } else if whole.is_zero() {
+                r.to_string()
+            } else {
+                format!("{} {}", whole, fract)
+            }
+        }
+        RatioFormat::Decimal { places } => {
+            let digits = round_to_places(r, places).abs().to_string();
+            let sign = if r.is_negative() && digits.chars().any(|c| c != '0') {
+                "-"
+            } else {
+                ""
+            };
+            if places == 0 {
+                return format!("{}{}", sign, digits);
+            }
+            let digits = format!("{:0>width$}", digits, width = places + 1);
+            let (whole, fract) = digits.split_at(digits.len() - places);
+            format!("{}{}.{}", sign, whole, fract)
+        }
+    }
+}
+
+/// Rounds `r` to `places` decimal places, half away from zero, returning
+/// the result scaled up by `10^places`.
+fn round_to_places(r: &Ratio, places: usize) -> Int {
+    let scale = Ratio::from_integer(pow(Int::from(10), places));
+    (r * scale).round().to_integer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(numerator: i64, denominator: i64, fmt: RatioFormat) -> String {
+        format_ratio(&Ratio::new(Int::from(numerator), Int::from(denominator)), fmt)
+    }
+
+    #[test]
+    fn improper_fractions() {
+        assert_eq!(format(3, 2, RatioFormat::Improper), "3/2");
+        assert_eq!(format(-3, 2, RatioFormat::Improper), "-3/2");
+        assert_eq!(format(6, 4, RatioFormat::Improper), "3/2");
+        assert_eq!(format(4, 2, RatioFormat::Improper), "2");
+        assert_eq!(format(0, 2, RatioFormat::Improper), "0");
+    }
+
+    #[test]
+    fn mixed_fractions() {
+        assert_eq!(format(3, 2, RatioFormat::Mixed), "1 1/2");
+        assert_eq!(format(-3, 2, RatioFormat::Mixed), "-1 1/2");
+        assert_eq!(format(7, 3, RatioFormat::Mixed), "2 1/3");
+        // Whole numbers and proper fractions have no whole, or no fraction.
+        assert_eq!(format(4, 1, RatioFormat::Mixed), "4");
+        assert_eq!(format(-4, 1, RatioFormat::Mixed), "-4");
+        assert_eq!(format(0, 1, RatioFormat::Mixed), "0");
+        assert_eq!(format(1, 2, RatioFormat::Mixed), "1/2");
+        assert_eq!(format(-1, 2, RatioFormat::Mixed), "-1/2");
+    }
+
+    #[test]
+    fn decimals_are_rounded_half_away_from_zero() {
+        let decimal = |places| RatioFormat::Decimal { places };
+        assert_eq!(format(1, 3, decimal(2)), "0.33");
+        assert_eq!(format(2, 3, decimal(2)), "0.67");
+        assert_eq!(format(-2, 3, decimal(2)), "-0.67");
+        assert_eq!(format(1, 200, decimal(2)), "0.01");
+        assert_eq!(format(-1, 200, decimal(2)), "-0.01");
+        assert_eq!(format(1234, 100, decimal(1)), "12.3");
+        assert_eq!(format(3, 1, decimal(3)), "3.000");
+        assert_eq!(format(5, 2, decimal(0)), "3");
+        assert_eq!(format(-5, 2, decimal(0)), "-3");
+    }
+
+    #[test]
+    fn decimals_that_round_to_zero_have_no_sign() {
+        let decimal = |places| RatioFormat::Decimal { places };
+        assert_eq!(format(-1, 1000, decimal(2)), "0.00");
+        assert_eq!(format(-1, 3, decimal(0)), "0");
+    }
+}
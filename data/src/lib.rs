@@ -1,22 +1,50 @@
 extern crate num_bigint;
 extern crate num_rational;
+extern crate num_traits;
 extern crate serde;
 extern crate string_interner;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(test)]
+extern crate serde_json;
 
+pub mod filters;
+mod format;
+mod merge;
+pub mod parsing;
+mod planning;
+mod prototypes;
+mod report;
+mod serde_flattened;
 mod serde_int;
 mod serde_option_ratio;
 mod serde_ratio;
+mod serde_sorted_set;
+#[cfg(test)]
+mod test_support;
+mod validation;
 
-use std::collections::HashSet;
+pub use crate::format::{format_ratio, RatioFormat};
+pub use crate::parsing::{ParseError, ParseWarning};
+pub use crate::prototypes::{
+    parse_prototypes, parse_prototypes_with_reporter, parse_prototypes_with_warnings, TransformOptions,
+    PARSE_PROTOTYPES_STAGE,
+};
+pub use crate::planning::{EffectBonus, EffectMultipliers, ModuleGoal, PlanError, RecipeClosure};
+pub use crate::report::{ConsoleReporter, Reporter, Warning};
+pub use crate::validation::{ValidationError, ValidationWarning};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::num::NonZeroU32;
 use std::ops::Deref;
 use std::u32;
-use std::sync::RwLock;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use num_traits::identities::Zero;
 use serde::{Serialize, Serializer, Deserialize, Deserializer};
 
 pub type Int = num_bigint::BigInt;
@@ -47,6 +75,19 @@ pub enum ID {
     Beacon(BeaconID),
 }
 
+/// The error when converting an `ID` into a specific kind of ID, such as
+/// `ItemID`, whilst it refers to a different kind of object.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct IDKindMismatch(pub ID);
+
+impl fmt::Display for IDKindMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is of a different kind of object", self.0)
+    }
+}
+
+impl std::error::Error for IDKindMismatch {}
+
 // Data definitions
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,13 +111,37 @@ pub struct Recipe {
     pub metadata: Metadata,
     #[serde(with = "serde_ratio")]
     pub time: Ratio,
+    /// In the order in which the game lists them, which is kept as is.
     pub ingredients: Vec<Ingredient>,
+    /// In the order in which the game lists them, which is kept as is.
     pub products: Vec<Product>,
+    #[serde(
+        serialize_with = "serde_sorted_set::serialize",
+        default,
+        skip_serializing_if = "HashSet::is_empty"
+    )]
     pub crafted_in: HashSet<MachineID>,
+    #[serde(
+        serialize_with = "serde_sorted_set::serialize",
+        default,
+        skip_serializing_if = "HashSet::is_empty"
+    )]
     pub supported_modules: HashSet<ItemID>,
+    #[serde(
+        with = "serde_option_ratio",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub emissions_multiplier: Option<Ratio>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_product: Option<ProductResource>,
+    /// The recipe category, such as `smelting`, which determines the
+    /// machines that can craft the recipe.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<Str>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Ingredient {
     #[serde(flatten)]
     pub resource: IngredientResource,
@@ -86,7 +151,12 @@ pub struct Ingredient {
     pub catalyst_amount: Ratio,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Flattened into `Ingredient`, where the variant is stored as a key, such
+/// as `"item": { "id": "iron-plate" }`. Data without exactly one variant key,
+/// or with fields that the variant doesn't have, fails to load with an error
+/// naming this enum. The other keys of the ingredient are ignored, unless
+/// the game data is loaded strictly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum IngredientResource {
     Item {
@@ -109,7 +179,7 @@ pub enum IngredientResource {
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Product {
     #[serde(flatten)]
     pub resource: ProductResource,
@@ -117,7 +187,8 @@ pub struct Product {
     pub amount: ProductAmount,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Flattened into `Product`, in the same way as `IngredientResource`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProductResource {
     Item {
@@ -125,15 +196,18 @@ pub enum ProductResource {
     },
     Fluid {
         id: FluidID,
+        /// May be negative, as some mods use sub-zero temperatures.
         #[serde(with = "serde_ratio")]
         temperature: Ratio,
     },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Flattened into `Product`, in the same way as `IngredientResource`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ProductAmount {
     Fixed {
+        /// Always positive, as a product of nothing is rejected when parsing.
         #[serde(with = "serde_ratio")]
         amount: Ratio,
         #[serde(with = "serde_ratio")]
@@ -162,7 +236,19 @@ pub struct Machine {
     pub energy_drain: Ratio,
     #[serde(with = "serde_int")]
     pub module_slots: Int,
+    #[serde(
+        serialize_with = "serde_sorted_set::serialize",
+        default,
+        skip_serializing_if = "HashSet::is_empty"
+    )]
     pub supported_modules: HashSet<ItemID>,
+    /// The recipe categories that the machine can craft.
+    #[serde(
+        serialize_with = "serde_sorted_set::serialize",
+        default,
+        skip_serializing_if = "HashSet::is_empty"
+    )]
+    pub crafting_categories: HashSet<Str>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -172,9 +258,34 @@ pub struct Beacon {
     pub metadata: Metadata,
     #[serde(with = "serde_ratio")]
     pub distribution_effectivity: Ratio,
+    #[serde(
+        serialize_with = "serde_sorted_set::serialize",
+        default,
+        skip_serializing_if = "HashSet::is_empty"
+    )]
     pub supported_modules: HashSet<ItemID>,
 }
 
+/// Which effects of modules a machine or beacon allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowedEffects {
+    pub energy: bool,
+    pub speed: bool,
+    pub productivity: bool,
+    pub pollution: bool,
+}
+
+impl AllowedEffects {
+    /// Whether every effect that `module` has is allowed. A module without
+    /// any effects is allowed everywhere.
+    pub fn allows(&self, module: &Module) -> bool {
+        (self.energy || module.modifier_energy.is_zero())
+            && (self.speed || module.modifier_speed.is_zero())
+            && (self.productivity || module.modifier_productivity.is_zero())
+            && (self.pollution || module.modifier_pollution.is_zero())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Module {
     pub id: ItemID,
@@ -188,13 +299,26 @@ pub struct Module {
     pub modifier_pollution: Ratio,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Metadata {
     pub localised_name: Str,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub localised_description: Option<Str>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub icon: Option<Icon>,
+    /// Whether the game had no translation for the name, in which case
+    /// `localised_name` holds the localisation key instead.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub untranslated_name: bool,
+    /// The item group that the game lists the object under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<Str>,
+    /// The subgroup, within the group, that the game lists the object under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subgroup: Option<Str>,
+    /// The string by which the game sorts objects within their subgroup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Str>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,23 +326,108 @@ pub struct TileMetadata {
     pub tile_size: (u32, u32),
     pub tile_count: u32,
     pub image_size: (u32, u32),
+    /// The amount of pixels on each side of every tile, separating them.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub padding: u32,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameData {
+    /// The version of the game that the data was extracted from, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub factorio_version: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tile_metadata: Option<TileMetadata>,
+    #[serde(serialize_with = "serde_sorted_set::serialize")]
     pub items: HashSet<Item>,
+    #[serde(serialize_with = "serde_sorted_set::serialize")]
     pub fluids: HashSet<Fluid>,
+    #[serde(serialize_with = "serde_sorted_set::serialize")]
     pub recipes: HashSet<Recipe>,
+    #[serde(serialize_with = "serde_sorted_set::serialize")]
     pub machines: HashSet<Machine>,
+    #[serde(serialize_with = "serde_sorted_set::serialize")]
     pub beacons: HashSet<Beacon>,
+    #[serde(serialize_with = "serde_sorted_set::serialize")]
     pub modules: HashSet<Module>,
 }
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Hash, Debug, Serialize, Deserialize)]
 pub struct Icon(NonZeroU32);
 
+#[derive(Debug, Clone, Default)]
+pub struct CollectionIconCoverage {
+    pub with_icon: usize,
+    /// The objects without an icon, sorted by their ID string.
+    pub missing: Vec<ID>,
+}
+
+/// The items in a subgroup, as listed by `GameData::items_by_group`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemSubgroup {
+    pub group: Option<Str>,
+    pub subgroup: Option<Str>,
+    /// The items, sorted by their order string, and then by their ID string.
+    pub items: Vec<ItemID>,
+}
+
+/// The amount of objects in game data, as counted by `GameData::statistics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+    pub items: usize,
+    pub fluids: usize,
+    pub recipes: usize,
+    pub machines: usize,
+    pub beacons: usize,
+    pub modules: usize,
+    /// The amount of items, fluids, recipes, machines, and beacons of which
+    /// the name has a translation.
+    pub translated: usize,
+}
+
+/// The objects of game data, sorted by their ID strings, as returned by
+/// `GameData::into_sorted_vecs`.
+#[derive(Debug, Clone)]
+pub struct SortedGameData {
+    pub factorio_version: Option<String>,
+    pub tile_metadata: Option<TileMetadata>,
+    pub items: Vec<Item>,
+    pub fluids: Vec<Fluid>,
+    pub recipes: Vec<Recipe>,
+    pub machines: Vec<Machine>,
+    pub beacons: Vec<Beacon>,
+    pub modules: Vec<Module>,
+}
+
+/// Like `SortedGameData`, but borrowing the objects, as returned by
+/// `GameData::sorted_views`.
+#[derive(Debug, Clone)]
+pub struct SortedGameDataView<'a> {
+    pub items: Vec<&'a Item>,
+    pub fluids: Vec<&'a Fluid>,
+    pub recipes: Vec<&'a Recipe>,
+    pub machines: Vec<&'a Machine>,
+    pub beacons: Vec<&'a Beacon>,
+    pub modules: Vec<&'a Module>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IconCoverage {
+    pub items: CollectionIconCoverage,
+    pub fluids: CollectionIconCoverage,
+    pub recipes: CollectionIconCoverage,
+    pub machines: CollectionIconCoverage,
+    pub beacons: CollectionIconCoverage,
+}
+
 pub trait GameObject {
     type Target;
     fn try_resolve<'s, 'd>(&'s self, game_data: &'d GameData) -> Option<&'d Self::Target>;
@@ -267,9 +476,23 @@ macro_rules! hash_by_id {
 }
 
 macro_rules! implement_game_object {
-    ($id:ty, $t:ty, $collection:ident) => {
+    ($id:ident, $t:ty, $variant:ident, $collection:ident, $by_str:ident) => {
         hash_by_id!($id, $t);
 
+        impl From<$id> for ID {
+            fn from(id: $id) -> ID { ID::$variant(id) }
+        }
+
+        impl TryFrom<ID> for $id {
+            type Error = IDKindMismatch;
+            fn try_from(id: ID) -> Result<$id, IDKindMismatch> {
+                match id {
+                    ID::$variant(id) => Ok(id),
+                    id => Err(IDKindMismatch(id)),
+                }
+            }
+        }
+
         impl $id {
             pub fn str(&self) -> &'static str { self.0.str() }
         }
@@ -290,14 +513,23 @@ macro_rules! implement_game_object {
                 self.try_resolve(game_data).map(|x| &x.metadata)
             }
         }
+
+        impl GameData {
+            /// Looks up an object by its raw ID string, without interning
+            /// it, so querying a string that was never seen before doesn't
+            /// add an entry to the global interner.
+            pub fn $by_str(&self, s: &str) -> Option<&$t> {
+                Str::get_existing(s).and_then(|s| self.$collection.get(&$id(s)))
+            }
+        }
     };
 }
 
-implement_game_object!(ItemID, Item, items);
-implement_game_object!(FluidID, Fluid, fluids);
-implement_game_object!(RecipeID, Recipe, recipes);
-implement_game_object!(MachineID, Machine, machines);
-implement_game_object!(BeaconID, Beacon, beacons);
+implement_game_object!(ItemID, Item, Item, items, item_by_str);
+implement_game_object!(FluidID, Fluid, Fluid, fluids, fluid_by_str);
+implement_game_object!(RecipeID, Recipe, Recipe, recipes, recipe_by_str);
+implement_game_object!(MachineID, Machine, Machine, machines, machine_by_str);
+implement_game_object!(BeaconID, Beacon, Beacon, beacons, beacon_by_str);
 hash_by_id!(ItemID, Module);
 
 macro_rules! forward_to_id_variant {
@@ -319,6 +551,38 @@ impl ID {
     pub fn str(&self) -> &'static str {
         forward_to_id_variant!(self, str)
     }
+
+    /// The name of the kind of object, as used when displaying the ID.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ID::Item(_) => "item",
+            ID::Fluid(_) => "fluid",
+            ID::Recipe(_) => "recipe",
+            ID::Machine(_) => "machine",
+            ID::Beacon(_) => "beacon",
+        }
+    }
+
+    /// A key to sort IDs by, being the kind of object, and then the string.
+    /// Unlike the derived `Ord`, which compares the position of the string
+    /// in the interner, this doesn't depend on the order of interning, so
+    /// it's the same between runs.
+    pub fn sort_key(&self) -> (u8, &'static str) {
+        let rank = match self {
+            ID::Item(_) => 0,
+            ID::Fluid(_) => 1,
+            ID::Recipe(_) => 2,
+            ID::Machine(_) => 3,
+            ID::Beacon(_) => 4,
+        };
+        (rank, self.str())
+    }
+}
+
+impl fmt::Display for ID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.kind(), self.str())
+    }
 }
 
 impl AsRef<Str> for ID {
@@ -337,13 +601,199 @@ impl MetadataObject for ID {
     }
 }
 
+impl Machine {
+    /// Checks whether this machine supports the module with the raw ID
+    /// string `s`, without interning `s`.
+    pub fn supports_module_str(&self, s: &str) -> bool {
+        Str::get_existing(s)
+            .map(|s| self.supported_modules.contains(&ItemID(s)))
+            .unwrap_or(false)
+    }
+}
+
+impl Beacon {
+    /// Checks whether this beacon supports the module with the raw ID
+    /// string `s`, without interning `s`.
+    pub fn supports_module_str(&self, s: &str) -> bool {
+        Str::get_existing(s)
+            .map(|s| self.supported_modules.contains(&ItemID(s)))
+            .unwrap_or(false)
+    }
+}
+
+impl Recipe {
+    /// Checks whether this recipe can be crafted in the machine with the
+    /// raw ID string `s`, without interning `s`.
+    pub fn crafted_in_contains_str(&self, s: &str) -> bool {
+        Str::get_existing(s)
+            .map(|s| self.crafted_in.contains(&MachineID(s)))
+            .unwrap_or(false)
+    }
+
+    /// Checks whether this recipe supports the module with the raw ID
+    /// string `s`, without interning `s`.
+    pub fn supports_module_str(&self, s: &str) -> bool {
+        Str::get_existing(s)
+            .map(|s| self.supported_modules.contains(&ItemID(s)))
+            .unwrap_or(false)
+    }
+
+    /// Gets the product that represents this recipe, which is the designated
+    /// main product if there is one, or otherwise the sole product.
+    ///
+    /// Returns `None` for recipes with multiple products and no main product.
+    pub fn primary_product(&self) -> Option<&Product> {
+        if let Some(main_product) = &self.main_product {
+            let product = self
+                .products
+                .iter()
+                .find(|product| product.resource.is_same_resource(main_product));
+            if product.is_some() {
+                return product;
+            }
+        }
+        if self.products.len() == 1 {
+            self.products.first()
+        } else {
+            None
+        }
+    }
+
+    /// The ingredients that are items, in the order of `ingredients`.
+    pub fn item_ingredients(&self) -> impl Iterator<Item = (ItemID, &Ingredient)> {
+        self.ingredients.iter().filter_map(|ingredient| match ingredient.resource {
+            IngredientResource::Item { id } => Some((id, ingredient)),
+            IngredientResource::Fluid { .. } => None,
+        })
+    }
+
+    /// The ingredients that are fluids, in the order of `ingredients`.
+    pub fn fluid_ingredients(&self) -> impl Iterator<Item = (FluidID, &Ingredient)> {
+        self.ingredients.iter().filter_map(|ingredient| match ingredient.resource {
+            IngredientResource::Fluid { id, .. } => Some((id, ingredient)),
+            IngredientResource::Item { .. } => None,
+        })
+    }
+
+    /// The products that are items, in the order of `products`.
+    pub fn item_products(&self) -> impl Iterator<Item = (ItemID, &Product)> {
+        self.products.iter().filter_map(|product| match product.resource {
+            ProductResource::Item { id } => Some((id, product)),
+            ProductResource::Fluid { .. } => None,
+        })
+    }
+
+    /// The products that are fluids, in the order of `products`.
+    pub fn fluid_products(&self) -> impl Iterator<Item = (FluidID, &Product)> {
+        self.products.iter().filter_map(|product| match product.resource {
+            ProductResource::Fluid { id, .. } => Some((id, product)),
+            ProductResource::Item { .. } => None,
+        })
+    }
+}
+
+impl IngredientResource {
+    /// The ID of the item or fluid.
+    pub fn id(&self) -> ID {
+        match self {
+            IngredientResource::Item { id } => ID::Item(*id),
+            IngredientResource::Fluid { id, .. } => ID::Fluid(*id),
+        }
+    }
+}
+
+impl ProductResource {
+    /// The ID of the item or fluid, regardless of its temperature.
+    pub fn id(&self) -> ID {
+        match self {
+            ProductResource::Item { id } => ID::Item(*id),
+            ProductResource::Fluid { id, .. } => ID::Fluid(*id),
+        }
+    }
+
+    /// Whether both refer to the same item, or to the same fluid at the same temperature.
+    pub fn is_same_resource(&self, other: &ProductResource) -> bool {
+        match (self, other) {
+            (ProductResource::Item { id: a }, ProductResource::Item { id: b }) => a == b,
+            (
+                ProductResource::Fluid { id: a, temperature: ta },
+                ProductResource::Fluid { id: b, temperature: tb },
+            ) => a == b && ta == tb,
+            _ => false,
+        }
+    }
+}
+
+impl IconCoverage {
+    /// The coverage of each collection, paired with the name of the collection.
+    pub fn collections(&self) -> [(&'static str, &CollectionIconCoverage); 5] {
+        [
+            ("items", &self.items),
+            ("fluids", &self.fluids),
+            ("recipes", &self.recipes),
+            ("machines", &self.machines),
+            ("beacons", &self.beacons),
+        ]
+    }
+
+    fn collections_mut(&mut self) -> [(&'static str, &mut CollectionIconCoverage); 5] {
+        [
+            ("items", &mut self.items),
+            ("fluids", &mut self.fluids),
+            ("recipes", &mut self.recipes),
+            ("machines", &mut self.machines),
+            ("beacons", &mut self.beacons),
+        ]
+    }
+
+    /// Whether every object has an icon.
+    pub fn is_complete(&self) -> bool {
+        self.collections()
+            .iter()
+            .all(|(_, collection)| collection.missing.is_empty())
+    }
+}
+
+impl Metadata {
+    /// Whether the name is translated, rather than falling back to its key.
+    pub fn is_translated(&self) -> bool {
+        !self.untranslated_name
+    }
+}
+
+impl Statistics {
+    /// The amount of objects that have metadata, which excludes modules, as
+    /// they're items as well.
+    pub fn object_count(&self) -> usize {
+        self.items + self.fluids + self.recipes + self.machines + self.beacons
+    }
+
+    /// The fraction of objects of which the name has a translation, which is
+    /// 1 if there are no objects at all.
+    pub fn translation_coverage(&self) -> f64 {
+        match self.object_count() {
+            0 => 1.0,
+            count => self.translated as f64 / count as f64,
+        }
+    }
+}
+
 impl Icon {
+    /// The position of the top-left pixel of the icon in the tile set,
+    /// excluding the padding around it.
+    ///
+    /// The amount of columns is derived from the width of the tile set,
+    /// which `transform_icons` makes exactly as wide as its columns of
+    /// tiles, so it matches the amount of columns the icons were packed in.
     pub fn position(&self, tile_metadata: &TileMetadata) -> (u32, u32) {
-        let columns = tile_metadata.image_size.0 / tile_metadata.tile_size.0;
+        let padding = tile_metadata.padding;
+        let stride_x = tile_metadata.tile_size.0 + 2 * padding;
+        let stride_y = tile_metadata.tile_size.1 + 2 * padding;
+        let columns = tile_metadata.image_size.0 / stride_x;
         let idx = self.index() as u32;
         let x = idx % columns;
         let y = idx / columns;
-        (x * tile_metadata.tile_size.0, y * tile_metadata.tile_size.1)
+        (x * stride_x + padding, y * stride_y + padding)
     }
 
     pub fn index(&self) -> usize {
@@ -381,6 +831,297 @@ impl GameData {
         set_metadata!(beacons, Beacon);
         Ok(())
     }
+
+    /// Iterates over the IDs of every object that has metadata, which are
+    /// the items, fluids, recipes, machines, and beacons.
+    pub fn all_ids<'a>(&'a self) -> impl Iterator<Item = ID> + 'a {
+        let items = self.items.iter().map(|x| ID::from(x.id));
+        let fluids = self.fluids.iter().map(|x| ID::from(x.id));
+        let recipes = self.recipes.iter().map(|x| ID::from(x.id));
+        let machines = self.machines.iter().map(|x| ID::from(x.id));
+        let beacons = self.beacons.iter().map(|x| ID::from(x.id));
+        items.chain(fluids).chain(recipes).chain(machines).chain(beacons)
+    }
+
+    pub fn metadata_for(&self, id: ID) -> Option<&Metadata> {
+        id.try_metadata(self)
+    }
+
+    /// Estimates how many bytes of heap memory this game data occupies.
+    ///
+    /// This sums the capacity of every collection, and the length of every
+    /// distinct string that is referred to. It is only an estimate, as it
+    /// doesn't account for the overhead of the hash sets, or the digits of
+    /// ratios. Also, strings are stored in the global interner, which is
+    /// shared between all game data, so they're counted for each game data
+    /// that refers to them, while the memory is only used once. See
+    /// `Str::interner_byte_size` for the size of the interner as a whole.
+    pub fn estimated_heap_size(&self) -> usize {
+        use std::mem::size_of;
+        fn set_size<T>(set: &HashSet<T>) -> usize {
+            set.capacity() * size_of::<T>()
+        }
+
+        let mut strs = HashSet::new();
+        for id in self.all_ids() {
+            strs.insert(*id.as_ref());
+            let metadata = id.metadata(self);
+            strs.insert(metadata.localised_name);
+            strs.extend(metadata.localised_description);
+            strs.extend(metadata.group);
+            strs.extend(metadata.subgroup);
+            strs.extend(metadata.order);
+        }
+        strs.extend(self.recipes.iter().filter_map(|recipe| recipe.category));
+        for machine in &self.machines {
+            strs.extend(machine.crafting_categories.iter().cloned());
+        }
+        let str_size: usize = strs.iter().map(|s| s.len()).sum();
+
+        let recipe_contents: usize = self
+            .recipes
+            .iter()
+            .map(|recipe| {
+                recipe.ingredients.capacity() * size_of::<Ingredient>()
+                    + recipe.products.capacity() * size_of::<Product>()
+                    + set_size(&recipe.crafted_in)
+                    + set_size(&recipe.supported_modules)
+            })
+            .sum();
+        let machine_contents: usize = self
+            .machines
+            .iter()
+            .map(|machine| set_size(&machine.supported_modules) + set_size(&machine.crafting_categories))
+            .sum();
+        let beacon_contents: usize = self
+            .beacons
+            .iter()
+            .map(|beacon| set_size(&beacon.supported_modules))
+            .sum();
+
+        set_size(&self.items)
+            + set_size(&self.fluids)
+            + set_size(&self.recipes)
+            + set_size(&self.machines)
+            + set_size(&self.beacons)
+            + set_size(&self.modules)
+            + recipe_contents
+            + machine_contents
+            + beacon_contents
+            + str_size
+    }
+
+    /// Lists every distinct string that the game data refers to, sorted.
+    ///
+    /// A `Str` is only meaningful within the process that interned it. A
+    /// compact format can instead store the index of each string within
+    /// this table, found using a binary search, and store the table
+    /// alongside. On the receiving end, `rebuild_from_strings` turns the
+    /// table back into `Str`s, at the same indices.
+    pub fn collect_strings(&self) -> Vec<String> {
+        let mut strs = BTreeSet::new();
+        for id in self.all_ids() {
+            strs.insert(id.str());
+            let metadata = id.metadata(self);
+            strs.insert(metadata.localised_name.str());
+            let optional = [metadata.localised_description, metadata.group, metadata.subgroup, metadata.order];
+            strs.extend(optional.iter().flatten().map(Str::str));
+        }
+        for recipe in &self.recipes {
+            let ingredients = recipe.ingredients.iter().map(|ingredient| ingredient.resource.id());
+            let products = recipe.products.iter().map(|product| product.resource.id());
+            let main_product = recipe.main_product.iter().map(ProductResource::id);
+            strs.extend(ingredients.chain(products).chain(main_product).map(|id| id.str()));
+            strs.extend(recipe.crafted_in.iter().map(|id| id.str()));
+            strs.extend(recipe.supported_modules.iter().map(|id| id.str()));
+            strs.extend(recipe.category.as_ref().map(Str::str));
+        }
+        for machine in &self.machines {
+            strs.extend(machine.supported_modules.iter().map(|id| id.str()));
+            strs.extend(machine.crafting_categories.iter().map(Str::str));
+        }
+        for beacon in &self.beacons {
+            strs.extend(beacon.supported_modules.iter().map(|id| id.str()));
+        }
+        strs.extend(self.modules.iter().map(|module| module.id.str()));
+
+        strs.into_iter().map(str::to_owned).collect()
+    }
+
+    /// Interns every string of a table created by `collect_strings`, which
+    /// may have been created by another process. The `Str` at each index
+    /// holds the string at the same index of `strings`.
+    pub fn rebuild_from_strings(strings: &[String]) -> Vec<Str> {
+        strings.iter().map(|s| Str::new(s)).collect()
+    }
+
+    /// Buckets the items by their group and subgroup, the way the game lists
+    /// them. The subgroups are sorted by the names of their group and their
+    /// own name, as the order of the groups themselves isn't exported. Items
+    /// without a group or subgroup are bucketed together after the others,
+    /// as are items without an order string, within their subgroup.
+    pub fn items_by_group(&self) -> Vec<ItemSubgroup> {
+        fn name_key(s: Option<Str>) -> (bool, &'static str) {
+            (s.is_none(), s.map(|s| s.str()).unwrap_or(""))
+        }
+
+        let mut subgroups: HashMap<(Option<Str>, Option<Str>), Vec<&Item>> = HashMap::new();
+        for item in &self.items {
+            let key = (item.metadata.group, item.metadata.subgroup);
+            subgroups.entry(key).or_default().push(item);
+        }
+        let mut subgroups = subgroups
+            .into_iter()
+            .map(|((group, subgroup), mut items)| {
+                items.sort_by_key(|item| (name_key(item.metadata.order), item.id.str()));
+                ItemSubgroup {
+                    group,
+                    subgroup,
+                    items: items.into_iter().map(|item| item.id).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        subgroups.sort_by_key(|subgroup| (name_key(subgroup.group), name_key(subgroup.subgroup)));
+        subgroups
+    }
+
+    /// Converts the collections into vectors, sorted by the ID strings of
+    /// the objects, which is the order in which they're serialized.
+    pub fn into_sorted_vecs(self) -> SortedGameData {
+        SortedGameData {
+            factorio_version: self.factorio_version,
+            tile_metadata: self.tile_metadata,
+            items: serde_sorted_set::into_sorted(self.items),
+            fluids: serde_sorted_set::into_sorted(self.fluids),
+            recipes: serde_sorted_set::into_sorted(self.recipes),
+            machines: serde_sorted_set::into_sorted(self.machines),
+            beacons: serde_sorted_set::into_sorted(self.beacons),
+            modules: serde_sorted_set::into_sorted(self.modules),
+        }
+    }
+
+    /// Like `into_sorted_vecs`, but borrowing the objects.
+    pub fn sorted_views(&self) -> SortedGameDataView<'_> {
+        SortedGameDataView {
+            items: serde_sorted_set::sorted(&self.items),
+            fluids: serde_sorted_set::sorted(&self.fluids),
+            recipes: serde_sorted_set::sorted(&self.recipes),
+            machines: serde_sorted_set::sorted(&self.machines),
+            beacons: serde_sorted_set::sorted(&self.beacons),
+            modules: serde_sorted_set::sorted(&self.modules),
+        }
+    }
+
+    /// Counts the objects in each collection, and how many of them have a
+    /// translated name.
+    pub fn statistics(&self) -> Statistics {
+        Statistics {
+            items: self.items.len(),
+            fluids: self.fluids.len(),
+            recipes: self.recipes.len(),
+            machines: self.machines.len(),
+            beacons: self.beacons.len(),
+            modules: self.modules.len(),
+            translated: self
+                .all_ids()
+                .filter(|&id| self.metadata_for(id).map(Metadata::is_translated).unwrap_or(false))
+                .count(),
+        }
+    }
+
+    /// Counts, per collection, how many objects have an icon assigned,
+    /// and lists the ones that don't.
+    pub fn icon_coverage_report(&self) -> IconCoverage {
+        let mut coverage = IconCoverage::default();
+        for id in self.all_ids() {
+            let collection = match id {
+                ID::Item(_) => &mut coverage.items,
+                ID::Fluid(_) => &mut coverage.fluids,
+                ID::Recipe(_) => &mut coverage.recipes,
+                ID::Machine(_) => &mut coverage.machines,
+                ID::Beacon(_) => &mut coverage.beacons,
+            };
+            if self.metadata_for(id).and_then(|meta| meta.icon).is_some() {
+                collection.with_icon += 1;
+            } else {
+                collection.missing.push(id);
+            }
+        }
+        for (_, collection) in coverage.collections_mut() {
+            collection.missing.sort_by_key(ID::sort_key);
+        }
+        coverage
+    }
+
+    /// The recipes in the recipe category `category`, sorted by their ID
+    /// strings.
+    pub fn recipes_in_category(&self, category: &str) -> Vec<&Recipe> {
+        let category = match Str::get_existing(category) {
+            Some(category) => category,
+            None => return Vec::new(),
+        };
+        let mut recipes = self
+            .recipes
+            .iter()
+            .filter(|recipe| recipe.category == Some(category))
+            .collect::<Vec<_>>();
+        recipes.sort_by_key(|recipe| recipe.id.str());
+        recipes
+    }
+
+    /// The machines that can craft `recipe`, sorted by their ID strings.
+    ///
+    /// These are the machines in `Recipe::crafted_in`. If that's empty, but
+    /// the recipe has a category, the machines are matched by category
+    /// instead, which is how the game determines them. Note that this can
+    /// include machines that don't have enough ingredient slots for the
+    /// recipe, which `crafted_in` excludes.
+    pub fn machines_for(&self, recipe: &Recipe) -> Vec<MachineID> {
+        let mut machines = match recipe.category {
+            Some(category) if recipe.crafted_in.is_empty() => self
+                .machines
+                .iter()
+                .filter(|machine| machine.crafting_categories.contains(&category))
+                .map(|machine| machine.id)
+                .collect(),
+            _ => recipe.crafted_in.iter().cloned().collect::<Vec<_>>(),
+        };
+        machines.sort_by_key(|machine| machine.str());
+        machines
+    }
+
+    /// Looks up objects of any kind by their raw ID string, checking
+    /// items, fluids, recipes, machines, and beacons, in that order.
+    ///
+    /// Each kind of object has its own namespace, so the same string can
+    /// back multiple objects, such as an item and a fluid that share a
+    /// name. All of them are returned, rather than just the first match.
+    ///
+    /// Like the per collection lookups (such as `item_by_str`), this doesn't
+    /// intern the string.
+    pub fn objects_by_str(&self, s: &str) -> Vec<ID> {
+        let s = match Str::get_existing(s) {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        let mut ids = Vec::new();
+        if self.items.contains(&ItemID(s)) {
+            ids.push(ID::Item(ItemID(s)));
+        }
+        if self.fluids.contains(&FluidID(s)) {
+            ids.push(ID::Fluid(FluidID(s)));
+        }
+        if self.recipes.contains(&RecipeID(s)) {
+            ids.push(ID::Recipe(RecipeID(s)));
+        }
+        if self.machines.contains(&MachineID(s)) {
+            ids.push(ID::Machine(MachineID(s)));
+        }
+        if self.beacons.contains(&BeaconID(s)) {
+            ids.push(ID::Beacon(BeaconID(s)));
+        }
+        ids
+    }
 }
 
 // String interning and (de)serializing
@@ -391,6 +1132,20 @@ lazy_static! {
     };
 }
 
+// A panic while the interner is locked poisons the lock, but it's safe to
+// keep using the interner afterwards. The lock is only held within the
+// functions below, which don't call any code outside of the interner. The
+// interner only panics before changing anything, when it runs out of
+// symbols, and strings are only ever added, so every `Str` handed out before
+// still resolves to the same string.
+fn read_interner() -> RwLockReadGuard<'static, Interner> {
+    INTERNER.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write_interner() -> RwLockWriteGuard<'static, Interner> {
+    INTERNER.write().unwrap_or_else(PoisonError::into_inner)
+}
+
 #[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Hash, Debug)]
 struct StrSym(NonZeroU32);
 
@@ -409,12 +1164,25 @@ impl string_interner::Symbol for StrSym {
 
 impl Str {
     pub fn new(s: &str) -> Str {
-        let mut lock = INTERNER.write().unwrap();
+        let mut lock = write_interner();
         Str(lock.get_or_intern(s).0)
     }
 
+    /// Gets the `Str` for `s` if it was interned before. Unlike `new`,
+    /// this never adds an entry to the global interner.
+    pub fn get_existing(s: &str) -> Option<Str> {
+        let lock = read_interner();
+        lock.get(s).map(|sym| Str(sym.0))
+    }
+
+    /// The total length, in bytes, of all strings in the global interner.
+    pub fn interner_byte_size() -> usize {
+        let lock = read_interner();
+        lock.iter_values().map(|s| s.len()).sum()
+    }
+
     pub fn str(&self) -> &'static str {
-        let lock = INTERNER.read().unwrap();
+        let lock = read_interner();
         unsafe {
             let ptr = lock.resolve_unchecked(StrSym(self.0)) as *const str;
             &*ptr
@@ -422,6 +1190,11 @@ impl Str {
     }
 }
 
+/// The amount of distinct strings in the global interner.
+pub fn interner_len() -> usize {
+    read_interner().len()
+}
+
 impl Deref for Str {
     type Target = str;
 
@@ -436,7 +1209,828 @@ impl Serialize for Str {
 
 impl<'de> Deserialize<'de> for Str {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Str, D::Error> {
-        let s = String::deserialize(deserializer)?;
-        Ok(Str::new(&s))
+        struct StrVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for StrVisitor {
+            type Value = Str;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, such as the name of a prototype")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Str, E> {
+                Ok(Str::new(s))
+            }
+        }
+
+        deserializer.deserialize_str(StrVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn tile_metadata(tile_count: u32, columns: u32, rows: u32, padding: u32) -> TileMetadata {
+        let stride = 32 + 2 * padding;
+        TileMetadata {
+            tile_size: (32, 32),
+            tile_count,
+            image_size: (columns * stride, rows * stride),
+            padding,
+        }
+    }
+
+    fn metadata(localised_name: &str, untranslated_name: bool) -> Metadata {
+        Metadata {
+            localised_name: Str::new(localised_name),
+            localised_description: None,
+            icon: None,
+            untranslated_name,
+            group: None,
+            subgroup: None,
+            order: None,
+        }
+    }
+
+    #[test]
+    fn translated_and_untranslated_metadata() {
+        assert!(metadata("Iron plate", false).is_translated());
+        assert!(!metadata("item-name.iron-plate", true).is_translated());
+    }
+
+    #[test]
+    fn translated_name_is_read_from_the_export() {
+        let parts = |parts: &[&str]| parts.join(&parsing::delimiters::SEPARATOR.to_string());
+        let mut lines = vec![
+            parts(&["item-name.iron-plate", "Iron plate"]),
+            parts(&["item-description.iron-plate", "Plates."]),
+            parts(&["item-name.mystery", "Unknown key: \"item-name.mystery\""]),
+            parts(&["item-description.mystery", ""]),
+        ]
+        .into_iter();
+        let translated = parsing::read_metadata(&mut lines, parsing::Untranslated::Drop).unwrap();
+        assert!(translated.is_translated());
+        assert_eq!(translated.localised_name.str(), "Iron plate");
+
+        let untranslated = parsing::read_metadata(&mut lines, parsing::Untranslated::Drop).unwrap();
+        assert!(!untranslated.is_translated());
+        assert_eq!(untranslated.localised_name.str(), "item-name.mystery");
+    }
+
+    #[test]
+    fn untranslated_name_defaults_to_translated() {
+        let translated: Metadata = serde_json::from_str(r#"{ "localised_name": "Iron plate" }"#).unwrap();
+        assert!(translated.is_translated());
+        let untranslated = metadata("item-name.mystery", true);
+        let json = serde_json::to_value(&untranslated).unwrap();
+        assert_eq!(json["untranslated_name"], serde_json::Value::Bool(true));
+    }
+
+    /// Serializes and deserializes `recipe`, checking that every field,
+    /// rather than only the ID, survives.
+    fn round_trip_recipe(recipe: &Recipe) -> serde_json::Value {
+        let json = serde_json::to_value(recipe).unwrap();
+        let read: Recipe = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(read.id, recipe.id);
+        assert_eq!(read.metadata, recipe.metadata);
+        assert_eq!(read.time, recipe.time);
+        assert_eq!(read.ingredients, recipe.ingredients);
+        assert_eq!(read.products, recipe.products);
+        assert_eq!(read.crafted_in, recipe.crafted_in);
+        assert_eq!(read.supported_modules, recipe.supported_modules);
+        assert_eq!(read.emissions_multiplier, recipe.emissions_multiplier);
+        assert_eq!(read.main_product, recipe.main_product);
+        assert_eq!(read.category, recipe.category);
+        json
+    }
+
+    #[test]
+    fn empty_recipe_sets_are_omitted() {
+        let game_data = test_support::game_data();
+        let mut uranium = game_data.recipe_by_str("uranium-processing").unwrap().clone();
+        assert!(uranium.crafted_in.is_empty());
+        uranium.supported_modules.clear();
+        let json = round_trip_recipe(&uranium);
+        assert!(json.get("crafted_in").is_none(), "{}", json);
+        assert!(json.get("supported_modules").is_none(), "{}", json);
+
+        let gear = game_data.recipe_by_str("iron-gear-wheel").unwrap();
+        let json = round_trip_recipe(gear);
+        assert!(json["crafted_in"].is_array(), "{}", json);
+        assert!(json["supported_modules"].is_array(), "{}", json);
+    }
+
+    /// IDs of every kind, with strings interned in the given order.
+    fn ids_interned_in_order(names: &[&str]) -> Vec<ID> {
+        let strs = names.iter().map(|name| Str::new(name)).collect::<Vec<_>>();
+        let mut ids = Vec::new();
+        for &s in &strs {
+            ids.push(ID::Beacon(BeaconID(s)));
+            ids.push(ID::Item(ItemID(s)));
+            ids.push(ID::Recipe(RecipeID(s)));
+        }
+        ids
+    }
+
+    #[test]
+    fn sort_key_is_independent_of_interning_order() {
+        // Unique to this test, so that no other test interns them first.
+        let forwards = ids_interned_in_order(&["sort-key-a-1", "sort-key-a-2", "sort-key-a-3"]);
+        let backwards = ids_interned_in_order(&["sort-key-b-3", "sort-key-b-2", "sort-key-b-1"]);
+
+        let sorted_strings = |mut ids: Vec<ID>| {
+            ids.reverse();
+            ids.sort_by_key(ID::sort_key);
+            ids.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        let expected = |prefix: &str| {
+            let mut expected = Vec::new();
+            for kind in &["item", "recipe", "beacon"] {
+                for index in 1..=3 {
+                    expected.push(format!("{}:sort-key-{}-{}", kind, prefix, index));
+                }
+            }
+            expected
+        };
+        assert_eq!(sorted_strings(forwards), expected("a"));
+        assert_eq!(sorted_strings(backwards.clone()), expected("b"));
+
+        // Whereas the derived order follows the interner.
+        let mut backwards = backwards;
+        backwards.sort();
+        assert_eq!(backwards[0].to_string(), "item:sort-key-b-3");
+    }
+
+    #[test]
+    fn id_is_displayed_with_its_kind() {
+        let id = ID::Recipe(RecipeID(Str::new("iron-plate")));
+        assert_eq!(id.to_string(), "recipe:iron-plate");
+        assert_eq!(ID::Fluid(FluidID(Str::new("water"))).to_string(), "fluid:water");
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Names {
+        first: Str,
+        second: Str,
+        empty: Str,
+        control: Str,
+    }
+
+    #[test]
+    fn str_round_trips_through_json() {
+        let json = r#"{"first":"str-round-trip","second":"str-round-trip","empty":"","control":"\u0001a\u001fb\n"}"#;
+        let names: Names = serde_json::from_str(json).unwrap();
+
+        assert_eq!(names.first.str(), "str-round-trip");
+        assert_eq!(names.first, names.second);
+        assert_eq!(names.empty.str(), "");
+        assert_eq!(names.control.str(), "\u{1}a\u{1f}b\n");
+        for s in &["str-round-trip", "", "\u{1}a\u{1f}b\n"] {
+            assert!(Str::get_existing(s).is_some());
+        }
+        assert_eq!(serde_json::to_string(&names).unwrap(), json);
+    }
+
+    #[test]
+    fn str_rejects_non_strings() {
+        let error = serde_json::from_str::<Str>("42").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid type: integer `42`, expected a string, such as the name of a prototype at line 1 column 2"
+        );
+    }
+
+    #[test]
+    fn icon_position_skips_the_padding() {
+        let tile_metadata = tile_metadata(5, 3, 2, 2);
+        let positions = (0..5)
+            .map(|index| Icon::new(index).position(&tile_metadata))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(2, 2), (38, 2), (74, 2), (2, 38), (38, 38)]);
+    }
+
+    fn names<I: Into<ID>>(ids: impl Iterator<Item = I>) -> Vec<&'static str> {
+        ids.map(|id| id.into().str()).collect()
+    }
+
+    #[test]
+    fn ids_convert_to_and_from_their_kind() {
+        let s = Str::new("id-conversion");
+        let ids: [ID; 5] = [
+            ItemID(s).into(),
+            FluidID(s).into(),
+            RecipeID(s).into(),
+            MachineID(s).into(),
+            BeaconID(s).into(),
+        ];
+        assert_eq!(
+            ids,
+            [
+                ID::Item(ItemID(s)),
+                ID::Fluid(FluidID(s)),
+                ID::Recipe(RecipeID(s)),
+                ID::Machine(MachineID(s)),
+                ID::Beacon(BeaconID(s)),
+            ]
+        );
+
+        assert_eq!(ItemID::try_from(ids[0]), Ok(ItemID(s)));
+        assert_eq!(FluidID::try_from(ids[1]), Ok(FluidID(s)));
+        assert_eq!(RecipeID::try_from(ids[2]), Ok(RecipeID(s)));
+        assert_eq!(MachineID::try_from(ids[3]), Ok(MachineID(s)));
+        assert_eq!(BeaconID::try_from(ids[4]), Ok(BeaconID(s)));
+    }
+
+    #[test]
+    fn id_of_another_kind_doesnt_convert() {
+        let water = ID::Fluid(FluidID(Str::new("water")));
+        let error = ItemID::try_from(water).unwrap_err();
+        assert_eq!(error, IDKindMismatch(water));
+        assert_eq!(error.to_string(), "fluid:water is of a different kind of object");
+        let beacon = ID::Beacon(BeaconID(Str::new("beacon")));
+        assert_eq!(MachineID::try_from(beacon), Err(IDKindMismatch(beacon)));
+    }
+
+    #[test]
+    fn objects_by_str_returns_every_object_sharing_the_name() {
+        let mut game_data = test_support::game_data();
+        assert_eq!(
+            game_data.objects_by_str("sulfuric-acid"),
+            vec![
+                ID::Fluid(FluidID(Str::new("sulfuric-acid"))),
+                ID::Recipe(RecipeID(Str::new("sulfuric-acid"))),
+            ]
+        );
+
+        game_data.items.insert(Item {
+            id: ItemID(Str::new("sulfuric-acid")),
+            metadata: metadata("Sulfuric acid barrel", false),
+        });
+        assert_eq!(
+            game_data.objects_by_str("sulfuric-acid"),
+            vec![
+                ID::Item(ItemID(Str::new("sulfuric-acid"))),
+                ID::Fluid(FluidID(Str::new("sulfuric-acid"))),
+                ID::Recipe(RecipeID(Str::new("sulfuric-acid"))),
+            ]
+        );
+        assert_eq!(
+            game_data.objects_by_str("chemical-plant"),
+            vec![ID::Machine(MachineID(Str::new("chemical-plant")))]
+        );
+        assert!(game_data.objects_by_str("no-such-object-anywhere").is_empty());
+    }
+
+    #[test]
+    fn ingredients_are_split_by_kind_in_order() {
+        let game_data = test_support::game_data();
+        let acid = game_data.recipe_by_str("sulfuric-acid").unwrap();
+        assert_eq!(names(acid.item_ingredients().map(|(id, _)| id)), vec!["sulfur", "iron-plate"]);
+        let amounts = acid
+            .item_ingredients()
+            .map(|(_, ingredient)| ingredient.amount.to_integer())
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec![Int::from(5), Int::from(1)]);
+        let fluids = acid.fluid_ingredients().collect::<Vec<_>>();
+        assert_eq!(fluids.len(), 1);
+        assert_eq!(fluids[0].0.str(), "water");
+        assert_eq!(fluids[0].1.amount.to_integer(), Int::from(100));
+
+        let gear = game_data.recipe_by_str("iron-gear-wheel").unwrap();
+        assert_eq!(names(gear.item_ingredients().map(|(id, _)| id)), vec!["iron-plate"]);
+        assert_eq!(gear.fluid_ingredients().count(), 0);
+    }
+
+    #[test]
+    fn products_are_split_by_kind_in_order() {
+        let game_data = test_support::game_data();
+        let uranium = game_data.recipe_by_str("uranium-processing").unwrap();
+        assert_eq!(
+            names(uranium.item_products().map(|(id, _)| id)),
+            vec!["uranium-235", "uranium-238"]
+        );
+        assert_eq!(uranium.fluid_products().count(), 0);
+
+        // A byproduct between the fluids is skipped, without changing their
+        // order.
+        let mut acid = game_data.recipe_by_str("sulfuric-acid").unwrap().clone();
+        let byproduct = Product {
+            resource: ProductResource::Item {
+                id: ItemID(Str::new("sulfur")),
+            },
+            amount: acid.products[0].amount.clone(),
+        };
+        let steam = Product {
+            resource: ProductResource::Fluid {
+                id: FluidID(Str::new("steam")),
+                temperature: Ratio::from_integer(Int::from(165)),
+            },
+            amount: acid.products[0].amount.clone(),
+        };
+        acid.products.push(byproduct.clone());
+        acid.products.push(steam.clone());
+        assert_eq!(
+            acid.item_products().collect::<Vec<_>>(),
+            vec![(ItemID(Str::new("sulfur")), &byproduct)]
+        );
+        assert_eq!(
+            names(acid.fluid_products().map(|(id, _)| id)),
+            vec!["sulfuric-acid", "steam"]
+        );
+        assert_eq!(acid.fluid_products().nth(1).unwrap().1, &steam);
+    }
+
+    #[test]
+    fn recipes_are_listed_by_category() {
+        let game_data = test_support::game_data();
+        let recipes = |category: &str| {
+            game_data
+                .recipes_in_category(category)
+                .iter()
+                .map(|recipe| recipe.id.str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(recipes("crafting"), vec!["copper-cable", "electronic-circuit", "iron-gear-wheel"]);
+        assert_eq!(recipes("chemistry"), vec!["sulfuric-acid"]);
+        assert_eq!(recipes("centrifuging"), vec!["uranium-processing"]);
+        assert!(recipes("category-that-no-recipe-has").is_empty());
+    }
+
+    #[test]
+    fn machines_are_resolved_by_category_without_crafted_in() {
+        let game_data = test_support::game_data();
+        let machines = |recipe: &Recipe| names(game_data.machines_for(recipe).into_iter());
+
+        let acid = game_data.recipe_by_str("sulfuric-acid").unwrap();
+        assert_eq!(machines(acid), vec!["chemical-plant"]);
+
+        // Without crafted_in, every machine with the category can craft it.
+        let mut gear = game_data.recipe_by_str("iron-gear-wheel").unwrap().clone();
+        gear.crafted_in.clear();
+        assert_eq!(machines(&gear), vec!["assembling-machine-1", "assembling-machine-2"]);
+        gear.category = Some(Str::new("advanced-crafting"));
+        assert_eq!(machines(&gear), vec!["assembling-machine-2"]);
+        gear.category = None;
+        assert!(machines(&gear).is_empty());
+
+        // No machine has the centrifuging category.
+        let uranium = game_data.recipe_by_str("uranium-processing").unwrap();
+        assert!(machines(uranium).is_empty());
+    }
+
+    fn update_item<F: FnOnce(&mut Item)>(game_data: &mut GameData, name: &str, update: F) {
+        let mut item = game_data.item_by_str(name).unwrap().clone();
+        update(&mut item);
+        game_data.items.replace(item);
+    }
+
+    fn item_names(subgroup: &ItemSubgroup) -> Vec<&'static str> {
+        subgroup.items.iter().map(|item| item.str()).collect()
+    }
+
+    #[test]
+    fn items_are_grouped_and_sorted_by_order() {
+        let subgroups = test_support::game_data().items_by_group();
+        let keys = subgroups
+            .iter()
+            .map(|subgroup| (subgroup.group.unwrap().str(), subgroup.subgroup.unwrap().str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                ("intermediate-products", "intermediate-product"),
+                ("intermediate-products", "module"),
+                ("intermediate-products", "raw-material"),
+                ("intermediate-products", "raw-resource"),
+            ]
+        );
+        // The uranium isotopes share their order string, and are sorted by
+        // their names instead.
+        assert_eq!(
+            item_names(&subgroups[0]),
+            vec!["copper-cable", "iron-gear-wheel", "electronic-circuit", "uranium-235", "uranium-238"]
+        );
+        assert_eq!(item_names(&subgroups[1]), vec!["speed-module", "productivity-module"]);
+        assert_eq!(item_names(&subgroups[2]), vec!["iron-plate", "copper-plate", "sulfur"]);
+        assert_eq!(item_names(&subgroups[3]), vec!["uranium-ore"]);
+    }
+
+    #[test]
+    fn items_without_a_group_or_order_come_last() {
+        let mut game_data = test_support::game_data();
+        update_item(&mut game_data, "iron-plate", |item| item.metadata.order = None);
+        update_item(&mut game_data, "uranium-ore", |item| {
+            item.metadata.group = None;
+            item.metadata.subgroup = None;
+        });
+
+        let subgroups = game_data.items_by_group();
+        assert_eq!(subgroups.len(), 4);
+        assert_eq!(item_names(&subgroups[2]), vec!["copper-plate", "sulfur", "iron-plate"]);
+        let last = &subgroups[3];
+        assert_eq!((last.group, last.subgroup), (None, None));
+        assert_eq!(item_names(last), vec!["uranium-ore"]);
+    }
+
+    /// The fixture, with two more items whose names are interned in the
+    /// reverse of their alphabetical order.
+    fn game_data_with_reverse_interned_items() -> GameData {
+        let mut game_data = test_support::game_data();
+        for name in &["sorted-views-z", "sorted-views-a"] {
+            let mut item = game_data.item_by_str("iron-plate").unwrap().clone();
+            item.id = ItemID(Str::new(name));
+            game_data.items.insert(item);
+        }
+        assert!(Str::new("sorted-views-z") < Str::new("sorted-views-a"));
+        game_data
+    }
+
+    fn assert_sorted(names: Vec<&'static str>, expected_count: usize) {
+        assert_eq!(names.len(), expected_count);
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+
+    #[test]
+    fn sorted_vecs_are_complete_and_sorted_by_name() {
+        let game_data = game_data_with_reverse_interned_items();
+        let counts = [
+            game_data.items.len(),
+            game_data.fluids.len(),
+            game_data.recipes.len(),
+            game_data.machines.len(),
+            game_data.beacons.len(),
+            game_data.modules.len(),
+        ];
+        let sorted = game_data.into_sorted_vecs();
+
+        let items = names(sorted.items.iter().map(|item| item.id));
+        assert_sorted(items.clone(), counts[0]);
+        let a = items.iter().position(|&name| name == "sorted-views-a").unwrap();
+        assert_eq!(items[a + 1], "sorted-views-z");
+        assert_sorted(names(sorted.fluids.iter().map(|fluid| fluid.id)), counts[1]);
+        assert_sorted(names(sorted.recipes.iter().map(|recipe| recipe.id)), counts[2]);
+        assert_sorted(names(sorted.machines.iter().map(|machine| machine.id)), counts[3]);
+        assert_sorted(names(sorted.beacons.iter().map(|beacon| beacon.id)), counts[4]);
+        assert_sorted(sorted.modules.iter().map(|module| module.id.str()).collect(), counts[5]);
+    }
+
+    #[test]
+    fn sorted_views_match_the_sorted_vecs() {
+        let game_data = game_data_with_reverse_interned_items();
+        let views = game_data.sorted_views();
+        let sorted = game_data.clone().into_sorted_vecs();
+
+        assert_eq!(
+            names(views.items.iter().map(|item| item.id)),
+            names(sorted.items.iter().map(|item| item.id))
+        );
+        assert_eq!(
+            names(views.fluids.iter().map(|fluid| fluid.id)),
+            names(sorted.fluids.iter().map(|fluid| fluid.id))
+        );
+        assert_eq!(
+            names(views.recipes.iter().map(|recipe| recipe.id)),
+            names(sorted.recipes.iter().map(|recipe| recipe.id))
+        );
+        assert_eq!(
+            names(views.machines.iter().map(|machine| machine.id)),
+            names(sorted.machines.iter().map(|machine| machine.id))
+        );
+        assert_eq!(
+            names(views.beacons.iter().map(|beacon| beacon.id)),
+            names(sorted.beacons.iter().map(|beacon| beacon.id))
+        );
+        assert_eq!(
+            views.modules.iter().map(|module| module.id.str()).collect::<Vec<_>>(),
+            sorted.modules.iter().map(|module| module.id.str()).collect::<Vec<_>>()
+        );
+    }
+
+    fn module(energy: i64, speed: i64, productivity: i64, pollution: i64) -> Module {
+        Module {
+            id: ItemID(Str::new("allowed-effects-module")),
+            modifier_energy: Ratio::from_integer(Int::from(energy)),
+            modifier_speed: Ratio::from_integer(Int::from(speed)),
+            modifier_productivity: Ratio::from_integer(Int::from(productivity)),
+            modifier_pollution: Ratio::from_integer(Int::from(pollution)),
+        }
+    }
+
+    const NO_EFFECTS: AllowedEffects = AllowedEffects {
+        energy: false,
+        speed: false,
+        productivity: false,
+        pollution: false,
+    };
+
+    #[test]
+    fn allowed_effects_round_trip() {
+        let allowed = AllowedEffects {
+            speed: true,
+            pollution: true,
+            ..NO_EFFECTS
+        };
+        let json = serde_json::to_string(&allowed).unwrap();
+        assert_eq!(json, r#"{"energy":false,"speed":true,"productivity":false,"pollution":true}"#);
+        assert_eq!(serde_json::from_str::<AllowedEffects>(&json).unwrap(), allowed);
+
+        // Still reachable at the path it had before it moved.
+        let previous: parsing::AllowedEffects = allowed;
+        assert_eq!(previous, allowed);
+    }
+
+    #[test]
+    fn allowed_effects_allow_modules_with_only_allowed_effects() {
+        let allowed = AllowedEffects {
+            energy: true,
+            speed: true,
+            ..NO_EFFECTS
+        };
+        assert!(allowed.allows(&module(1, 1, 0, 0)));
+        assert!(allowed.allows(&module(0, -1, 0, 0)));
+        assert!(!allowed.allows(&module(1, 1, 1, 0)));
+        assert!(!allowed.allows(&module(0, 0, 0, -1)));
+
+        // A module without effects is allowed everywhere.
+        assert!(NO_EFFECTS.allows(&module(0, 0, 0, 0)));
+        assert!(!NO_EFFECTS.allows(&module(0, 1, 0, 0)));
+    }
+
+    #[test]
+    fn poisoned_interner_is_still_used() {
+        let interned = Str::new("interned-before-poisoning");
+        let poisoner = std::thread::spawn(|| {
+            let _interner = write_interner();
+            panic!("poisoning the interner on purpose");
+        });
+        assert!(poisoner.join().is_err());
+        assert!(INTERNER.is_poisoned());
+
+        // The other tests share the interner, so this checks that they keep
+        // working as well.
+        let after = Str::new("interned-after-poisoning");
+        assert_eq!(after.str(), "interned-after-poisoning");
+        assert_eq!(Str::get_existing("interned-after-poisoning"), Some(after));
+        assert_eq!(Str::new("interned-before-poisoning"), interned);
+        assert_eq!(interned.str(), "interned-before-poisoning");
+    }
+
+    fn fixed_product(name: &str, numerator: i64, denominator: i64) -> Product {
+        Product {
+            resource: ProductResource::Item {
+                id: ItemID(Str::new(name)),
+            },
+            amount: ProductAmount::Fixed {
+                amount: Ratio::new(Int::from(numerator), Int::from(denominator)),
+                catalyst_amount: Ratio::zero(),
+            },
+        }
+    }
+
+    #[test]
+    fn identical_products_are_equal() {
+        assert_eq!(fixed_product("iron-plate", 1, 2), fixed_product("iron-plate", 1, 2));
+        // Ratios are compared exactly, regardless of how they're written.
+        assert_eq!(fixed_product("iron-plate", 1, 2), fixed_product("iron-plate", 2, 4));
+
+        let acid = test_support::game_data().recipe_by_str("sulfuric-acid").unwrap().clone();
+        let again = test_support::game_data().recipe_by_str("sulfuric-acid").unwrap().clone();
+        assert_eq!(acid.products, again.products);
+        assert_eq!(acid.ingredients, again.ingredients);
+    }
+
+    #[test]
+    fn differing_products_are_not_equal() {
+        let plate = fixed_product("iron-plate", 1, 2);
+        assert_ne!(plate, fixed_product("iron-plate", 1, 3));
+        assert_ne!(plate, fixed_product("copper-plate", 1, 2));
+
+        let mut catalyst = plate.clone();
+        catalyst.amount = ProductAmount::Fixed {
+            amount: Ratio::new(Int::from(1), Int::from(2)),
+            catalyst_amount: Ratio::new(Int::from(1), Int::from(2)),
+        };
+        assert_ne!(plate, catalyst);
+
+        let mut probability = plate.clone();
+        probability.amount = ProductAmount::Probability {
+            amount_min: Ratio::new(Int::from(1), Int::from(2)),
+            amount_max: Ratio::new(Int::from(1), Int::from(2)),
+            probability: Ratio::from_integer(Int::from(1)),
+        };
+        assert_ne!(plate, probability);
+
+        // A fluid of the same name is a different resource.
+        let mut fluid = plate.clone();
+        fluid.resource = ProductResource::Fluid {
+            id: FluidID(Str::new("iron-plate")),
+            temperature: Ratio::zero(),
+        };
+        assert_ne!(plate, fluid);
+    }
+
+    #[test]
+    fn primary_product_is_the_main_or_sole_product() {
+        let game_data = test_support::game_data();
+        let product_name = |recipe: &Recipe| recipe.primary_product().map(|product| product.resource.id().str());
+
+        let gear = game_data.recipe_by_str("iron-gear-wheel").unwrap();
+        assert!(gear.main_product.is_none());
+        assert_eq!(product_name(gear), Some("iron-gear-wheel"));
+
+        // Both uranium isotopes are products, so neither represents the recipe.
+        let mut uranium = game_data.recipe_by_str("uranium-processing").unwrap().clone();
+        assert_eq!(uranium.products.len(), 2);
+        assert_eq!(product_name(&uranium), None);
+
+        uranium.main_product = Some(ProductResource::Item {
+            id: ItemID(Str::new("uranium-238")),
+        });
+        assert_eq!(product_name(&uranium), Some("uranium-238"));
+
+        // A main product that isn't produced is ignored.
+        uranium.main_product = Some(ProductResource::Item {
+            id: ItemID(Str::new("iron-plate")),
+        });
+        assert_eq!(product_name(&uranium), None);
+    }
+
+    #[test]
+    fn icon_coverage_lists_the_objects_without_icons() {
+        let mut game_data = test_support::game_data();
+        game_data
+            .modify_metadata::<(), _>(|id, metadata| {
+                let mut metadata = metadata.clone();
+                let has_icon = match id {
+                    ID::Item(_) | ID::Fluid(_) | ID::Beacon(_) => true,
+                    ID::Recipe(id) => id.str() == "iron-gear-wheel",
+                    ID::Machine(_) => false,
+                };
+                if has_icon {
+                    metadata.icon = Some(Icon::new(0));
+                }
+                Ok(metadata)
+            })
+            .unwrap();
+
+        let coverage = game_data.icon_coverage_report();
+        let counts = coverage
+            .collections()
+            .iter()
+            .map(|(name, collection)| (*name, collection.with_icon, collection.missing.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                ("items", 11, 0),
+                ("fluids", 2, 0),
+                ("recipes", 1, 4),
+                ("machines", 0, 3),
+                ("beacons", 1, 0),
+            ]
+        );
+        assert_eq!(
+            names(coverage.recipes.missing.iter().copied()),
+            vec!["copper-cable", "electronic-circuit", "sulfuric-acid", "uranium-processing"]
+        );
+        assert_eq!(
+            names(coverage.machines.missing.iter().copied()),
+            vec!["assembling-machine-1", "assembling-machine-2", "chemical-plant"]
+        );
+        assert!(!coverage.is_complete());
+
+        game_data
+            .modify_metadata::<(), _>(|_, metadata| {
+                let mut metadata = metadata.clone();
+                metadata.icon = Some(Icon::new(0));
+                Ok(metadata)
+            })
+            .unwrap();
+        assert!(game_data.icon_coverage_report().is_complete());
+    }
+
+    /// Replaces every string in `json` for which `map` returns a new one.
+    fn map_strings(json: &mut serde_json::Value, map: &dyn Fn(&str) -> Option<String>) {
+        match json {
+            serde_json::Value::String(s) => {
+                if let Some(mapped) = map(s) {
+                    *s = mapped;
+                }
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(|value| map_strings(value, map)),
+            serde_json::Value::Object(values) => values.values_mut().for_each(|value| map_strings(value, map)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn game_data_is_rebuilt_from_its_strings() {
+        let game_data = test_support::game_data();
+        let original = serde_json::to_value(&game_data).unwrap();
+        let strings = game_data.collect_strings();
+        assert!(strings.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Stores the index of every string other than the numbers instead,
+        // as a compact format would, so each of them must be in the table.
+        let mut compact = original.clone();
+        map_strings(&mut compact, &|s| {
+            if s.parse::<Ratio>().is_ok() {
+                return None;
+            }
+            match strings.binary_search_by(|string| string.as_str().cmp(s)) {
+                Ok(index) => Some(format!("#{}", index)),
+                Err(_) => panic!("{} isn't in the string table", s),
+            }
+        });
+
+        let strs = GameData::rebuild_from_strings(&strings);
+        map_strings(&mut compact, &|s| {
+            if !s.starts_with('#') {
+                return None;
+            }
+            let index = s[1..].parse::<usize>().unwrap();
+            Some(strs[index].str().to_owned())
+        });
+        let rebuilt: GameData = serde_json::from_value(compact).unwrap();
+        assert_eq!(serde_json::to_value(&rebuilt).unwrap(), original);
+
+        // Including the strings that only a few objects refer to.
+        let machine = rebuilt.machine_by_str("chemical-plant").unwrap();
+        assert_eq!(machine.metadata.order.unwrap().str(), "e[chemical-plant]");
+        assert!(machine.crafting_categories.contains(&Str::new("chemistry")));
+        let recipe = rebuilt.recipe_by_str("iron-gear-wheel").unwrap();
+        assert_eq!(recipe.category.unwrap().str(), "crafting");
+    }
+
+    #[test]
+    fn estimated_heap_size_grows_with_the_data() {
+        let parse = |max_records| {
+            let options = TransformOptions {
+                max_records,
+                ..TransformOptions::default()
+            };
+            parse_prototypes(test_support::prototypes(), options).unwrap()
+        };
+        let sizes = [parse(Some(0)), parse(Some(1)), parse(Some(2)), parse(None)]
+            .iter()
+            .map(GameData::estimated_heap_size)
+            .collect::<Vec<_>>();
+        assert!(sizes.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", sizes);
+    }
+
+    #[test]
+    fn estimated_heap_size_counts_the_group_strings() {
+        let mut game_data = test_support::game_data();
+        let mut item = game_data.item_by_str("iron-plate").unwrap().clone();
+        item.metadata.group = None;
+        item.metadata.subgroup = None;
+        item.metadata.order = None;
+        game_data.items.replace(item.clone());
+        let size = game_data.estimated_heap_size();
+
+        // Unique to this test, so that no other object refers to them.
+        item.metadata.group = Some(Str::new("heap-size-group"));
+        item.metadata.subgroup = Some(Str::new("heap-size-subgroup"));
+        item.metadata.order = Some(Str::new("heap-size-order"));
+        game_data.items.replace(item);
+
+        let added = "heap-size-group".len() + "heap-size-subgroup".len() + "heap-size-order".len();
+        assert_eq!(game_data.estimated_heap_size(), size + added);
+    }
+
+    #[test]
+    fn estimated_heap_size_counts_the_crafting_categories() {
+        let mut game_data = test_support::game_data();
+        let mut recipe = game_data.recipe_by_str("iron-gear-wheel").unwrap().clone();
+        let mut machine = game_data.machine_by_str("chemical-plant").unwrap().clone();
+        recipe.category = None;
+        machine.crafting_categories = HashSet::new();
+        game_data.recipes.replace(recipe.clone());
+        game_data.machines.replace(machine.clone());
+        let size = game_data.estimated_heap_size();
+
+        // Unique to this test, so that no other object refers to them.
+        recipe.category = Some(Str::new("heap-size-category"));
+        machine.crafting_categories.insert(Str::new("heap-size-category"));
+        machine.crafting_categories.insert(Str::new("heap-size-other-category"));
+        let categories = machine.crafting_categories.capacity() * std::mem::size_of::<Str>();
+        game_data.recipes.replace(recipe);
+        game_data.machines.replace(machine);
+
+        let added = "heap-size-category".len() + "heap-size-other-category".len() + categories;
+        assert_eq!(game_data.estimated_heap_size(), size + added);
+    }
+
+    #[test]
+    fn metadata_is_compared_by_content() {
+        assert_eq!(metadata("Iron plate", false), metadata("Iron plate", false));
+        assert_ne!(metadata("Iron plate", false), metadata("Iron plate", true));
+        let mut ordered = metadata("Iron plate", false);
+        ordered.order = Some(Str::new("a[iron-plate]"));
+        assert_ne!(metadata("Iron plate", false), ordered);
     }
 }
@@ -0,0 +1,123 @@
+//! Combining game data that was extracted separately, such as with different
+//! sets of mods enabled.
+
+use crate::{GameData, ID};
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Moves the objects of `from` that aren't in `into` yet into it, adding
+/// the IDs of the others to `dropped`.
+fn merge_set<T, F>(into: &mut HashSet<T>, from: HashSet<T>, id: F, dropped: &mut Vec<ID>)
+where
+    T: Hash + Eq,
+    F: Fn(&T) -> ID,
+{
+    for object in from {
+        if into.contains(&object) {
+            dropped.push(id(&object));
+        } else {
+            into.insert(object);
+        }
+    }
+}
+
+impl GameData {
+    /// Adds the objects of `other` to these. Where both have an object of
+    /// the same kind with the same ID, the one that's already here is kept,
+    /// and the other is dropped. Modules are added or dropped along with
+    /// their item. The version and tile metadata aren't changed.
+    ///
+    /// The icons of the objects that are added still refer to the tile set
+    /// of `other`, so they have to be repacked along with the tile set of
+    /// this game data to be usable.
+    ///
+    /// Returns the objects of `other` that were dropped, sorted by
+    /// `ID::sort_key`.
+    pub fn merge(&mut self, other: GameData) -> Vec<ID> {
+        let mut dropped = Vec::new();
+
+        let added_items = other
+            .items
+            .iter()
+            .filter(|item| !self.items.contains(*item))
+            .map(|item| item.id)
+            .collect::<HashSet<_>>();
+        let modules = other
+            .modules
+            .into_iter()
+            .filter(|module| added_items.contains(&module.id));
+        self.modules.extend(modules);
+
+        merge_set(&mut self.items, other.items, |item| item.id.into(), &mut dropped);
+        merge_set(&mut self.fluids, other.fluids, |fluid| fluid.id.into(), &mut dropped);
+        merge_set(&mut self.recipes, other.recipes, |recipe| recipe.id.into(), &mut dropped);
+        merge_set(&mut self.machines, other.machines, |machine| machine.id.into(), &mut dropped);
+        merge_set(&mut self.beacons, other.beacons, |beacon| beacon.id.into(), &mut dropped);
+
+        dropped.sort_by_key(ID::sort_key);
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::Str;
+
+    /// The fixture, with only the objects named in `kept`, and the recipes
+    /// that only refer to those.
+    fn subset(kept: &[&str]) -> GameData {
+        let mut game_data = test_support::game_data();
+        game_data.remove_hidden(|id, _| !kept.contains(&id.str()));
+        game_data
+    }
+
+    fn names(ids: &[ID]) -> Vec<&'static str> {
+        ids.iter().map(ID::str).collect()
+    }
+
+    #[test]
+    fn merge_adds_new_objects_and_keeps_existing_ones() {
+        let mut game_data = subset(&["iron-plate", "copper-plate", "copper-cable", "assembling-machine-1"]);
+        let mut other = subset(&[
+            "iron-plate",
+            "iron-gear-wheel",
+            "water",
+            "assembling-machine-1",
+            "beacon",
+            "speed-module",
+        ]);
+        other
+            .modify_metadata::<(), _>(|_, metadata| {
+                let mut metadata = metadata.clone();
+                metadata.localised_name = Str::new("From the other data set");
+                Ok(metadata)
+            })
+            .unwrap();
+
+        let dropped = game_data.merge(other);
+        assert_eq!(names(&dropped), vec!["iron-plate", "assembling-machine-1"]);
+
+        let plate = game_data.item_by_str("iron-plate").unwrap();
+        assert_eq!(plate.metadata.localised_name.str(), "Iron plate");
+        let gear = game_data.item_by_str("iron-gear-wheel").unwrap();
+        assert_eq!(gear.metadata.localised_name.str(), "From the other data set");
+        assert!(game_data.recipe_by_str("copper-cable").is_some());
+        assert!(game_data.recipe_by_str("iron-gear-wheel").is_some());
+        assert!(game_data.fluid_by_str("water").is_some());
+        assert!(game_data.beacon_by_str("beacon").is_some());
+        assert_eq!(game_data.modules.len(), 1);
+        assert!(game_data.resolve_all().is_empty());
+    }
+
+    #[test]
+    fn module_is_dropped_along_with_its_item() {
+        let mut game_data = subset(&["speed-module"]);
+        game_data.modules.clear();
+        let other = subset(&["speed-module"]);
+
+        assert_eq!(names(&game_data.merge(other)), vec!["speed-module"]);
+        assert!(game_data.modules.is_empty());
+    }
+}
@@ -0,0 +1,856 @@
+use crate::{FluidID, Int, ItemID, Metadata, ProductResource, Ratio, RecipeID, Str};
+use num_traits::identities::{One, Zero};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+/// Re-exported from the crate root, where it moved to, for users of its
+/// previous path.
+pub use crate::AllowedEffects;
+
+/// An error encountered whilst parsing the exported prototypes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Describes what went wrong.
+    Message(&'static str),
+    /// A value couldn't be parsed, holding what was expected, and the offending input.
+    InvalidValue { expected: &'static str, value: String },
+}
+
+impl From<&'static str> for ParseError {
+    fn from(message: &'static str) -> ParseError {
+        ParseError::Message(message)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Message(message) => f.write_str(message),
+            ParseError::InvalidValue { expected, value } => {
+                write!(f, "expected {}, got {:?}", expected, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Something unexpected in the exported prototypes, which doesn't prevent
+/// them from being parsed, but may indicate a quirk of the export script.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseWarning {
+    /// A recipe lists the same machine multiple times, holding the amount
+    /// of machines that were read, and the amount of distinct machines.
+    DuplicateCraftedIn { recipe: RecipeID, read: usize, stored: usize },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseWarning::DuplicateCraftedIn { recipe, read, stored } => write!(
+                f,
+                "recipe {} lists {} machine(s) to be crafted in, of which only {} are distinct",
+                recipe.str(),
+                read,
+                stored
+            ),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, ParseError>;
+
+pub(crate) type Iter = ::std::vec::IntoIter<String>;
+
+/// The control characters that frame the exported data, shared between the
+/// export script and the parser.
+///
+/// The output starts at `START` and ends at `FINISH`, with every line in
+/// between being enclosed by `RECORD_START` and `RECORD_END`. Lines that
+/// consist of multiple parts have them separated by `SEPARATOR`. Any of these
+/// characters occurring in the data itself are written as `ESCAPE`, followed
+/// by the character offset by `ESCAPE_OFFSET`.
+pub mod delimiters {
+    pub const START: char = '\x01';
+    pub const RECORD_START: char = '\x02';
+    pub const RECORD_END: char = '\x03';
+    pub const FINISH: char = '\x04';
+    pub const ESCAPE: char = '\x10';
+    pub const SEPARATOR: char = '\x1f';
+
+    pub const ESCAPE_OFFSET: u8 = 0x40;
+
+    /// Every delimiter, which are the characters that have to be escaped.
+    pub const ALL: &[char] = &[START, RECORD_START, RECORD_END, FINISH, ESCAPE, SEPARATOR];
+}
+
+/// Reverses the escaping performed by the export script.
+pub fn unescape(s: &str) -> Result<String> {
+    if !s.contains(delimiters::ESCAPE) {
+        return Ok(s.to_owned());
+    }
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != delimiters::ESCAPE {
+            unescaped.push(c);
+            continue;
+        }
+        let escaped = chars
+            .next()
+            .filter(|c| c.is_ascii())
+            .map(|c| (c as u8).wrapping_sub(delimiters::ESCAPE_OFFSET) as char)
+            .filter(|c| delimiters::ALL.contains(c))
+            .ok_or_else(|| ParseError::InvalidValue {
+                expected: "escaped delimiter",
+                value: s.to_owned(),
+            })?;
+        unescaped.push(escaped);
+    }
+    Ok(unescaped)
+}
+
+/// Splits a line that consists of multiple parts, unescaping each of them.
+pub fn split_parts(line: &str) -> Result<Vec<String>> {
+    line.split(delimiters::SEPARATOR).map(unescape).collect()
+}
+
+/// Reads a line without unescaping it, for lines that consist of multiple parts.
+fn read_raw_line(p: &mut Iter) -> Result<String> {
+    p.next().ok_or_else(|| "unexpected end of data".into())
+}
+
+/// Skips `count` lines, without unescaping or interpreting them.
+pub fn skip_lines(p: &mut Iter, count: usize) -> Result<()> {
+    for _ in 0..count {
+        read_raw_line(p)?;
+    }
+    Ok(())
+}
+
+pub fn read_line(p: &mut Iter) -> Result<String> {
+    unescape(&read_raw_line(p)?)
+}
+
+pub fn read_str(p: &mut Iter) -> Result<Str> {
+    read_line(p).map(|x| Str::new(&x))
+}
+
+/// What to use in place of a localised string that the game has no translation for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Untranslated {
+    /// Uses the localisation key instead.
+    Key,
+    /// Leaves the string out.
+    #[default]
+    Drop,
+}
+
+/// Reads the localised name and description. Names are required, so an
+/// untranslated name always falls back to its key, whereas descriptions
+/// follow `untranslated_description`.
+pub fn read_metadata(p: &mut Iter, untranslated_description: Untranslated) -> Result<Metadata> {
+    let (key, value) = read_localised_parts(p)?;
+    let localised_name = localise(&key, &value, Untranslated::Key).unwrap();
+    let localised_description = read_optional_localised_str(p, untranslated_description)?;
+    Ok(Metadata {
+        localised_name,
+        localised_description,
+        icon: None,
+        untranslated_name: is_untranslated(&key, &value),
+        group: None,
+        subgroup: None,
+        order: None,
+    })
+}
+
+/// Reads the group, subgroup, and order of a prototype into its metadata,
+/// which are optional fields of every section.
+pub fn read_ordering(metadata: &mut Metadata, optional: &OptionalFields) -> Result<()> {
+    metadata.group = read_optional_str(optional.get("group"))?;
+    metadata.subgroup = read_optional_str(optional.get("subgroup"))?;
+    metadata.order = read_optional_str(optional.get("order"))?;
+    Ok(())
+}
+
+pub fn read_localised_str(p: &mut Iter) -> Result<Str> {
+    read_localised_str_internal(p, Untranslated::Key).map(|x| x.unwrap())
+}
+pub fn read_optional_localised_str(p: &mut Iter, untranslated: Untranslated) -> Result<Option<Str>> {
+    read_localised_str_internal(p, untranslated)
+}
+
+/// The key of a localised string is escaped, but its value is translated by
+/// the game whilst it's being written, so it can't be. Because of this, the
+/// value is everything after the first separator, which may contain further
+/// separators.
+fn read_localised_str_internal(p: &mut Iter, untranslated: Untranslated) -> Result<Option<Str>> {
+    let (key, value) = read_localised_parts(p)?;
+    Ok(localise(&key, &value, untranslated))
+}
+
+/// Reads the key of a localised string, and the value it was translated to.
+fn read_localised_parts(p: &mut Iter) -> Result<(String, String)> {
+    let s = read_raw_line(p)?;
+    let mut iter = s.splitn(2, delimiters::SEPARATOR);
+    let key = unescape(iter.next().unwrap())?;
+    let value = iter.next().ok_or("no value part in localised string")?;
+    Ok((key, value.to_owned()))
+}
+
+/// Picks the string to use for a localised string with the given key, and
+/// the value that the game translated it to.
+pub fn localise(key: &str, value: &str, untranslated: Untranslated) -> Option<Str> {
+    if !is_untranslated(key, value) {
+        Some(Str::new(value))
+    } else {
+        match untranslated {
+            Untranslated::Key => Some(Str::new(key)),
+            Untranslated::Drop => None,
+        }
+    }
+}
+
+/// When the game has no translation for a key, it translates it to
+/// `Unknown key: "<key>"` instead.
+fn is_untranslated(key: &str, value: &str) -> bool {
+    value
+        .strip_prefix("Unknown key: \"")
+        .and_then(|rest| rest.strip_suffix('"'))
+        == Some(key)
+}
+
+/// The first line of the export, holding the amount of records in each
+/// section, in the order in which the sections follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub machine_count: usize,
+    pub beacon_count: usize,
+    pub recipe_count: usize,
+    pub item_count: usize,
+    pub fluid_count: usize,
+}
+
+impl Header {
+    /// The amount of counts on the line.
+    const COUNT: usize = 5;
+}
+
+pub fn read_header(p: &mut Iter) -> Result<Header> {
+    let line = p.next().ok_or("unexpected end")?;
+    let parts = line.split(delimiters::SEPARATOR).collect::<Vec<_>>();
+    if parts.len() != Header::COUNT {
+        return Err(ParseError::InvalidValue {
+            expected: "5 counts on the first line",
+            value: line.clone(),
+        });
+    }
+    let mut counts = [0usize; Header::COUNT];
+    for (count, part) in counts.iter_mut().zip(parts) {
+        *count = part.parse().map_err(|_| ParseError::InvalidValue {
+            expected: "a count of records",
+            value: part.to_owned(),
+        })?;
+    }
+    let [machine_count, beacon_count, recipe_count, item_count, fluid_count] = counts;
+    Ok(Header {
+        machine_count,
+        beacon_count,
+        recipe_count,
+        item_count,
+        fluid_count,
+    })
+}
+
+/// The version of the record format that is understood by the parser.
+///
+/// This only has to be incremented when the fixed fields of a record change.
+/// New fields should be added as optional fields instead, which older versions
+/// of the parser will skip.
+pub const RECORD_FORMAT_VERSION: usize = 1;
+
+/// Describes the records of a section (machines, beacons, recipes, items, or fluids).
+///
+/// Each section starts with a single line, consisting of the record format
+/// version, followed by the names of the optional fields, as separate parts.
+/// Every record in the section then consists of its fixed fields, followed by
+/// exactly one line per optional field, in the order of the header.
+pub struct SectionHeader {
+    fields: Vec<String>,
+}
+
+pub fn read_section_header(p: &mut Iter) -> Result<SectionHeader> {
+    let parts = split_parts(&read_raw_line(p)?)?;
+    let mut iter = parts.into_iter();
+    let version: usize = iter
+        .next()
+        .unwrap()
+        .parse()
+        .map_err(|_| "cannot read record format version in section header")?;
+    if version != RECORD_FORMAT_VERSION {
+        return Err("unsupported record format version in section header".into());
+    }
+    Ok(SectionHeader {
+        fields: iter.collect(),
+    })
+}
+
+/// The optional fields of a single record. Fields that the parser doesn't
+/// know about are read, but never queried, so they're effectively skipped.
+pub struct OptionalFields {
+    values: HashMap<String, Vec<String>>,
+}
+
+impl OptionalFields {
+    /// Gets the parts of an optional field, or `None` if the exporter didn't write it.
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.values.get(name).map(|value| value.as_slice())
+    }
+}
+
+impl SectionHeader {
+    pub fn read_optional_fields(&self, p: &mut Iter) -> Result<OptionalFields> {
+        let values = self
+            .fields
+            .iter()
+            .map(|field| Ok((field.clone(), split_parts(&read_raw_line(p)?)?)))
+            .collect::<Result<_>>()?;
+        Ok(OptionalFields { values })
+    }
+
+    /// Skips the optional fields of a single record, without reading them.
+    pub fn skip_optional_fields(&self, p: &mut Iter) -> Result<()> {
+        skip_lines(p, self.fields.len())
+    }
+}
+
+/// Collects the results of reading a known amount of entries, allocating for
+/// all of them up front. Collecting into a `Result` directly doesn't
+/// preallocate, as the amount of entries isn't known when an error may occur.
+pub trait CollectResults<T>: Iterator<Item = Result<T>> + Sized {
+    fn read_into_vec(self, count: usize) -> Result<Vec<T>> {
+        let mut vec = Vec::with_capacity(count);
+        for entry in self {
+            vec.push(entry?);
+        }
+        Ok(vec)
+    }
+
+    fn read_into_set(self, count: usize) -> Result<HashSet<T>>
+    where
+        T: Hash + Eq,
+    {
+        let mut set = HashSet::with_capacity(count);
+        for entry in self {
+            set.insert(entry?);
+        }
+        Ok(set)
+    }
+}
+
+impl<T, I: Iterator<Item = Result<T>>> CollectResults<T> for I {}
+
+/// Like `CollectResults`, but for key-value pairs.
+pub trait CollectPairResults<K: Hash + Eq, V>: Iterator<Item = Result<(K, V)>> + Sized {
+    fn read_into_map(self, count: usize) -> Result<HashMap<K, V>> {
+        let mut map = HashMap::with_capacity(count);
+        for entry in self {
+            let (key, value) = entry?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
+
+impl<K: Hash + Eq, V, I: Iterator<Item = Result<(K, V)>>> CollectPairResults<K, V> for I {}
+
+pub fn read_usize(p: &mut Iter) -> Result<usize> {
+    read_line(p)?.parse().map_err(|_| "cannot read usize".into())
+}
+
+pub fn read_int(p: &mut Iter) -> Result<Int> {
+    parse_int(&read_line(p)?)
+}
+
+/// Parses an integer, allowing a single leading `+` or `-`, and single `_`
+/// separators between digits.
+pub fn parse_int(s: &str) -> Result<Int> {
+    let invalid = || ParseError::InvalidValue {
+        expected: "integer",
+        value: s.to_owned(),
+    };
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'+') => (false, &s[1..]),
+        Some(b'-') => (true, &s[1..]),
+        _ => (false, s),
+    };
+    let is_digit_group = |group: &str| !group.is_empty() && group.bytes().all(|c| c.is_ascii_digit());
+    if !unsigned.split('_').all(is_digit_group) {
+        return Err(invalid());
+    }
+    let magnitude = unsigned.replace('_', "").parse::<Int>().map_err(|_| invalid())?;
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+pub fn read_ratio(p: &mut Iter) -> Result<Ratio> {
+    parse_ratio(&read_line(p)?)
+}
+
+/// Checks whether an optional field is absent, or consists of a single empty part.
+fn is_empty_field(parts: Option<&[String]>) -> bool {
+    match parts {
+        None => true,
+        Some(parts) => parts.len() == 1 && parts[0].is_empty(),
+    }
+}
+
+/// Reads an optional string, where an empty line indicates the absence of a value.
+pub fn read_optional_str(parts: Option<&[String]>) -> Result<Option<Str>> {
+    if is_empty_field(parts) {
+        return Ok(None);
+    }
+    match parts.unwrap() {
+        [value] => Ok(Some(Str::new(value))),
+        _ => Err("expected a single part for string".into()),
+    }
+}
+
+/// Reads an optional ratio, where an empty line indicates the absence of a value.
+pub fn read_optional_ratio(parts: Option<&[String]>) -> Result<Option<Ratio>> {
+    if is_empty_field(parts) {
+        return Ok(None);
+    }
+    match parts.unwrap() {
+        [value] => parse_ratio(value).map(Some),
+        _ => Err("expected a single part for ratio".into()),
+    }
+}
+
+/// Reads an optional list of strings, being the amount of strings, followed
+/// by the strings. Unlike an empty field, a count of zero indicates an empty
+/// list.
+pub fn read_optional_str_list(parts: Option<&[String]>) -> Result<Option<Vec<Str>>> {
+    if is_empty_field(parts) {
+        return Ok(None);
+    }
+    let parts = parts.unwrap();
+    let count = parts[0].parse::<usize>().map_err(|_| ParseError::InvalidValue {
+        expected: "list length",
+        value: parts[0].clone(),
+    })?;
+    if parts.len() - 1 != count {
+        return Err("list length doesn't match the amount of entries in the list".into());
+    }
+    Ok(Some(parts[1..].iter().map(|s| Str::new(s)).collect()))
+}
+
+/// Reads an optional list of modules, in the same way as
+/// `read_optional_str_list`.
+pub fn read_optional_module_list(parts: Option<&[String]>) -> Result<Option<HashSet<ItemID>>> {
+    Ok(read_optional_str_list(parts)?.map(|names| names.into_iter().map(ItemID).collect()))
+}
+
+/// Reads an optional product resource, which is either empty, the parts
+/// `item`, `<name>`, or the parts `fluid`, `<name>`, `<temperature>`.
+pub fn read_optional_product_resource(parts: Option<&[String]>) -> Result<Option<ProductResource>> {
+    if is_empty_field(parts) {
+        return Ok(None);
+    }
+    let mut iter = parts.unwrap().iter().map(|part| part.as_str());
+    let kind = iter.next().unwrap();
+    let id = Str::new(iter.next().ok_or("no name part in product resource")?);
+    let resource = match kind {
+        "item" => ProductResource::Item { id: ItemID(id) },
+        "fluid" => ProductResource::Fluid {
+            id: FluidID(id),
+            temperature: parse_ratio(iter.next().ok_or("no temperature part in fluid product resource")?)?,
+        },
+        _ => return Err("unknown product resource kind".into()),
+    };
+    if iter.next().is_some() {
+        return Err("extra part in product resource".into());
+    }
+    Ok(Some(resource))
+}
+
+// TODO: Improve approximating
+pub fn parse_ratio(s: &str) -> Result<Ratio> {
+    if s.len() < 1 {
+        return Err("expected ratio, got empty string".into());
+    }
+    let negative = s.starts_with('-');
+    let s = if negative { &s[1..] } else { s };
+    let period = s.find('.');
+    let whole = if let Some(period) = period {
+        if let Some(_) = s[period + 1..].find('e') {
+            return Err("scientific notation not supported".into());
+        }
+        &s[0..period]
+    } else {
+        s
+    };
+
+    let mut base = Int::zero();
+    for char in whole.chars() {
+        let d = char
+            .to_digit(10)
+            .ok_or("unexpected non-digit in string to ratio")?;
+        base *= 10;
+        base += d;
+    }
+
+    let whole = Ratio::new_raw(base, Int::one());
+    let fraction = if let Some(period) = period {
+        let approx = s[period..]
+            .parse::<f64>()
+            .ok()
+            .ok_or("cannot parse fractional part as f64 for ratio")?;
+
+        if approx <= 0.0 {
+            Ratio::zero()
+        } else {
+            let (mut closest_delta, mut closest_num, mut closest_den) = (approx, 0, 1);
+
+            // PERF: Very inefficient
+            'outer: for den in 1..1001 {
+                for num in 1..den {
+                    let delta = (approx - (num as f64) / (den as f64)).abs();
+                    if delta < closest_delta {
+                        closest_delta = delta;
+                        closest_num = num as i64;
+                        closest_den = den as i64;
+                        if delta <= 0.00000001 {
+                            break 'outer;
+                        }
+                    }
+                }
+            }
+
+            Ratio::new(Int::from(closest_num), Int::from(closest_den))
+        }
+    } else {
+        Ratio::zero()
+    };
+
+    Ok(if negative {
+        -(whole + fraction)
+    } else {
+        whole + fraction
+    })
+}
+
+pub fn read_allowed_effects(p: &mut Iter) -> Result<AllowedEffects> {
+    let line = read_line(p)?;
+    if line.len() != 4 {
+        return Err("expected allowed_effects to be 4 bits".into());
+    }
+    let bytes = line.as_bytes();
+    #[inline(always)]
+    fn parse_bit(c: u8) -> Result<bool> {
+        match c {
+            b'0' => Ok(false),
+            b'1' => Ok(true),
+            _ => Err("expected 0 or 1 as bit value".into()),
+        }
+    }
+
+    let energy = parse_bit(bytes[0])?;
+    let speed = parse_bit(bytes[1])?;
+    let productivity = parse_bit(bytes[2])?;
+    let pollution = parse_bit(bytes[3])?;
+
+    Ok(AllowedEffects {
+        energy,
+        speed,
+        productivity,
+        pollution,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_int_accepts_signs_and_separators() {
+        assert_eq!(parse_int("1_000"), Ok(Int::from(1000)));
+        assert_eq!(parse_int("+3"), Ok(Int::from(3)));
+        assert_eq!(parse_int("-2"), Ok(Int::from(-2)));
+        assert_eq!(parse_int("-1_000_000"), Ok(Int::from(-1_000_000)));
+        assert_eq!(parse_int("0"), Ok(Int::from(0)));
+    }
+
+    #[test]
+    fn parse_int_rejects_malformed_integers() {
+        for input in &["1.5", "+-3", "-+3", "_1", "-_1", "1_", "1__0", "", "+", "-", "_", "1 000", "0x10"] {
+            assert_eq!(
+                parse_int(input),
+                Err(ParseError::InvalidValue {
+                    expected: "integer",
+                    value: (*input).to_owned(),
+                }),
+                "{:?} should be rejected",
+                input
+            );
+        }
+    }
+
+    fn header(line: &str) -> Result<Header> {
+        let line = line.replace('|', &delimiters::SEPARATOR.to_string());
+        read_header(&mut vec![line].into_iter())
+    }
+
+    #[test]
+    fn header_reads_the_counts_in_section_order() {
+        assert_eq!(
+            header("3|1|5|11|2"),
+            Ok(Header {
+                machine_count: 3,
+                beacon_count: 1,
+                recipe_count: 5,
+                item_count: 11,
+                fluid_count: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn header_with_too_few_counts_is_rejected() {
+        assert_eq!(
+            header("3|1|5|11"),
+            Err(ParseError::InvalidValue {
+                expected: "5 counts on the first line",
+                value: "3|1|5|11".replace('|', &delimiters::SEPARATOR.to_string()),
+            })
+        );
+        assert!(read_header(&mut Vec::new().into_iter()).is_err());
+    }
+
+    #[test]
+    fn header_with_a_non_numeric_count_is_rejected() {
+        assert_eq!(
+            header("3|1|many|11|2"),
+            Err(ParseError::InvalidValue {
+                expected: "a count of records",
+                value: "many".to_owned(),
+            })
+        );
+        assert!(header("3|1|-5|11|2").is_err());
+    }
+
+    /// Reads each line as a count, like the parser reads its entries.
+    fn counts(lines: &[&str]) -> Vec<Result<usize>> {
+        let mut p = section(lines);
+        (0..lines.len()).map(|_| read_usize(&mut p)).collect()
+    }
+
+    #[test]
+    fn preallocated_collections_match_collecting_directly() {
+        let lines = ["3", "1", "2", "1"];
+        let collected = counts(&lines).into_iter().collect::<Result<Vec<_>>>();
+        assert_eq!(counts(&lines).into_iter().read_into_vec(lines.len()), collected);
+        assert_eq!(collected, Ok(vec![3, 1, 2, 1]));
+
+        let collected = counts(&lines).into_iter().collect::<Result<HashSet<_>>>();
+        assert_eq!(counts(&lines).into_iter().read_into_set(lines.len()), collected);
+        assert_eq!(collected.unwrap().len(), 3);
+
+        // Later values of duplicate keys replace earlier ones.
+        let pairs = || {
+            counts(&lines)
+                .into_iter()
+                .enumerate()
+                .map(|(index, count)| count.map(|count| (count, index)))
+        };
+        let collected = pairs().collect::<Result<HashMap<_, _>>>();
+        assert_eq!(pairs().read_into_map(lines.len()), collected);
+        assert_eq!(collected.unwrap()[&1], 3);
+    }
+
+    #[test]
+    fn preallocated_collections_stop_at_the_first_error() {
+        let lines = ["3", "one", "2", "-1"];
+        let error = ParseError::Message("cannot read usize");
+        assert_eq!(counts(&lines).into_iter().read_into_vec(lines.len()), Err(error.clone()));
+        assert_eq!(counts(&lines).into_iter().read_into_set(lines.len()), Err(error));
+
+        // Reading stops there, leaving the remaining lines unread.
+        let mut p = section(&lines);
+        let result = (0..lines.len()).map(|_| read_usize(&mut p)).read_into_vec(lines.len());
+        assert!(result.is_err());
+        assert_eq!(p.next().as_deref(), Some("2"));
+    }
+
+    /// Escapes `s` like the export script does.
+    fn escape(s: &str) -> String {
+        let mut escaped = String::with_capacity(s.len());
+        for c in s.chars() {
+            if delimiters::ALL.contains(&c) {
+                escaped.push(delimiters::ESCAPE);
+                escaped.push((c as u8 + delimiters::ESCAPE_OFFSET) as char);
+            } else {
+                escaped.push(c);
+            }
+        }
+        escaped
+    }
+
+    #[test]
+    fn escaped_delimiters_round_trip() {
+        let s: String = ["a", "b", "c", "d", "e", "f", "g"].join(&delimiters::ALL.iter().collect::<String>());
+        let escaped = escape(&s);
+        assert!(!escaped.contains(|c| c != delimiters::ESCAPE && delimiters::ALL.contains(&c)));
+        assert_eq!(read_line(&mut vec![escaped.clone()].into_iter()), Ok(s.clone()));
+
+        // Escaped separators don't split the parts.
+        let line = [escape("a\x1fb"), escape("c")].join(&delimiters::SEPARATOR.to_string());
+        assert_eq!(split_parts(&line), Ok(vec!["a\x1fb".to_owned(), "c".to_owned()]));
+    }
+
+    #[test]
+    fn localised_name_with_a_separator_round_trips() {
+        let key = "item-name.odd\x1fname";
+        let name = "Odd\x1fname";
+        let localised = |value: &str| format!("{}{}{}", escape(key), delimiters::SEPARATOR, value);
+
+        // The translated value is written as is, and may contain separators.
+        let mut p = vec![localised(name), localised("")].into_iter();
+        let metadata = read_metadata(&mut p, Untranslated::Drop).unwrap();
+        assert_eq!(metadata.localised_name.str(), name);
+        assert!(!metadata.untranslated_name);
+
+        // The escaped key comes back unchanged when it's used instead.
+        let untranslated = format!("Unknown key: \"{}\"", key);
+        let mut p = vec![localised(&untranslated), localised(&untranslated)].into_iter();
+        let metadata = read_metadata(&mut p, Untranslated::Key).unwrap();
+        assert_eq!(metadata.localised_name.str(), key);
+        assert_eq!(metadata.localised_description.map(|s| s.str()), Some(key));
+        assert!(metadata.untranslated_name);
+    }
+
+    #[test]
+    fn untranslated_descriptions_follow_the_option() {
+        let read = |name: &str, description: &str, untranslated| {
+            read_metadata(&mut section(&[name, description]), untranslated).unwrap()
+        };
+        let name = "item-name.plate|Plate";
+        let untranslated_name = "item-name.plate|Unknown key: \"item-name.plate\"";
+        let description = "item-description.plate|Flat.";
+        let untranslated_description = "item-description.plate|Unknown key: \"item-description.plate\"";
+
+        for &untranslated in &[Untranslated::Key, Untranslated::Drop] {
+            let metadata = read(name, description, untranslated);
+            assert_eq!(metadata.localised_name.str(), "Plate");
+            assert_eq!(metadata.localised_description.map(|s| s.str()), Some("Flat."));
+            assert!(!metadata.untranslated_name);
+
+            // Names always fall back to their key.
+            let metadata = read(untranslated_name, description, untranslated);
+            assert_eq!(metadata.localised_name.str(), "item-name.plate");
+            assert!(metadata.untranslated_name);
+        }
+
+        let metadata = read(name, untranslated_description, Untranslated::Key);
+        assert_eq!(metadata.localised_description.map(|s| s.str()), Some("item-description.plate"));
+        let metadata = read(name, untranslated_description, Untranslated::Drop);
+        assert_eq!(metadata.localised_description, None);
+        // An untranslated description doesn't make the name untranslated.
+        assert!(!metadata.untranslated_name);
+    }
+
+    #[test]
+    fn unescape_rejects_malformed_escapes() {
+        let escaped = |s: &str| format!("{}{}", s, delimiters::ESCAPE);
+        for malformed in &[
+            escaped("trailing"),
+            // `!` doesn't stand for any of the delimiters.
+            format!("{}!", escaped("not a delimiter")),
+            format!("{}\u{e9}", escaped("not ascii")),
+        ] {
+            assert_eq!(
+                unescape(malformed),
+                Err(ParseError::InvalidValue {
+                    expected: "escaped delimiter",
+                    value: malformed.clone(),
+                })
+            );
+        }
+    }
+
+    /// The lines of a section, with `|` separating the parts of a line.
+    fn section(lines: &[&str]) -> Iter {
+        let separator = delimiters::SEPARATOR.to_string();
+        lines
+            .iter()
+            .map(|line| line.replace('|', &separator))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Reads records consisting of a name, followed by the optional fields,
+    /// returning the name and group of each.
+    fn read_records(p: &mut Iter, count: usize) -> Result<Vec<(String, Option<Vec<String>>)>> {
+        let header = read_section_header(p)?;
+        (0..count)
+            .map(|_| {
+                let name = read_line(p)?;
+                let optional = header.read_optional_fields(p)?;
+                Ok((name, optional.get("group").map(|parts| parts.to_vec())))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn section_without_optional_fields_is_read() {
+        let mut p = section(&["1", "iron-plate", "iron-gear-wheel", "next-section"]);
+        assert_eq!(
+            read_records(&mut p, 2),
+            Ok(vec![("iron-plate".to_owned(), None), ("iron-gear-wheel".to_owned(), None)])
+        );
+        assert_eq!(p.next().as_deref(), Some("next-section"));
+    }
+
+    #[test]
+    fn unknown_optional_fields_are_skipped() {
+        let mut p = section(&[
+            "1|group|future_field",
+            "iron-plate",
+            "intermediate-products",
+            "unknown|parts",
+            "iron-gear-wheel",
+            "",
+            "",
+            "next-section",
+        ]);
+        assert_eq!(
+            read_records(&mut p, 2),
+            Ok(vec![
+                ("iron-plate".to_owned(), Some(vec!["intermediate-products".to_owned()])),
+                ("iron-gear-wheel".to_owned(), Some(vec![String::new()])),
+            ])
+        );
+        assert_eq!(p.next().as_deref(), Some("next-section"));
+
+        // Skipping a record skips its unknown fields along with the known ones.
+        let mut p = section(&["1|group|future_field", "intermediate-products", "unknown", "next-section"]);
+        read_section_header(&mut p).unwrap().skip_optional_fields(&mut p).unwrap();
+        assert_eq!(p.next().as_deref(), Some("next-section"));
+    }
+
+    #[test]
+    fn section_with_another_record_format_version_is_rejected() {
+        let unsupported = (RECORD_FORMAT_VERSION + 1).to_string();
+        for version in &[unsupported.as_str(), "0"] {
+            assert_eq!(
+                read_section_header(&mut section(&[&format!("{}|group", version)])).err(),
+                Some(ParseError::Message("unsupported record format version in section header"))
+            );
+        }
+        assert_eq!(
+            read_section_header(&mut section(&["group"])).err(),
+            Some(ParseError::Message("cannot read record format version in section header"))
+        );
+    }
+}
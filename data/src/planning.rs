@@ -0,0 +1,859 @@
+use crate::{
+    Beacon, GameData, GameObject, Int, ItemID, Machine, MachineID, Module, ProductAmount, Ratio,
+    Recipe, RecipeID, ID,
+};
+use num_traits::identities::{One, Zero};
+use num_traits::{Signed, ToPrimitive};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::ops::Add;
+
+/// No machine in the game has anywhere near this many module slots, so
+/// anything above it is treated as bogus data, rather than allocated for.
+const MAX_MODULE_SLOTS: usize = u8::MAX as usize;
+
+/// The reasons that a planning query can't be answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    /// The object isn't part of the game data.
+    UnknownObject(ID),
+    /// The recipe can't be crafted in the machine.
+    NotCraftableIn { recipe: RecipeID, machine: MachineID },
+    /// The module isn't supported by the recipe, or by the machine.
+    UnsupportedModule { module: ItemID },
+    /// More modules are given than the machine has slots for.
+    TooManyModules { machine: MachineID },
+    /// The recipe has multiple products, but no main product, so it's not
+    /// clear which product the rate applies to.
+    AmbiguousProduct { recipe: RecipeID },
+    /// The machine doesn't produce anything when crafting the recipe.
+    NoThroughput { recipe: RecipeID, machine: MachineID },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::UnknownObject(id) => write!(f, "{} doesn't exist", id),
+            PlanError::NotCraftableIn { recipe, machine } => write!(
+                f,
+                "recipe {} can't be crafted in {}",
+                recipe.str(),
+                machine.str()
+            ),
+            PlanError::UnsupportedModule { module } => {
+                write!(f, "module {} isn't supported", module.str())
+            }
+            PlanError::TooManyModules { machine } => {
+                write!(f, "machine {} doesn't have enough module slots", machine.str())
+            }
+            PlanError::AmbiguousProduct { recipe } => write!(
+                f,
+                "recipe {} has multiple products, but no main product",
+                recipe.str()
+            ),
+            PlanError::NoThroughput { recipe, machine } => write!(
+                f,
+                "machine {} doesn't produce anything crafting {}",
+                machine.str(),
+                recipe.str()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+/// The recipes and resources that are needed to make a set of targets, as
+/// found by `GameData::recipe_closure`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecipeClosure {
+    /// The recipes that make the targets, or their ingredients, sorted by
+    /// their ID strings.
+    pub recipes: Vec<RecipeID>,
+    /// The items and fluids that are produced by those recipes, including
+    /// the targets, sorted by `ID::sort_key`.
+    pub intermediates: Vec<ID>,
+    /// The items and fluids that no recipe produces, where the walk ended,
+    /// sorted by `ID::sort_key`.
+    pub raw: Vec<ID>,
+    /// The items and fluids that are produced by more than one recipe, of
+    /// which only one was picked, sorted by `ID::sort_key`.
+    pub ambiguous: Vec<ID>,
+}
+
+/// What to optimize for when choosing modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleGoal {
+    MaxSpeed,
+    MaxProductivity,
+    MinEnergy,
+}
+
+impl ModuleGoal {
+    /// How much a module contributes towards the goal, where higher is better.
+    fn score(self, module: &Module) -> Ratio {
+        match self {
+            ModuleGoal::MaxSpeed => module.modifier_speed.clone(),
+            ModuleGoal::MaxProductivity => module.modifier_productivity.clone(),
+            ModuleGoal::MinEnergy => -module.modifier_energy.clone(),
+        }
+    }
+}
+
+/// Modules can reduce the speed and energy consumption of a machine,
+/// but never below 20% of its base value.
+fn minimum_multiplier() -> Ratio {
+    Ratio::new(Int::from(1), Int::from(5))
+}
+
+/// The summed modifiers of a set of modules, either inserted into a
+/// machine directly, or distributed to it by beacons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectBonus {
+    pub energy: Ratio,
+    pub speed: Ratio,
+    pub productivity: Ratio,
+    pub pollution: Ratio,
+}
+
+impl EffectBonus {
+    /// A bonus that doesn't affect anything.
+    pub fn none() -> EffectBonus {
+        EffectBonus {
+            energy: Ratio::zero(),
+            speed: Ratio::zero(),
+            productivity: Ratio::zero(),
+            pollution: Ratio::zero(),
+        }
+    }
+
+    /// Sums the modifiers of all `modules`.
+    pub fn from_modules(modules: &[&Module]) -> EffectBonus {
+        modules.iter().fold(EffectBonus::none(), |acc, module| EffectBonus {
+            energy: acc.energy + &module.modifier_energy,
+            speed: acc.speed + &module.modifier_speed,
+            productivity: acc.productivity + &module.modifier_productivity,
+            pollution: acc.pollution + &module.modifier_pollution,
+        })
+    }
+
+    /// Multiplies every modifier by `factor`.
+    pub fn scale(&self, factor: &Ratio) -> EffectBonus {
+        EffectBonus {
+            energy: &self.energy * factor,
+            speed: &self.speed * factor,
+            productivity: &self.productivity * factor,
+            pollution: &self.pollution * factor,
+        }
+    }
+
+    /// `1 + speed`, floored at the minimum multiplier.
+    pub fn speed_multiplier(&self) -> Ratio {
+        ::std::cmp::max(Ratio::one() + &self.speed, minimum_multiplier())
+    }
+
+    /// `1 + energy`, floored at the minimum multiplier.
+    pub fn energy_multiplier(&self) -> Ratio {
+        ::std::cmp::max(Ratio::one() + &self.energy, minimum_multiplier())
+    }
+
+    /// `1 + productivity`, productivity never going below the base output.
+    pub fn productivity_multiplier(&self) -> Ratio {
+        ::std::cmp::max(Ratio::one() + &self.productivity, Ratio::one())
+    }
+
+    /// The speed, energy, and productivity multipliers, each floored like
+    /// their individual methods.
+    pub fn multipliers(&self) -> EffectMultipliers {
+        EffectMultipliers {
+            speed: self.speed_multiplier(),
+            energy: self.energy_multiplier(),
+            productivity: self.productivity_multiplier(),
+        }
+    }
+}
+
+/// The factors that an `EffectBonus` multiplies the crafting speed, energy
+/// consumption, and output of a machine by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EffectMultipliers {
+    pub speed: Ratio,
+    pub energy: Ratio,
+    pub productivity: Ratio,
+}
+
+impl<'a> Add<&'a EffectBonus> for EffectBonus {
+    type Output = EffectBonus;
+
+    fn add(self, other: &'a EffectBonus) -> EffectBonus {
+        EffectBonus {
+            energy: self.energy + &other.energy,
+            speed: self.speed + &other.speed,
+            productivity: self.productivity + &other.productivity,
+            pollution: self.pollution + &other.pollution,
+        }
+    }
+}
+
+impl Beacon {
+    /// The bonus that this beacon gives to every machine in its range,
+    /// when holding `modules`. To model a machine in range of multiple
+    /// beacons, add up the bonus of each of them.
+    pub fn apply(&self, modules: &[&Module]) -> EffectBonus {
+        EffectBonus::from_modules(modules).scale(&self.distribution_effectivity)
+    }
+}
+
+impl Machine {
+    /// The power drawn whilst crafting, in watts.
+    pub fn active_energy(&self) -> Ratio {
+        &self.energy_consumption + &self.energy_drain
+    }
+
+    /// The power drawn whilst idle, in watts.
+    pub fn idle_energy(&self) -> Ratio {
+        self.energy_drain.clone()
+    }
+
+    /// The crafting speed with the speed modifiers of `modules` applied.
+    pub fn effective_speed(&self, modules: &[&Module]) -> Ratio {
+        &self.crafting_speed * EffectBonus::from_modules(modules).speed_multiplier()
+    }
+
+    /// The speed, energy, and productivity multipliers of `machine_modules`
+    /// and `beacon_bonus` combined. The floors apply to their sum, rather than
+    /// to each of them separately, so beacons can make up for the slowdown
+    /// of the modules in the machine.
+    ///
+    /// Multiply `crafting_speed` by the speed multiplier to get the crafting
+    /// speed of the beaconed machine.
+    pub fn effective_speed_with_beacons(
+        &self,
+        machine_modules: &[&Module],
+        beacon_bonus: &EffectBonus,
+    ) -> EffectMultipliers {
+        (EffectBonus::from_modules(machine_modules) + beacon_bonus).multipliers()
+    }
+
+    /// The energy consumption with the energy modifiers of `modules`
+    /// applied. The drain is not affected by modules.
+    pub fn effective_energy_consumption(&self, modules: &[&Module]) -> Ratio {
+        &self.energy_consumption * EffectBonus::from_modules(modules).energy_multiplier()
+    }
+
+    /// The power drawn whilst crafting, in watts, with `modules` applied.
+    pub fn effective_active_energy(&self, modules: &[&Module]) -> Ratio {
+        self.effective_energy_consumption(modules) + &self.energy_drain
+    }
+
+    /// The amount of module slots, for use as a count. Negative amounts are
+    /// treated as zero, and bogus amounts are capped at 255.
+    pub fn module_slot_count(&self) -> usize {
+        if self.module_slots.is_negative() {
+            0
+        } else {
+            self.module_slots
+                .to_usize()
+                .map_or(MAX_MODULE_SLOTS, |slots| slots.min(MAX_MODULE_SLOTS))
+        }
+    }
+
+    /// Recommends which modules to insert to best achieve `goal`.
+    ///
+    /// Every slot is filled with the supported module that scores best for the
+    /// goal, ties being broken by the ID string. Modules that don't improve the
+    /// goal at all are never recommended, so the result is empty if there are none.
+    pub fn best_modules(&self, game_data: &GameData, goal: ModuleGoal) -> Vec<ItemID> {
+        let slots = self.module_slot_count();
+
+        let best = self
+            .supported_modules
+            .iter()
+            .filter_map(|id| game_data.modules.get(id))
+            .map(|module| (goal.score(module), module))
+            .filter(|(score, _)| *score > Ratio::zero())
+            .max_by(|(a_score, a), (b_score, b)| match a_score.cmp(b_score) {
+                Ordering::Equal => b.id.str().cmp(a.id.str()),
+                ordering => ordering,
+            });
+
+        match best {
+            Some((_, module)) => vec![module.id; slots],
+            None => Vec::new(),
+        }
+    }
+}
+
+impl ProductAmount {
+    /// The amount that's produced per craft on average, without any
+    /// productivity bonus.
+    pub fn expected(&self) -> Ratio {
+        match self {
+            ProductAmount::Fixed { amount, .. } => amount.clone(),
+            ProductAmount::Probability {
+                amount_min,
+                amount_max,
+                probability,
+            } => (amount_min + amount_max) / Ratio::from_integer(Int::from(2)) * probability,
+        }
+    }
+}
+
+impl Recipe {
+    /// The amount of the item or fluid `id` that's produced per craft on
+    /// average, without any productivity bonus.
+    pub fn expected_amount(&self, id: ID) -> Ratio {
+        self.products
+            .iter()
+            .filter(|product| product.resource.id() == id)
+            .fold(Ratio::zero(), |acc, product| acc + product.amount.expected())
+    }
+
+    /// The amount of the primary product that `machine` produces per second,
+    /// when crafting this recipe with `modules` inserted into it. Returns
+    /// `None` if there's no primary product.
+    pub fn throughput(&self, machine: &Machine, modules: &[&Module]) -> Option<Ratio> {
+        let product = self.primary_product()?;
+        let bonus = EffectBonus::from_modules(modules);
+        let crafts_per_second = machine.effective_speed(modules) / &self.time;
+        let amount = self.expected_amount(product.resource.id());
+        Some(crafts_per_second * amount * bonus.productivity_multiplier())
+    }
+
+    /// The energy, in joules, that it takes for `machine` to craft this
+    /// recipe once, with `modules` inserted into it.
+    ///
+    /// # Panics
+    /// Will panic if the crafting speed of `machine` is zero.
+    pub fn energy_per_craft(&self, machine: &Machine, modules: &[&Module]) -> Ratio {
+        machine.effective_active_energy(modules) * &self.time / machine.effective_speed(modules)
+    }
+}
+
+impl GameData {
+    /// How many of `machine`, with `modules` inserted into each of them, are
+    /// needed to produce `target_per_second` of the primary product of
+    /// `recipe`. The count is fractional, so round it up for whole machines.
+    pub fn machines_required(
+        &self,
+        recipe: RecipeID,
+        machine: MachineID,
+        modules: &[ItemID],
+        target_per_second: &Ratio,
+    ) -> Result<Ratio, PlanError> {
+        let recipe_data = recipe
+            .try_resolve(self)
+            .ok_or(PlanError::UnknownObject(recipe.into()))?;
+        let machine_data = machine
+            .try_resolve(self)
+            .ok_or(PlanError::UnknownObject(machine.into()))?;
+        if !recipe_data.crafted_in.contains(&machine) {
+            return Err(PlanError::NotCraftableIn { recipe, machine });
+        }
+        if modules.len() > machine_data.module_slot_count() {
+            return Err(PlanError::TooManyModules { machine });
+        }
+        let modules = modules
+            .iter()
+            .map(|&module| {
+                let supported = recipe_data.supported_modules.contains(&module)
+                    && machine_data.supported_modules.contains(&module);
+                match self.modules.get(&module) {
+                    Some(module_data) if supported => Ok(module_data),
+                    Some(_) => Err(PlanError::UnsupportedModule { module }),
+                    None => Err(PlanError::UnknownObject(module.into())),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let throughput = recipe_data
+            .throughput(machine_data, &modules)
+            .ok_or(PlanError::AmbiguousProduct { recipe })?;
+        if !throughput.is_positive() {
+            return Err(PlanError::NoThroughput { recipe, machine });
+        }
+        Ok(target_per_second / throughput)
+    }
+
+    /// The modules that can be inserted into `machine` while it crafts
+    /// `recipe`, being those supported by both, sorted by their ID strings.
+    /// Productivity modules, for example, are usually supported by machines,
+    /// but only by some recipes. If either is unknown, there are none.
+    pub fn modules_for(&self, recipe: RecipeID, machine: MachineID) -> Vec<&Module> {
+        let (recipe_data, machine_data) = match (recipe.try_resolve(self), machine.try_resolve(self)) {
+            (Some(recipe_data), Some(machine_data)) => (recipe_data, machine_data),
+            _ => return Vec::new(),
+        };
+        let mut modules = recipe_data
+            .supported_modules
+            .intersection(&machine_data.supported_modules)
+            .filter_map(|id| self.modules.get(id))
+            .collect::<Vec<_>>();
+        modules.sort_by_key(|module| module.id.str());
+        modules
+    }
+
+    /// Maps every item and fluid that is produced by a recipe, to the
+    /// recipes that produce it. Fluids are indexed regardless of temperature.
+    pub fn producing_recipes(&self) -> HashMap<ID, Vec<RecipeID>> {
+        let mut producers: HashMap<ID, Vec<RecipeID>> = HashMap::new();
+        for recipe in &self.recipes {
+            for product in &recipe.products {
+                let recipes = producers.entry(product.resource.id()).or_default();
+                if !recipes.contains(&recipe.id) {
+                    recipes.push(recipe.id);
+                }
+            }
+        }
+        producers
+    }
+
+    /// The items and fluids that are used as an ingredient, but aren't
+    /// produced by any recipe, which is where a traversal of the
+    /// dependencies ends.
+    ///
+    /// The game doesn't describe mining or pumping as recipes, so resources
+    /// such as ores and water are raw, which is what most planners want.
+    /// If a mod adds recipes that produce them, they're no longer raw.
+    pub fn raw_resources(&self) -> HashSet<ID> {
+        let producers = self.producing_recipes();
+        self.recipes
+            .iter()
+            .flat_map(|recipe| recipe.ingredients.iter())
+            .map(|ingredient| ingredient.resource.id())
+            .filter(|id| !producers.contains_key(id))
+            .collect()
+    }
+
+    /// Walks backwards from the `targets`, through the recipes that produce
+    /// them and their ingredients, until it reaches raw resources.
+    ///
+    /// When a resource is produced by multiple recipes, a single one is
+    /// picked, so that the closure describes one way of making everything.
+    /// Recipes of which the resource is the primary product are preferred,
+    /// and ties are broken by their ID strings. Such resources are listed as
+    /// `ambiguous`. Targets that are recipes are always included, along with
+    /// their ingredients. Machines and beacons aren't resources, and are
+    /// skipped.
+    pub fn recipe_closure(&self, targets: &[ID]) -> RecipeClosure {
+        let producers = self.producing_recipes();
+        let pick_recipe = |id: ID, recipes: &[RecipeID]| {
+            recipes
+                .iter()
+                .min_by_key(|recipe| {
+                    let is_primary = recipe
+                        .try_resolve(self)
+                        .and_then(Recipe::primary_product)
+                        .map(|product| product.resource.id() == id)
+                        .unwrap_or(false);
+                    (!is_primary, recipe.str())
+                })
+                .cloned()
+        };
+
+        let mut recipes = BTreeSet::new();
+        let mut visited = HashSet::new();
+        let mut pending = targets.to_vec();
+        let mut closure = RecipeClosure::default();
+        while let Some(id) = pending.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let recipe = match id {
+                ID::Recipe(recipe) => recipe,
+                ID::Item(_) | ID::Fluid(_) => {
+                    let candidates = producers.get(&id).map(Vec::as_slice).unwrap_or(&[]);
+                    if candidates.len() > 1 {
+                        closure.ambiguous.push(id);
+                    }
+                    match pick_recipe(id, candidates) {
+                        Some(recipe) => {
+                            closure.intermediates.push(id);
+                            recipe
+                        }
+                        None => {
+                            closure.raw.push(id);
+                            continue;
+                        }
+                    }
+                }
+                ID::Machine(_) | ID::Beacon(_) => continue,
+            };
+            if !recipes.insert((recipe.str(), recipe)) {
+                continue;
+            }
+            if let Some(recipe) = recipe.try_resolve(self) {
+                pending.extend(recipe.ingredients.iter().map(|ingredient| ingredient.resource.id()));
+            }
+        }
+
+        closure.recipes = recipes.into_iter().map(|(_, recipe)| recipe).collect();
+        closure.intermediates.sort_by_key(ID::sort_key);
+        closure.raw.sort_by_key(ID::sort_key);
+        closure.ambiguous.sort_by_key(ID::sort_key);
+        closure
+    }
+
+    /// Lists, for every recipe, the machines that can craft it, both sorted by
+    /// their ID strings. Recipes that can only be crafted by hand are listed
+    /// with no machines.
+    pub fn crafting_matrix(&self) -> Vec<(RecipeID, Vec<MachineID>)> {
+        let mut matrix = self
+            .recipes
+            .iter()
+            .map(|recipe| {
+                let mut machines = recipe.crafted_in.iter().cloned().collect::<Vec<_>>();
+                machines.sort_by_key(|machine| machine.str());
+                (recipe.id, machines)
+            })
+            .collect::<Vec<_>>();
+        matrix.sort_by_key(|(recipe, _)| recipe.str());
+        matrix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::{FluidID, IngredientResource, ProductResource, Str};
+
+    fn ratio(numerator: i64, denominator: i64) -> Ratio {
+        Ratio::new(Int::from(numerator), Int::from(denominator))
+    }
+
+    fn recipe(name: &str) -> RecipeID {
+        RecipeID(Str::new(name))
+    }
+
+    fn machine(name: &str) -> MachineID {
+        MachineID(Str::new(name))
+    }
+
+    fn module(name: &str) -> ItemID {
+        ItemID(Str::new(name))
+    }
+
+    #[test]
+    fn machines_required_without_modules() {
+        let game_data = test_support::game_data();
+        // At a speed of 0.75, a gear takes 2/3 of a second, so one machine
+        // makes 1.5 gears per second.
+        let count = game_data
+            .machines_required(recipe("iron-gear-wheel"), machine("assembling-machine-2"), &[], &ratio(6, 1))
+            .unwrap();
+        assert_eq!(count, ratio(4, 1));
+
+        let count = game_data
+            .machines_required(recipe("iron-gear-wheel"), machine("assembling-machine-1"), &[], &ratio(1, 1))
+            .unwrap();
+        assert_eq!(count, ratio(1, 1));
+    }
+
+    #[test]
+    fn machines_required_with_speed_modules() {
+        let game_data = test_support::game_data();
+        let speed = module("speed-module");
+        // Two modules add 40% speed, making 2.1 gears per second.
+        let count = game_data
+            .machines_required(
+                recipe("iron-gear-wheel"),
+                machine("assembling-machine-2"),
+                &[speed, speed],
+                &ratio(6, 1),
+            )
+            .unwrap();
+        assert_eq!(count, ratio(20, 7));
+
+        // One module adds 20% speed, making 1.8 gears per second.
+        let count = game_data
+            .machines_required(recipe("iron-gear-wheel"), machine("assembling-machine-2"), &[speed], &ratio(6, 1))
+            .unwrap();
+        assert_eq!(count, ratio(10, 3));
+    }
+
+    #[test]
+    fn machines_required_with_productivity_modules() {
+        let game_data = test_support::game_data();
+        let productivity = module("productivity-module");
+        // 10% slower, at 1.35 crafts per second, but 8% more per craft.
+        let count = game_data
+            .machines_required(
+                recipe("iron-gear-wheel"),
+                machine("assembling-machine-2"),
+                &[productivity, productivity],
+                &ratio(1458, 1000),
+            )
+            .unwrap();
+        assert_eq!(count, ratio(1, 1));
+    }
+
+    #[test]
+    fn machines_required_errors() {
+        let game_data = test_support::game_data();
+        let one = ratio(1, 1);
+        let speed = module("speed-module");
+
+        assert_eq!(
+            game_data.machines_required(recipe("sulfuric-acid"), machine("assembling-machine-2"), &[], &one),
+            Err(PlanError::NotCraftableIn {
+                recipe: recipe("sulfuric-acid"),
+                machine: machine("assembling-machine-2"),
+            })
+        );
+        assert_eq!(
+            game_data.machines_required(recipe("iron-gear-wheel"), machine("assembling-machine-1"), &[speed], &one),
+            Err(PlanError::TooManyModules {
+                machine: machine("assembling-machine-1"),
+            })
+        );
+        assert_eq!(
+            game_data.machines_required(
+                recipe("sulfuric-acid"),
+                machine("chemical-plant"),
+                &[module("productivity-module")],
+                &one
+            ),
+            Err(PlanError::UnsupportedModule {
+                module: module("productivity-module"),
+            })
+        );
+        assert_eq!(
+            game_data.machines_required(recipe("iron-gear-wheel"), machine("furnace"), &[], &one),
+            Err(PlanError::UnknownObject(machine("furnace").into()))
+        );
+    }
+
+    #[test]
+    fn active_and_idle_energy_of_machines() {
+        let game_data = test_support::game_data();
+        let energy = |name: &str| {
+            let machine = game_data.machine_by_str(name).unwrap();
+            (machine.active_energy(), machine.idle_energy())
+        };
+        assert_eq!(energy("assembling-machine-1"), (ratio(80_000, 1), ratio(2_500, 1)));
+        assert_eq!(energy("assembling-machine-2"), (ratio(155_000, 1), ratio(5_000, 1)));
+        assert_eq!(energy("chemical-plant"), (ratio(217_000, 1), ratio(7_000, 1)));
+    }
+
+    #[test]
+    fn energy_per_craft_with_and_without_modules() {
+        let game_data = test_support::game_data();
+        let gear = game_data.recipe_by_str("iron-gear-wheel").unwrap();
+        let assembler_1 = game_data.machine_by_str("assembling-machine-1").unwrap();
+        let assembler_2 = game_data.machine_by_str("assembling-machine-2").unwrap();
+        let speed = game_data.modules.get(&module("speed-module")).unwrap();
+
+        // Half a second at a speed of 0.5 takes a second at 80kW.
+        assert_eq!(gear.energy_per_craft(assembler_1, &[]), ratio(80_000, 1));
+        // 155kW for 2/3 of a second.
+        assert_eq!(gear.energy_per_craft(assembler_2, &[]), ratio(310_000, 3));
+        // Two speed modules double the consumption, but not the drain, to
+        // 305kW, and shorten the craft to 10/21 of a second.
+        assert_eq!(gear.energy_per_craft(assembler_2, &[speed, speed]), ratio(3_050_000, 21));
+        assert_eq!(assembler_2.effective_active_energy(&[speed, speed]), ratio(305_000, 1));
+    }
+
+    #[test]
+    fn beacon_bonus_is_combined_with_the_machine_modules() {
+        let game_data = test_support::game_data();
+        let assembler_2 = game_data.machine_by_str("assembling-machine-2").unwrap();
+        let beacon = game_data.beacons.iter().next().unwrap();
+        let speed = game_data.modules.get(&module("speed-module")).unwrap();
+        let productivity = game_data.modules.get(&module("productivity-module")).unwrap();
+        assert_eq!(beacon.distribution_effectivity, ratio(1, 2));
+
+        // Two speed modules at half effectivity give 0.2 speed and 0.5 energy.
+        let beacon_bonus = beacon.apply(&[speed, speed]);
+        assert_eq!(
+            beacon_bonus,
+            EffectBonus {
+                energy: ratio(1, 2),
+                speed: ratio(1, 5),
+                productivity: ratio(0, 1),
+                pollution: ratio(0, 1),
+            }
+        );
+        let multipliers = assembler_2.effective_speed_with_beacons(&[], &beacon_bonus);
+        assert_eq!(
+            multipliers,
+            EffectMultipliers {
+                speed: ratio(6, 5),
+                energy: ratio(3, 2),
+                productivity: ratio(1, 1),
+            }
+        );
+        assert_eq!(&assembler_2.crafting_speed * multipliers.speed, ratio(9, 10));
+
+        // The productivity modules' energy and productivity add to the
+        // beacon's, and the beacon makes up for their slowdown.
+        assert_eq!(
+            assembler_2.effective_speed_with_beacons(&[productivity, productivity], &beacon_bonus),
+            EffectMultipliers {
+                speed: ratio(11, 10),
+                energy: ratio(23, 10),
+                productivity: ratio(27, 25),
+            }
+        );
+
+        // The floors apply to the sum.
+        let slowdown = EffectBonus {
+            energy: ratio(-2, 1),
+            speed: ratio(-2, 1),
+            productivity: ratio(-2, 1),
+            pollution: ratio(0, 1),
+        };
+        assert_eq!(
+            assembler_2.effective_speed_with_beacons(&[speed, speed], &slowdown),
+            EffectMultipliers {
+                speed: ratio(1, 5),
+                energy: ratio(1, 5),
+                productivity: ratio(1, 1),
+            }
+        );
+    }
+
+    #[test]
+    fn best_modules_fill_every_slot() {
+        let game_data = test_support::game_data();
+        let assembler_1 = game_data.machine_by_str("assembling-machine-1").unwrap();
+        let assembler_2 = game_data.machine_by_str("assembling-machine-2").unwrap();
+
+        assert_eq!(
+            assembler_2.best_modules(&game_data, ModuleGoal::MaxSpeed),
+            vec![module("speed-module"), module("speed-module")]
+        );
+        assert_eq!(
+            assembler_2.best_modules(&game_data, ModuleGoal::MaxProductivity),
+            vec![module("productivity-module"), module("productivity-module")]
+        );
+        // Both modules increase the energy consumption.
+        assert!(assembler_2.best_modules(&game_data, ModuleGoal::MinEnergy).is_empty());
+        assert!(assembler_1.best_modules(&game_data, ModuleGoal::MaxSpeed).is_empty());
+    }
+
+    #[test]
+    fn module_slot_count_is_clamped() {
+        let game_data = test_support::game_data();
+        let mut machine = game_data.machine_by_str("assembling-machine-2").unwrap().clone();
+        assert_eq!(machine.module_slot_count(), 2);
+
+        machine.module_slots = Int::from(-3);
+        assert_eq!(machine.module_slot_count(), 0);
+
+        machine.module_slots = Int::from(MAX_MODULE_SLOTS + 1);
+        assert_eq!(machine.module_slot_count(), 255);
+        // Even when it doesn't fit in a usize.
+        machine.module_slots = Int::from(u64::MAX) * Int::from(u64::MAX);
+        assert_eq!(machine.module_slot_count(), 255);
+        assert_eq!(machine.best_modules(&game_data, ModuleGoal::MaxSpeed).len(), 255);
+    }
+
+    fn item(name: &str) -> ID {
+        ID::Item(ItemID(Str::new(name)))
+    }
+
+    fn fluid(name: &str) -> ID {
+        ID::Fluid(FluidID(Str::new(name)))
+    }
+
+    fn sorted_raw_resources(game_data: &GameData) -> Vec<ID> {
+        let mut raw = game_data.raw_resources().into_iter().collect::<Vec<_>>();
+        raw.sort_by_key(ID::sort_key);
+        raw
+    }
+
+    #[test]
+    fn raw_resources_are_the_ingredients_without_recipes() {
+        let mut game_data = test_support::game_data();
+        assert_eq!(
+            sorted_raw_resources(&game_data),
+            vec![
+                item("copper-plate"),
+                item("iron-plate"),
+                item("sulfur"),
+                item("uranium-ore"),
+                fluid("water"),
+            ]
+        );
+
+        // Once plates are smelted from ore, the ore is raw instead.
+        let mut smelting = game_data.recipe_by_str("iron-gear-wheel").unwrap().clone();
+        smelting.id = recipe("iron-plate");
+        smelting.ingredients[0].resource = IngredientResource::Item {
+            id: ItemID(Str::new("iron-ore")),
+        };
+        smelting.products[0].resource = ProductResource::Item {
+            id: ItemID(Str::new("iron-plate")),
+        };
+        game_data.recipes.insert(smelting);
+        let raw = sorted_raw_resources(&game_data);
+        assert!(raw.contains(&item("iron-ore")));
+        assert!(!raw.contains(&item("iron-plate")));
+        assert!(raw.contains(&item("copper-plate")));
+    }
+
+    #[test]
+    fn recipe_closure_walks_the_whole_chain() {
+        let game_data = test_support::game_data();
+        // Circuits need cables, which need copper plates, whilst the machine
+        // isn't a resource, and is skipped.
+        let closure = game_data.recipe_closure(&[
+            item("electronic-circuit"),
+            fluid("sulfuric-acid"),
+            machine("assembling-machine-1").into(),
+        ]);
+        assert_eq!(
+            closure.recipes,
+            vec![recipe("copper-cable"), recipe("electronic-circuit"), recipe("sulfuric-acid")]
+        );
+        assert_eq!(
+            closure.intermediates,
+            vec![item("copper-cable"), item("electronic-circuit"), fluid("sulfuric-acid")]
+        );
+        assert_eq!(
+            closure.raw,
+            vec![item("copper-plate"), item("iron-plate"), item("sulfur"), fluid("water")]
+        );
+        assert!(closure.ambiguous.is_empty());
+    }
+
+    #[test]
+    fn recipe_closure_of_a_recipe_includes_its_ingredients() {
+        let game_data = test_support::game_data();
+        let closure = game_data.recipe_closure(&[recipe("iron-gear-wheel").into()]);
+        assert_eq!(closure.recipes, vec![recipe("iron-gear-wheel")]);
+        assert!(closure.intermediates.is_empty());
+        assert_eq!(closure.raw, vec![item("iron-plate")]);
+    }
+
+    fn module_names(modules: Vec<&Module>) -> Vec<&'static str> {
+        modules.iter().map(|module| module.id.str()).collect()
+    }
+
+    #[test]
+    fn modules_for_allowed_by_the_recipe() {
+        let game_data = test_support::game_data();
+        let modules = game_data.modules_for(recipe("electronic-circuit"), machine("assembling-machine-2"));
+        assert_eq!(module_names(modules), vec!["productivity-module", "speed-module"]);
+    }
+
+    #[test]
+    fn modules_for_blocked_by_the_recipe() {
+        let game_data = test_support::game_data();
+        // The machine supports productivity, but the acid isn't an
+        // intermediate product.
+        let plant = game_data.machine_by_str("chemical-plant").unwrap();
+        assert!(plant.supports_module_str("productivity-module"));
+        let modules = game_data.modules_for(recipe("sulfuric-acid"), machine("chemical-plant"));
+        assert_eq!(module_names(modules), vec!["speed-module"]);
+    }
+
+    #[test]
+    fn modules_for_unknown_objects() {
+        let game_data = test_support::game_data();
+        assert!(game_data.modules_for(recipe("missing-recipe"), machine("assembling-machine-2")).is_empty());
+        assert!(game_data.modules_for(recipe("electronic-circuit"), machine("missing-machine")).is_empty());
+    }
+}
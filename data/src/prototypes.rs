@@ -0,0 +1,1007 @@
+use crate::parsing::*;
+use crate::report::{Reporter, Warning};
+use crate::{
+    AllowedEffects, Beacon, BeaconID, Fluid, FluidID, GameData, Ingredient, IngredientResource, Item, ItemID,
+    Machine, MachineID, Module, Product, ProductAmount, ProductResource, Recipe, RecipeID, ID,
+};
+use num_traits::Signed;
+use std::collections::{HashMap, HashSet};
+
+/// Options that affect how the exported prototypes are turned into game data.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransformOptions {
+    /// Prints every object as it's encountered.
+    pub log_entries: bool,
+    /// What to do with descriptions that have no translation.
+    pub untranslated_descriptions: Untranslated,
+    /// Keeps at most this many objects of each kind, being the first ones
+    /// that were exported. The records after those are skipped without
+    /// being parsed, and references to them are cleaned up, as described by
+    /// `GameData::remove_all`.
+    pub max_records: Option<usize>,
+}
+
+/// Parses the lines written by the export script into game data.
+///
+/// The lines are the contents of the records, without the delimiters that
+/// frame them, as stored in `prototypes.json`.
+pub fn parse_prototypes(lines: Vec<String>, options: TransformOptions) -> Result<GameData> {
+    parse_prototypes_with_warnings(lines, options).map(|(game_data, _)| game_data)
+}
+
+/// Like `parse_prototypes`, but also returns the warnings encountered.
+pub fn parse_prototypes_with_warnings(
+    lines: Vec<String>,
+    options: TransformOptions,
+) -> Result<(GameData, Vec<ParseWarning>)> {
+    /// Keeps the warnings, and drops the other entries, which callers that
+    /// want them get from `parse_prototypes_with_reporter` instead.
+    struct WarningCollector(Vec<ParseWarning>);
+
+    impl Reporter for WarningCollector {
+        fn info(&mut self, _message: &str) {}
+
+        fn warn(&mut self, warning: Warning) {
+            if let Warning::Parse(warning) = warning {
+                self.0.push(warning);
+            }
+        }
+    }
+
+    let mut collector = WarningCollector(Vec::new());
+    let game_data = parse_prototypes_with_reporter(lines, options, &mut collector)?;
+    Ok((game_data, collector.0))
+}
+
+/// The name of the stage that `parse_prototypes_with_reporter` reports the
+/// progress of.
+pub const PARSE_PROTOTYPES_STAGE: &str = "parse_prototypes";
+
+/// Like `parse_prototypes`, but reports the entries that are read (if
+/// `log_entries` is set), the warnings, and the progress after each kind of
+/// object, to `reporter`.
+pub fn parse_prototypes_with_reporter(
+    lines: Vec<String>,
+    options: TransformOptions,
+    reporter: &mut dyn Reporter,
+) -> Result<GameData> {
+    let log_entries = options.log_entries;
+    let mut iter = lines.into_iter();
+
+    let Header {
+        machine_count,
+        beacon_count,
+        recipe_count,
+        item_count,
+        fluid_count,
+    } = read_header(&mut iter)?;
+    let total = machine_count + beacon_count + recipe_count + item_count + fluid_count;
+    let limit = |count: usize| options.max_records.map_or(count, |max| count.min(max));
+    // The objects past the limit, which may still be referred to.
+    let mut skipped: HashSet<ID> = HashSet::new();
+
+    let (items, fluids, recipes, machines, beacons, modules) = {
+        let iter = &mut iter;
+
+        // Load primary data (machines, recipes, items, and fluids)
+
+        let header = read_section_header(iter)?;
+        let kept_count = limit(machine_count);
+        let mut machines = (0..kept_count)
+            .map(|_| {
+                let id = MachineID(read_str(iter)?);
+                let mut metadata = read_metadata(iter, options.untranslated_descriptions)?;
+                let crafting_speed = read_ratio(iter)?;
+                let energy_consumption = read_ratio(iter)?;
+                let energy_drain = read_ratio(iter)?;
+                let module_slots = read_int(iter)?;
+
+                let allowed_effects = read_allowed_effects(iter)?;
+                let optional = header.read_optional_fields(iter)?;
+                read_ordering(&mut metadata, &optional)?;
+                let allowed_modules = read_optional_module_list(optional.get("allowed_modules"))?;
+                let crafting_categories = read_optional_str_list(optional.get("crafting_categories"))?
+                    .map(|categories| categories.into_iter().collect())
+                    .unwrap_or_default();
+
+                if log_entries {
+                    reporter.info(&format!(
+                        "machine {} (\"{}\")",
+                        id.0.str(),
+                        metadata.localised_name.str()
+                    ));
+                }
+
+                Ok((
+                    id,
+                    (
+                        Machine {
+                            id: id,
+                            metadata,
+                            crafting_speed,
+                            energy_consumption,
+                            energy_drain,
+                            module_slots,
+                            supported_modules: HashSet::new(),
+                            crafting_categories,
+                        },
+                        allowed_effects,
+                        allowed_modules,
+                    ),
+                ))
+            })
+            .read_into_map(kept_count)?;
+        if machines.len() != kept_count {
+            return Err("duplicate machines in exported data set".into());
+        }
+        for _ in kept_count..machine_count {
+            skipped.insert(skip_machine(iter, &header)?.into());
+        }
+        reporter.progress(PARSE_PROTOTYPES_STAGE, machine_count, total);
+
+        let header = read_section_header(iter)?;
+        let kept_count = limit(beacon_count);
+        let mut beacons = (0..kept_count)
+            .map(|_| {
+                let id = BeaconID(read_str(iter)?);
+                let mut metadata = read_metadata(iter, options.untranslated_descriptions)?;
+                let distribution_effectivity = read_ratio(iter)?;
+                let allowed_effects = read_allowed_effects(iter)?;
+                let optional = header.read_optional_fields(iter)?;
+                read_ordering(&mut metadata, &optional)?;
+
+                if log_entries {
+                    reporter.info(&format!(
+                        "beacon {} (\"{}\")",
+                        id.0.str(),
+                        metadata.localised_name.str()
+                    ));
+                }
+
+                Ok((
+                    id,
+                    (
+                        Beacon {
+                            id,
+                            metadata,
+                            distribution_effectivity,
+                            supported_modules: HashSet::new(),
+                        },
+                        allowed_effects,
+                    ),
+                ))
+            })
+            .read_into_map(kept_count)?;
+        for _ in kept_count..beacon_count {
+            skipped.insert(skip_beacon(iter, &header)?.into());
+        }
+        reporter.progress(PARSE_PROTOTYPES_STAGE, machine_count + beacon_count, total);
+
+        let header = read_section_header(iter)?;
+        let kept_count = limit(recipe_count);
+        let recipes = (0..kept_count).map(|_| {
+            let id = RecipeID(read_str(iter)?);
+            let mut metadata = read_metadata(iter, options.untranslated_descriptions)?;
+            let time = read_ratio(iter)?;
+
+            let ingredient_count = read_usize(iter)?;
+            let ingredients = (0..ingredient_count).map(|_| {
+
+                let kind = read_line(iter)?;
+                let id = read_str(iter)?;
+                let amount = read_ratio(iter)?;
+                let catalyst_amount = read_ratio(iter)?;
+
+                let resource = match kind.as_str() {
+                    "item" => IngredientResource::Item {
+                            id: ItemID(id),
+                        },
+                    "fluid" => {
+                        let flags = read_line(iter)?;
+                        let flags = flags.as_bytes();
+                        if flags.len() != 2 {
+                            return Err("expected optional field flags in ingredient fluid to be 2 bits".into())
+                        }
+                        let minimum_temperature = match flags[0] {
+                            b'0' => None,
+                            b'1' => Some(read_ratio(iter)?),
+                            _ => return Err("expected optional field flags in ingredient fluid to be 0 or 1".into()),
+                        };
+                        let maximum_temperature = match flags[1] {
+                            b'0' => None,
+                            b'1' => Some(read_ratio(iter)?),
+                            _ => return Err("expected optional field flags in ingredient fluid to be 0 or 1".into()),
+                        };
+                        IngredientResource::Fluid {
+                            id: FluidID(id),
+                            minimum_temperature,
+                            maximum_temperature,
+                        }
+                    },
+                    _ => return Err("unknown recipe ingredient kind".into())
+                };
+
+                Ok(Ingredient {
+                    resource,
+                    amount,
+                    catalyst_amount,
+                })
+            })
+                .read_into_vec(ingredient_count)?;
+
+            let product_count = read_usize(iter)?;
+            let products = (0..product_count).map(|_| {
+                let kind = read_line(iter)?;
+                let id = read_str(iter)?;
+                let resource = match kind.as_str() {
+                    "item" => ProductResource::Item{ 
+                        id: ItemID(id),
+                    },
+                    "fluid" => ProductResource::Fluid {
+                        id: FluidID(id),
+                        temperature: read_ratio(iter)?,
+                    },
+                    _ => return Err("unknown recipe product kind".into()),
+                };
+
+                let kind = read_line(iter)?;
+                let amount = match kind.as_str() {
+                    "fixed" => {
+                        let amount = read_ratio(iter)?;
+                        // Unlike temperatures, which mods set below zero, a
+                        // recipe that produces nothing makes no sense.
+                        if !amount.is_positive() {
+                            return Err(ParseError::InvalidValue {
+                                expected: "positive product amount",
+                                value: amount.to_string(),
+                            });
+                        }
+                        let catalyst_amount = read_ratio(iter)?;
+                        ProductAmount::Fixed {
+                            amount,
+                            catalyst_amount,
+                        }
+                    },
+                    "probability" => {
+                        let amount_min = read_ratio(iter)?;
+                        let amount_max = read_ratio(iter)?;
+                        let probability = read_ratio(iter)?;
+                        ProductAmount::Probability {
+                            amount_min,
+                            amount_max,
+                            probability,
+                        }
+                    },
+                    _ => return Err("unknown recipe product amount kind".into()),
+                };
+
+                Ok(Product {
+                    resource,
+                    amount,
+                })
+            }).read_into_vec(product_count)?;
+
+            let crafted_in_count = read_usize(iter)?;
+            let crafted_in = (0..crafted_in_count)
+                .map(|_| Ok(MachineID(read_str(iter)?)))
+                .read_into_set(crafted_in_count)?;
+            if crafted_in.len() != crafted_in_count {
+                reporter.warn(Warning::Parse(ParseWarning::DuplicateCraftedIn {
+                    recipe: id,
+                    read: crafted_in_count,
+                    stored: crafted_in.len(),
+                }));
+            }
+            let optional = header.read_optional_fields(iter)?;
+            read_ordering(&mut metadata, &optional)?;
+            let emissions_multiplier = read_optional_ratio(optional.get("emissions_multiplier"))?;
+            let main_product = read_optional_product_resource(optional.get("main_product"))?;
+            let category = read_optional_str(optional.get("category"))?;
+
+            if log_entries {
+                reporter.info(&format!("recipe {} (\"{}\")",
+                    id.str(),
+                    metadata.localised_name.str()
+                ));
+            }
+
+            Ok(Recipe {
+                id,
+                metadata,
+                time,
+                ingredients,
+                products,
+                crafted_in,
+                supported_modules: HashSet::new(),
+                emissions_multiplier,
+                main_product,
+                category,
+            })
+        }).read_into_set(kept_count)?;
+        if recipes.len() != kept_count {
+            return Err("duplicate recipes in exported data set".into());
+        }
+        for _ in kept_count..recipe_count {
+            skipped.insert(skip_recipe(iter, &header)?.into());
+        }
+        reporter.progress(PARSE_PROTOTYPES_STAGE, machine_count + beacon_count + recipe_count, total);
+
+        // The amount of modules isn't exported, but every module is an
+        // item, so the amount of items is an upper bound.
+        let mut modules = HashSet::with_capacity(item_count);
+        let mut module_count = 0;
+        // The modules supported by each recipe, which are applied to the
+        // recipes once all items have been read.
+        let mut recipe_modules: HashMap<RecipeID, HashSet<ItemID>> = HashMap::new();
+
+        let header = read_section_header(iter)?;
+        let kept_count = limit(item_count);
+        let items = (0..kept_count)
+            .map(|_| {
+                let id = ItemID(read_str(iter)?);
+                let mut metadata = read_metadata(iter, options.untranslated_descriptions)?;
+
+                let is_module = read_line(iter)?;
+                let is_module = match is_module.as_str() {
+                    "0" => false,
+                    "1" => true,
+                    _ => return Err("expected module flag on item to be 0 or 1".into()),
+                };
+                if is_module {
+                    let modifier_energy = read_ratio(iter)?;
+                    let modifier_speed = read_ratio(iter)?;
+                    let modifier_productivity = read_ratio(iter)?;
+                    let modifier_pollution = read_ratio(iter)?;
+                    module_count += 1;
+                    modules.insert(Module {
+                        id,
+                        modifier_energy,
+                        modifier_speed,
+                        modifier_productivity,
+                        modifier_pollution,
+                    });
+
+                    let has_limitations = read_line(iter)?;
+                    let has_limitations = match has_limitations.as_str() {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err("expected limitations flag on item to be 0 or 1".into()),
+                    };
+
+                    let limitations: HashSet<RecipeID> = if has_limitations {
+                        let limitation_count = read_usize(iter)?;
+                        (0..limitation_count)
+                            .map(|_| Ok(RecipeID(read_str(iter)?)))
+                            .read_into_set(limitation_count)?
+                    } else {
+                        recipes.iter().map(|recipe| recipe.id).collect()
+                    };
+
+                    for limitation in limitations {
+                        if !recipes.contains(&limitation) {
+                            if skipped.contains(&limitation.into()) {
+                                continue;
+                            }
+                            return Err("module limitation contains non-existent recipe".into());
+                        }
+                        recipe_modules.entry(limitation).or_default().insert(id);
+                    }
+                }
+                let optional = header.read_optional_fields(iter)?;
+                read_ordering(&mut metadata, &optional)?;
+
+                if log_entries {
+                    reporter.info(&format!("item {} (\"{}\")", id.str(), metadata.localised_name.str()));
+                }
+
+                Ok(Item { id, metadata })
+            })
+            .read_into_set(kept_count)?;
+        if modules.len() != module_count {
+            return Err("duplicate modules in exported data set".into());
+        }
+        if items.len() != kept_count {
+            return Err("duplicate items in exported data set".into());
+        }
+        for _ in kept_count..item_count {
+            skipped.insert(skip_item(iter, &header)?.into());
+        }
+        reporter.progress(PARSE_PROTOTYPES_STAGE, total - fluid_count, total);
+        let recipes = recipes
+            .into_iter()
+            .map(|mut recipe| {
+                if let Some(supported_modules) = recipe_modules.remove(&recipe.id) {
+                    recipe.supported_modules = supported_modules;
+                }
+                recipe
+            })
+            .collect::<HashSet<Recipe>>();
+
+        let header = read_section_header(iter)?;
+        let kept_count = limit(fluid_count);
+        let fluids = (0..kept_count)
+            .map(|_| {
+                let id = FluidID(read_str(iter)?);
+                let mut metadata = read_metadata(iter, options.untranslated_descriptions)?;
+                let optional = header.read_optional_fields(iter)?;
+                read_ordering(&mut metadata, &optional)?;
+
+                if log_entries {
+                    reporter.info(&format!("fluid {} (\"{}\")", id.str(), metadata.localised_name.str()));
+                }
+
+                Ok(Fluid { id, metadata })
+            })
+            .read_into_set(kept_count)?;
+        if fluids.len() != kept_count {
+            return Err("duplicate fluids in exported data set".into());
+        }
+        for _ in kept_count..fluid_count {
+            skipped.insert(skip_fluid(iter, &header)?.into());
+        }
+        reporter.progress(PARSE_PROTOTYPES_STAGE, total, total);
+
+        // Combine data
+        /// The modules whose effects are all allowed, and if there's an
+        /// explicit list of allowed modules, that are also part of it.
+        ///
+        /// A module without any effects is allowed everywhere, which
+        /// `GameData::validate_with_warnings` warns about.
+        fn get_allowed_modules(
+            modules: &HashSet<Module>,
+            allowed_effects: &AllowedEffects,
+            allowed_modules: Option<&HashSet<ItemID>>,
+        ) -> HashSet<ItemID> {
+            modules
+                .iter()
+                .filter(|module| allowed_effects.allows(module))
+                .filter(|module| {
+                    allowed_modules
+                        .map(|allowed| allowed.contains(&module.id))
+                        .unwrap_or(true)
+                })
+                .map(|module| module.id)
+                .collect()
+        }
+
+        for (_, (machine, allowed_effects, allowed_modules)) in machines.iter_mut() {
+            machine.supported_modules =
+                get_allowed_modules(&modules, allowed_effects, allowed_modules.as_ref());
+        }
+        for (_, (beacon, allowed_effects)) in beacons.iter_mut() {
+            beacon.supported_modules = get_allowed_modules(&modules, allowed_effects, None);
+        }
+        let machines = machines
+            .into_iter()
+            .map(|(_, (machine, _, _))| machine)
+            .collect::<HashSet<Machine>>();
+        let beacons = beacons
+            .into_iter()
+            .map(|(_, (beacon, _))| beacon)
+            .collect::<HashSet<Beacon>>();
+
+        (items, fluids, recipes, machines, beacons, modules)
+    };
+
+    let mut game_data = GameData {
+        factorio_version: None,
+        tile_metadata: None,
+        items,
+        fluids,
+        recipes,
+        machines,
+        beacons,
+        modules,
+    };
+    if !skipped.is_empty() {
+        // The skipped objects aren't part of the game data, but the recipes
+        // that were kept may still refer to them.
+        game_data.remove_all(&skipped);
+    }
+    Ok(game_data)
+}
+
+/// The lines of the localised name and description of a record.
+const METADATA_LINES: usize = 2;
+
+/// Skips a machine past `TransformOptions::max_records`, returning its ID.
+fn skip_machine(iter: &mut Iter, header: &SectionHeader) -> Result<MachineID> {
+    let id = MachineID(read_str(iter)?);
+    // The crafting speed, energy consumption and drain, module slots, and
+    // allowed effects.
+    skip_lines(iter, METADATA_LINES + 5)?;
+    header.skip_optional_fields(iter)?;
+    Ok(id)
+}
+
+/// Skips a beacon past `TransformOptions::max_records`, returning its ID.
+fn skip_beacon(iter: &mut Iter, header: &SectionHeader) -> Result<BeaconID> {
+    let id = BeaconID(read_str(iter)?);
+    // The distribution effectivity, and allowed effects.
+    skip_lines(iter, METADATA_LINES + 2)?;
+    header.skip_optional_fields(iter)?;
+    Ok(id)
+}
+
+/// Skips a recipe past `TransformOptions::max_records`, returning its ID.
+/// Only the kinds and counts that determine the length of the record are
+/// read.
+fn skip_recipe(iter: &mut Iter, header: &SectionHeader) -> Result<RecipeID> {
+    let id = RecipeID(read_str(iter)?);
+    // The time.
+    skip_lines(iter, METADATA_LINES + 1)?;
+
+    for _ in 0..read_usize(iter)? {
+        let kind = read_line(iter)?;
+        // The ID, amount, and catalyst amount.
+        skip_lines(iter, 3)?;
+        match kind.as_str() {
+            "item" => {}
+            "fluid" => {
+                let flags = read_line(iter)?;
+                let temperatures = flags.bytes().filter(|&flag| flag == b'1').count();
+                skip_lines(iter, temperatures)?;
+            }
+            _ => return Err("unknown recipe ingredient kind".into()),
+        }
+    }
+
+    for _ in 0..read_usize(iter)? {
+        let kind = read_line(iter)?;
+        match kind.as_str() {
+            "item" => skip_lines(iter, 1)?,
+            // The ID and temperature.
+            "fluid" => skip_lines(iter, 2)?,
+            _ => return Err("unknown recipe product kind".into()),
+        }
+        let kind = read_line(iter)?;
+        match kind.as_str() {
+            "fixed" => skip_lines(iter, 2)?,
+            "probability" => skip_lines(iter, 3)?,
+            _ => return Err("unknown recipe product amount kind".into()),
+        }
+    }
+
+    let crafted_in_count = read_usize(iter)?;
+    skip_lines(iter, crafted_in_count)?;
+    header.skip_optional_fields(iter)?;
+    Ok(id)
+}
+
+/// Skips an item past `TransformOptions::max_records`, returning its ID. If
+/// it's a module, the module is skipped along with it.
+fn skip_item(iter: &mut Iter, header: &SectionHeader) -> Result<ItemID> {
+    let id = ItemID(read_str(iter)?);
+    skip_lines(iter, METADATA_LINES)?;
+    match read_line(iter)?.as_str() {
+        "0" => {}
+        "1" => {
+            // The energy, speed, productivity, and pollution modifiers.
+            skip_lines(iter, 4)?;
+            match read_line(iter)?.as_str() {
+                "0" => {}
+                "1" => {
+                    let limitation_count = read_usize(iter)?;
+                    skip_lines(iter, limitation_count)?;
+                }
+                _ => return Err("expected limitations flag on item to be 0 or 1".into()),
+            }
+        }
+        _ => return Err("expected module flag on item to be 0 or 1".into()),
+    }
+    header.skip_optional_fields(iter)?;
+    Ok(id)
+}
+
+/// Skips a fluid past `TransformOptions::max_records`, returning its ID.
+fn skip_fluid(iter: &mut Iter, header: &SectionHeader) -> Result<FluidID> {
+    let id = FluidID(read_str(iter)?);
+    skip_lines(iter, METADATA_LINES)?;
+    header.skip_optional_fields(iter)?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Int, Ratio, Str, ValidationWarning};
+
+    fn parts(parts: &[&str]) -> String {
+        parts.join(&delimiters::SEPARATOR.to_string())
+    }
+
+    fn localised(key: &str, value: &str) -> String {
+        parts(&[key, value])
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|&line| line.to_owned()).collect()
+    }
+
+    /// Builds the lines of an export, one record at a time.
+    #[derive(Default)]
+    struct Export {
+        machines: Vec<Vec<String>>,
+        beacons: Vec<Vec<String>>,
+        recipes: Vec<Vec<String>>,
+        items: Vec<Vec<String>>,
+        fluids: Vec<Vec<String>>,
+    }
+
+    impl Export {
+        /// Adds a machine that allows every effect, and if `allowed_modules`
+        /// is given, only those modules.
+        fn machine(mut self, name: &str, allowed_modules: Option<&[&str]>) -> Export {
+            let mut record = lines(&[name, &localised("entity-name", name), &localised("entity-description", name)]);
+            record.extend(lines(&["1", "75000", "0", "2", "1111"]));
+            record.push(match allowed_modules {
+                Some(modules) => {
+                    let count = modules.len().to_string();
+                    parts(&[&[count.as_str()], modules].concat())
+                }
+                None => String::new(),
+            });
+            record.push(parts(&["1", "crafting"]));
+            self.machines.push(record);
+            self
+        }
+
+        fn beacon(mut self, name: &str) -> Export {
+            let mut record = lines(&[name, &localised("entity-name", name), &localised("entity-description", name)]);
+            record.extend(lines(&["0.5", "1111"]));
+            self.beacons.push(record);
+            self
+        }
+
+        /// Adds a recipe taking one of each of `ingredients`, and producing
+        /// one of each of `products`, all being items.
+        fn recipe(self, name: &str, ingredients: &[&str], products: &[&str], crafted_in: &[&str]) -> Export {
+            let products = products
+                .iter()
+                .map(|&product| vec!["item", product, "fixed", "1", "0"])
+                .collect::<Vec<_>>();
+            let products = products.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            self.recipe_with_products(name, ingredients, &products, crafted_in)
+        }
+
+        /// Like `recipe`, but with the lines of each product given as is.
+        fn recipe_with_products(
+            mut self,
+            name: &str,
+            ingredients: &[&str],
+            products: &[&[&str]],
+            crafted_in: &[&str],
+        ) -> Export {
+            let mut record = lines(&[name, &localised("recipe-name", name), &localised("recipe-description", name)]);
+            record.push("1".to_owned());
+            record.push(ingredients.len().to_string());
+            for ingredient in ingredients {
+                record.extend(lines(&["item", ingredient, "1", "0"]));
+            }
+            record.push(products.len().to_string());
+            for product in products {
+                record.extend(lines(product));
+            }
+            record.push(crafted_in.len().to_string());
+            record.extend(lines(crafted_in));
+            record.extend(lines(&["", "", "crafting"]));
+            self.recipes.push(record);
+            self
+        }
+
+        fn item(mut self, name: &str) -> Export {
+            let mut record = lines(&[name, &localised("item-name", name), &localised("item-description", name)]);
+            record.push("0".to_owned());
+            self.items.push(record);
+            self
+        }
+
+        /// Adds a module with the given energy, speed, productivity, and
+        /// pollution modifiers, limited to `limitations` if given.
+        fn module(mut self, name: &str, modifiers: [&str; 4], limitations: Option<&[&str]>) -> Export {
+            let mut record = lines(&[name, &localised("item-name", name), &localised("item-description", name)]);
+            record.push("1".to_owned());
+            record.extend(lines(&modifiers));
+            match limitations {
+                Some(limitations) => {
+                    record.push("1".to_owned());
+                    record.push(limitations.len().to_string());
+                    record.extend(lines(limitations));
+                }
+                None => record.push("0".to_owned()),
+            }
+            self.items.push(record);
+            self
+        }
+
+        fn fluid(mut self, name: &str) -> Export {
+            self.fluids
+                .push(lines(&[name, &localised("fluid-name", name), &localised("fluid-description", name)]));
+            self
+        }
+
+        fn lines(&self) -> Vec<String> {
+            let sections = [
+                (&self.machines, parts(&["1", "allowed_modules", "crafting_categories"])),
+                (&self.beacons, "1".to_owned()),
+                (&self.recipes, parts(&["1", "emissions_multiplier", "main_product", "category"])),
+                (&self.items, "1".to_owned()),
+                (&self.fluids, "1".to_owned()),
+            ];
+            let counts = sections
+                .iter()
+                .map(|(records, _)| records.len().to_string())
+                .collect::<Vec<_>>();
+            let mut lines = vec![counts.join(&delimiters::SEPARATOR.to_string())];
+            for (records, header) in sections.iter() {
+                lines.push(header.clone());
+                lines.extend(records.iter().flatten().cloned());
+            }
+            lines
+        }
+
+        fn parse(&self) -> Result<GameData> {
+            parse_prototypes(self.lines(), TransformOptions::default())
+        }
+    }
+
+    fn supported_modules(modules: &HashSet<ItemID>) -> Vec<&'static str> {
+        let mut modules = modules.iter().map(|module| module.str()).collect::<Vec<_>>();
+        modules.sort();
+        modules
+    }
+
+    const SPEED: [&str; 4] = ["0.5", "0.2", "0", "0"];
+
+    #[test]
+    fn duplicate_module_is_an_error() {
+        let export = Export::default()
+            .module("speed-module", SPEED, None)
+            .module("speed-module", SPEED, None);
+        assert_eq!(
+            export.parse().unwrap_err(),
+            ParseError::Message("duplicate modules in exported data set")
+        );
+    }
+
+    #[test]
+    fn duplicate_item_is_an_error() {
+        let export = Export::default().item("plate").item("plate");
+        assert_eq!(
+            export.parse().unwrap_err(),
+            ParseError::Message("duplicate items in exported data set")
+        );
+    }
+
+    #[test]
+    fn duplicate_crafted_in_is_a_warning() {
+        let export = Export::default()
+            .machine("assembler", None)
+            .machine("furnace", None)
+            .recipe("gear", &["plate"], &["gear"], &["assembler", "furnace", "assembler"])
+            .recipe("rod", &["plate"], &["rod"], &["assembler"])
+            .item("plate")
+            .item("gear")
+            .item("rod");
+        let (game_data, warnings) = parse_prototypes_with_warnings(export.lines(), TransformOptions::default()).unwrap();
+
+        assert_eq!(
+            warnings,
+            vec![ParseWarning::DuplicateCraftedIn {
+                recipe: RecipeID(Str::new("gear")),
+                read: 3,
+                stored: 2,
+            }]
+        );
+        let gear = game_data.recipes.get(&RecipeID(Str::new("gear"))).unwrap();
+        assert_eq!(gear.crafted_in.len(), 2);
+    }
+
+    #[test]
+    fn allowed_modules_restrict_what_the_effects_allow() {
+        let game_data = Export::default()
+            .machine("restricted", Some(&["speed-module"]))
+            .machine("unrestricted", None)
+            .machine("no-modules", Some(&[]))
+            .beacon("beacon")
+            .recipe("iron-gear-wheel", &["iron-plate"], &["iron-gear-wheel"], &["restricted"])
+            .item("iron-plate")
+            .item("iron-gear-wheel")
+            .module("speed-module", SPEED, None)
+            .module("speed-module-2", SPEED, None)
+            .fluid("water")
+            .parse()
+            .unwrap();
+
+        let machine = |name: &str| game_data.machines.get(&MachineID(Str::new(name))).unwrap();
+        assert_eq!(supported_modules(&machine("restricted").supported_modules), vec!["speed-module"]);
+        assert_eq!(
+            supported_modules(&machine("unrestricted").supported_modules),
+            vec!["speed-module", "speed-module-2"]
+        );
+        assert!(machine("no-modules").supported_modules.is_empty());
+
+        // Beacons have no list, and the recipe isn't limited.
+        let beacon = game_data.beacons.iter().next().unwrap();
+        assert_eq!(supported_modules(&beacon.supported_modules), vec!["speed-module", "speed-module-2"]);
+        let recipe = game_data.recipes.iter().next().unwrap();
+        assert_eq!(supported_modules(&recipe.supported_modules), vec!["speed-module", "speed-module-2"]);
+    }
+
+    #[test]
+    fn module_without_effects_is_supported_everywhere_with_a_warning() {
+        let game_data = Export::default()
+            .machine("restricted", Some(&["no-op-module"]))
+            .machine("unrestricted", None)
+            .beacon("beacon")
+            .recipe("iron-gear-wheel", &["iron-plate"], &["iron-gear-wheel"], &["restricted"])
+            .item("iron-plate")
+            .item("iron-gear-wheel")
+            .module("no-op-module", ["0", "0", "0", "0"], None)
+            .module("speed-module", SPEED, None)
+            .parse()
+            .unwrap();
+
+        for machine in &game_data.machines {
+            assert!(machine.supports_module_str("no-op-module"), "{}", machine.id.str());
+        }
+        for beacon in &game_data.beacons {
+            assert!(beacon.supports_module_str("no-op-module"));
+        }
+        for recipe in &game_data.recipes {
+            assert!(recipe.supports_module_str("no-op-module"));
+        }
+
+        let (errors, warnings) = game_data.check_all_with_warnings();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            warnings,
+            vec![ValidationWarning::NoOpModule {
+                id: ItemID(Str::new("no-op-module")),
+            }]
+        );
+    }
+
+    #[test]
+    fn module_limitation_applies_to_every_listed_recipe() {
+        let recipes = ["gear", "rod", "cable", "circuit", "pipe"];
+        let mut export = Export::default().machine("assembler", None);
+        for recipe in &recipes {
+            export = export.recipe(recipe, &["plate"], &[recipe], &["assembler"]);
+        }
+        let game_data = export
+            .recipe("plate", &[], &["plate"], &["assembler"])
+            .module("productivity-module", ["0.4", "-0.05", "0.04", "0.05"], Some(&recipes))
+            .module("speed-module", SPEED, None)
+            .item("plate")
+            .parse()
+            .unwrap();
+
+        for recipe in &recipes {
+            let recipe = game_data.recipes.get(&RecipeID(Str::new(recipe))).unwrap();
+            assert_eq!(
+                supported_modules(&recipe.supported_modules),
+                vec!["productivity-module", "speed-module"],
+                "{}",
+                recipe.id.str()
+            );
+        }
+        let plate = game_data.recipes.get(&RecipeID(Str::new("plate"))).unwrap();
+        assert_eq!(supported_modules(&plate.supported_modules), vec!["speed-module"]);
+    }
+
+    #[test]
+    fn module_limited_to_skipped_recipes_is_kept() {
+        let export = Export::default()
+            .machine("assembler", None)
+            .machine("furnace", None)
+            .recipe("module-recipe", &[], &["limited-module"], &["assembler", "furnace"])
+            .recipe("gear", &["plate"], &["gear"], &["assembler"])
+            .module("limited-module", SPEED, Some(&["module-recipe", "gear"]))
+            .item("plate")
+            .item("gear")
+            .fluid("water")
+            .fluid("steam");
+        let options = TransformOptions {
+            max_records: Some(1),
+            ..TransformOptions::default()
+        };
+        let game_data = parse_prototypes(export.lines(), options).unwrap();
+
+        assert_eq!(game_data.machines.len(), 1);
+        assert_eq!(game_data.fluids.len(), 1);
+        assert_eq!(game_data.modules.len(), 1);
+        let recipe = game_data.recipes.get(&RecipeID(Str::new("module-recipe"))).unwrap();
+        assert_eq!(supported_modules(&recipe.supported_modules), vec!["limited-module"]);
+        // The skipped furnace was taken out of the machines it's crafted in.
+        assert_eq!(recipe.crafted_in.len(), 1);
+
+        // Limitations to recipes that weren't exported at all are errors.
+        let export = Export::default()
+            .recipe("gear", &[], &["gear"], &[])
+            .module("limited-module", SPEED, Some(&["missing"]));
+        assert_eq!(
+            parse_prototypes(export.lines(), options).unwrap_err(),
+            ParseError::Message("module limitation contains non-existent recipe")
+        );
+    }
+
+    #[test]
+    fn crafting_matrix_lists_the_machines_of_each_recipe() {
+        let export = Export::default()
+            .machine("furnace", None)
+            .machine("assembler", None)
+            .recipe("wire", &["plate"], &["wire"], &["furnace", "assembler"])
+            .recipe("gear", &["plate"], &["gear"], &["assembler"])
+            .item("plate")
+            .item("wire")
+            .item("gear");
+        let game_data = export.parse().unwrap();
+
+        let matrix = game_data
+            .crafting_matrix()
+            .into_iter()
+            .map(|(recipe, machines)| {
+                (
+                    recipe.str(),
+                    machines.into_iter().map(|machine| machine.str()).collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            matrix,
+            vec![("gear", vec!["assembler"]), ("wire", vec!["assembler", "furnace"])]
+        );
+    }
+
+    #[test]
+    fn hand_crafted_recipes_have_a_row_without_machines() {
+        let export = Export::default()
+            .machine("assembler", None)
+            .recipe("gear", &["plate"], &["gear"], &["assembler"])
+            .recipe("wood", &[], &["wood"], &[])
+            .item("plate")
+            .item("gear")
+            .item("wood");
+        let matrix = export.parse().unwrap().crafting_matrix();
+
+        assert_eq!(matrix.len(), 2);
+        assert_eq!(matrix[1].0.str(), "wood");
+        assert!(matrix[1].1.is_empty());
+    }
+
+    #[test]
+    fn fluid_products_may_be_below_zero() {
+        let liquid_air: &[&str] = &["fluid", "liquid-air", "-50", "fixed", "10", "0"];
+        let export = Export::default()
+            .recipe_with_products("liquefaction", &["air"], &[liquid_air], &[])
+            .item("air")
+            .fluid("liquid-air");
+        let game_data = export.parse().unwrap();
+
+        let recipe = game_data.recipe_by_str("liquefaction").unwrap();
+        match &recipe.products[0].resource {
+            ProductResource::Fluid { id, temperature } => {
+                assert_eq!(id.str(), "liquid-air");
+                assert_eq!(*temperature, Ratio::from_integer(Int::from(-50)));
+            }
+            resource => panic!("expected a fluid, got {:?}", resource),
+        }
+    }
+
+    #[test]
+    fn products_of_nothing_are_rejected() {
+        for amount in &["0", "-1"] {
+            let export = Export::default()
+                .recipe_with_products("nothing", &[], &[&["item", "gear", "fixed", amount, "0"]], &[])
+                .item("gear");
+            assert_eq!(
+                export.parse().unwrap_err(),
+                ParseError::InvalidValue {
+                    expected: "positive product amount",
+                    value: amount.to_string(),
+                }
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::parsing::ParseWarning;
+use crate::validation::ValidationWarning;
+use std::fmt;
+
+/// Something that doesn't prevent the game data from being produced, but
+/// that may indicate a problem with it.
+///
+/// Warnings are serializable, so that they can be stored along with the game
+/// data that they're about, and reported again later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Warning {
+    /// A quirk of the exported prototypes.
+    Parse(ParseWarning),
+    /// Something in the game data that's likely a mistake.
+    Validation(ValidationWarning),
+    /// Any other warning, described by its message.
+    Message(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::Parse(warning) => warning.fmt(f),
+            Warning::Validation(warning) => warning.fmt(f),
+            Warning::Message(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<ParseWarning> for Warning {
+    fn from(warning: ParseWarning) -> Warning {
+        Warning::Parse(warning)
+    }
+}
+
+impl From<ValidationWarning> for Warning {
+    fn from(warning: ValidationWarning) -> Warning {
+        Warning::Validation(warning)
+    }
+}
+
+impl From<String> for Warning {
+    fn from(message: String) -> Warning {
+        Warning::Message(message)
+    }
+}
+
+impl<'a> From<&'a str> for Warning {
+    fn from(message: &'a str) -> Warning {
+        Warning::Message(message.to_owned())
+    }
+}
+
+/// Receives what happens while the game data is being produced, so that an
+/// application embedding this crate can show it however it likes, instead
+/// of it being printed.
+pub trait Reporter {
+    /// Describes what's being done, such as an object that was read.
+    fn info(&mut self, message: &str);
+
+    /// Reports a warning, which doesn't cause the stage to fail.
+    fn warn(&mut self, warning: Warning);
+
+    /// Reports that `done` out of `total` steps of `stage` are finished.
+    fn progress(&mut self, _stage: &str, _done: usize, _total: usize) {}
+}
+
+/// Prints everything that's reported to the standard output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleReporter;
+
+impl Reporter for ConsoleReporter {
+    fn info(&mut self, message: &str) {
+        println!("{}", message);
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        println!("warning: {}", warning);
+    }
+
+    fn progress(&mut self, stage: &str, done: usize, total: usize) {
+        println!("{}: {}/{}", stage, done, total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemID, RecipeID, Str};
+
+    #[test]
+    fn warnings_round_trip_with_their_kind() {
+        let warnings = vec![
+            Warning::Parse(ParseWarning::DuplicateCraftedIn {
+                recipe: RecipeID(Str::new("iron-gear-wheel")),
+                read: 3,
+                stored: 2,
+            }),
+            Warning::Validation(ValidationWarning::NoOpModule {
+                id: ItemID(Str::new("empty-module")),
+            }),
+            Warning::from("something odd"),
+        ];
+        let json = serde_json::to_value(&warnings).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "parse": { "duplicate_crafted_in": { "recipe": "iron-gear-wheel", "read": 3, "stored": 2 } }
+            })
+        );
+        assert_eq!(json[2], serde_json::json!({ "message": "something odd" }));
+        assert_eq!(serde_json::from_value::<Vec<Warning>>(json).unwrap(), warnings);
+    }
+}
@@ -0,0 +1,363 @@
+//! Deserialization of the enums that are flattened into their containers,
+//! such as `IngredientResource` into `Ingredient`, where the variant is one
+//! of the keys of the container.
+//!
+//! The derived deserialization can't tell which keys belong to the enum, so
+//! it reports a missing variant without naming the keys it looked for, and
+//! ignores fields that don't belong to the variant, such as an item with a
+//! temperature. The variants are read by hand instead.
+
+use crate::{serde_option_ratio, serde_ratio, FluidID, IngredientResource, ItemID, ProductAmount, ProductResource, Ratio};
+use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// An enum that's stored as one of the keys of its container, naming its
+/// variant, with the fields of the variant as its value.
+trait Flattened: Sized {
+    const NAME: &'static str;
+    const VARIANTS: &'static [&'static str];
+
+    /// Reads the value of the key `variant`, which is one of `VARIANTS`.
+    fn variant<'de, A: MapAccess<'de>>(variant: &str, map: &mut A) -> Result<Self, A::Error>;
+}
+
+struct VariantList(&'static [&'static str]);
+
+impl fmt::Display for VariantList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, variant) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "`{}`", variant)?;
+        }
+        Ok(())
+    }
+}
+
+struct FlattenedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Flattened> Visitor<'de> for FlattenedVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "enum {} with one of the keys {}", T::NAME, VariantList(T::VARIANTS))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let mut value = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if !T::VARIANTS.contains(&key.as_str()) {
+                // The other fields of the container, which include the
+                // variants of other enums flattened into it.
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            if value.is_some() {
+                return Err(de::Error::custom(format_args!(
+                    "enum {} has more than one of the keys {}",
+                    T::NAME,
+                    VariantList(T::VARIANTS)
+                )));
+            }
+            value = Some(T::variant(&key, &mut map).map_err(|error| {
+                de::Error::custom(format_args!("invalid `{}` variant of enum {}: {}", key, T::NAME, error))
+            })?);
+        }
+        value.ok_or_else(|| {
+            de::Error::custom(format_args!(
+                "enum {} is missing one of the keys {}",
+                T::NAME,
+                VariantList(T::VARIANTS)
+            ))
+        })
+    }
+}
+
+macro_rules! implement_deserialize {
+    ($($enum:ty),*) => {
+        $(
+            impl<'de> Deserialize<'de> for $enum {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: Deserializer<'de>,
+                {
+                    deserializer.deserialize_map(FlattenedVisitor(PhantomData))
+                }
+            }
+        )*
+    };
+}
+
+implement_deserialize!(IngredientResource, ProductResource, ProductAmount);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemVariant {
+    id: ItemID,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IngredientFluidVariant {
+    id: FluidID,
+    #[serde(with = "serde_option_ratio", default)]
+    minimum_temperature: Option<Ratio>,
+    #[serde(with = "serde_option_ratio", default)]
+    maximum_temperature: Option<Ratio>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProductFluidVariant {
+    id: FluidID,
+    #[serde(with = "serde_ratio")]
+    temperature: Ratio,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixedVariant {
+    #[serde(with = "serde_ratio")]
+    amount: Ratio,
+    #[serde(with = "serde_ratio")]
+    catalyst_amount: Ratio,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbabilityVariant {
+    #[serde(with = "serde_ratio")]
+    amount_min: Ratio,
+    #[serde(with = "serde_ratio")]
+    amount_max: Ratio,
+    #[serde(with = "serde_ratio")]
+    probability: Ratio,
+}
+
+impl Flattened for IngredientResource {
+    const NAME: &'static str = "IngredientResource";
+    const VARIANTS: &'static [&'static str] = &["item", "fluid"];
+
+    fn variant<'de, A: MapAccess<'de>>(variant: &str, map: &mut A) -> Result<Self, A::Error> {
+        match variant {
+            "item" => {
+                let ItemVariant { id } = map.next_value()?;
+                Ok(IngredientResource::Item { id })
+            }
+            "fluid" => {
+                let fluid: IngredientFluidVariant = map.next_value()?;
+                Ok(IngredientResource::Fluid {
+                    id: fluid.id,
+                    minimum_temperature: fluid.minimum_temperature,
+                    maximum_temperature: fluid.maximum_temperature,
+                })
+            }
+            _ => Err(de::Error::unknown_variant(variant, Self::VARIANTS)),
+        }
+    }
+}
+
+impl Flattened for ProductResource {
+    const NAME: &'static str = "ProductResource";
+    const VARIANTS: &'static [&'static str] = &["item", "fluid"];
+
+    fn variant<'de, A: MapAccess<'de>>(variant: &str, map: &mut A) -> Result<Self, A::Error> {
+        match variant {
+            "item" => {
+                let ItemVariant { id } = map.next_value()?;
+                Ok(ProductResource::Item { id })
+            }
+            "fluid" => {
+                let ProductFluidVariant { id, temperature } = map.next_value()?;
+                Ok(ProductResource::Fluid { id, temperature })
+            }
+            _ => Err(de::Error::unknown_variant(variant, Self::VARIANTS)),
+        }
+    }
+}
+
+impl Flattened for ProductAmount {
+    const NAME: &'static str = "ProductAmount";
+    const VARIANTS: &'static [&'static str] = &["fixed", "probability"];
+
+    fn variant<'de, A: MapAccess<'de>>(variant: &str, map: &mut A) -> Result<Self, A::Error> {
+        match variant {
+            "fixed" => {
+                let FixedVariant { amount, catalyst_amount } = map.next_value()?;
+                Ok(ProductAmount::Fixed { amount, catalyst_amount })
+            }
+            "probability" => {
+                let ProbabilityVariant {
+                    amount_min,
+                    amount_max,
+                    probability,
+                } = map.next_value()?;
+                Ok(ProductAmount::Probability {
+                    amount_min,
+                    amount_max,
+                    probability,
+                })
+            }
+            _ => Err(de::Error::unknown_variant(variant, Self::VARIANTS)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FluidID, Ingredient, IngredientResource, Int, ItemID, Product, ProductAmount, ProductResource, Ratio, Str};
+
+    fn ratio(numerator: i64) -> Ratio {
+        Ratio::from_integer(Int::from(numerator))
+    }
+
+    /// The message of the error when deserializing `json`, without the
+    /// position that serde_json appends.
+    fn error<T: ::std::fmt::Debug + for<'de> serde::Deserialize<'de>>(json: &str) -> String {
+        let message = serde_json::from_str::<T>(json).unwrap_err().to_string();
+        match message.find(" at line ") {
+            Some(end) => message[..end].to_owned(),
+            None => message,
+        }
+    }
+
+    fn round_trip<T>(value: &T)
+    where
+        T: ::std::fmt::Debug + PartialEq + serde::Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value, "{}", json);
+    }
+
+    #[test]
+    fn valid_variants_round_trip() {
+        round_trip(&Ingredient {
+            resource: IngredientResource::Item {
+                id: ItemID(Str::new("iron-plate")),
+            },
+            amount: ratio(2),
+            catalyst_amount: ratio(0),
+        });
+        round_trip(&Ingredient {
+            resource: IngredientResource::Fluid {
+                id: FluidID(Str::new("water")),
+                minimum_temperature: Some(ratio(15)),
+                maximum_temperature: None,
+            },
+            amount: ratio(100),
+            catalyst_amount: ratio(0),
+        });
+        round_trip(&Product {
+            resource: ProductResource::Fluid {
+                id: FluidID(Str::new("steam")),
+                temperature: ratio(-10),
+            },
+            amount: ProductAmount::Fixed {
+                amount: ratio(60),
+                catalyst_amount: ratio(0),
+            },
+        });
+        round_trip(&Product {
+            resource: ProductResource::Item {
+                id: ItemID(Str::new("uranium-235")),
+            },
+            amount: ProductAmount::Probability {
+                amount_min: ratio(1),
+                amount_max: ratio(1),
+                probability: Ratio::new(Int::from(7), Int::from(1000)),
+            },
+        });
+        // Not flattened, as a recipe's main product.
+        round_trip(&ProductResource::Item {
+            id: ItemID(Str::new("iron-plate")),
+        });
+    }
+
+    #[test]
+    fn valid_variants_are_read_from_their_key() {
+        let ingredient: Ingredient = serde_json::from_str(
+            r#"{"fluid": {"id": "water", "maximum_temperature": "100"}, "amount": "10", "catalyst_amount": "0"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ingredient.resource,
+            IngredientResource::Fluid {
+                id: FluidID(Str::new("water")),
+                minimum_temperature: None,
+                maximum_temperature: Some(ratio(100)),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_variant_names_the_enum() {
+        assert_eq!(
+            error::<Ingredient>(r#"{"steam": {"id": "steam"}, "amount": "1", "catalyst_amount": "0"}"#),
+            "enum IngredientResource is missing one of the keys `item`, `fluid`"
+        );
+        // A variant stored as a type field, rather than as a key.
+        assert_eq!(
+            error::<Product>(r#"{"type": "item", "id": "iron-plate", "fixed": {"amount": "1", "catalyst_amount": "0"}}"#),
+            "enum ProductResource is missing one of the keys `item`, `fluid`"
+        );
+    }
+
+    #[test]
+    fn missing_variant_names_the_enum() {
+        assert_eq!(
+            error::<Ingredient>(r#"{"amount": "1", "catalyst_amount": "0"}"#),
+            "enum IngredientResource is missing one of the keys `item`, `fluid`"
+        );
+        assert_eq!(
+            error::<Product>(r#"{"item": {"id": "iron-plate"}, "amount": "1"}"#),
+            "enum ProductAmount is missing one of the keys `fixed`, `probability`"
+        );
+        assert_eq!(
+            error::<ProductResource>(r#"{}"#),
+            "enum ProductResource is missing one of the keys `item`, `fluid`"
+        );
+    }
+
+    #[test]
+    fn item_with_a_temperature_is_rejected() {
+        assert_eq!(
+            error::<Product>(
+                r#"{"item": {"id": "iron-plate", "temperature": "15"}, "fixed": {"amount": "1", "catalyst_amount": "0"}}"#
+            ),
+            "invalid `item` variant of enum ProductResource: unknown field `temperature`, expected `id`"
+        );
+        assert_eq!(
+            error::<Ingredient>(
+                r#"{"item": {"id": "iron-plate", "minimum_temperature": "15"}, "amount": "1", "catalyst_amount": "0"}"#
+            ),
+            "invalid `item` variant of enum IngredientResource: unknown field `minimum_temperature`, expected `id`"
+        );
+    }
+
+    #[test]
+    fn variant_with_missing_fields_is_rejected() {
+        assert_eq!(
+            error::<Product>(r#"{"fluid": {"id": "steam"}, "fixed": {"amount": "1", "catalyst_amount": "0"}}"#),
+            "invalid `fluid` variant of enum ProductResource: missing field `temperature`"
+        );
+        assert_eq!(
+            error::<Product>(
+                r#"{"item": {"id": "uranium-235"}, "probability": {"amount_min": "1", "amount_max": "1"}}"#
+            ),
+            "invalid `probability` variant of enum ProductAmount: missing field `probability`"
+        );
+    }
+
+    #[test]
+    fn more_than_one_variant_is_rejected() {
+        assert_eq!(
+            error::<Ingredient>(
+                r#"{"item": {"id": "iron-plate"}, "fluid": {"id": "water"}, "amount": "1", "catalyst_amount": "0"}"#
+            ),
+            "enum IngredientResource has more than one of the keys `item`, `fluid`"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Ratio;
+use crate::{format_ratio, Ratio, RatioFormat};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::str::FromStr;
 
@@ -8,7 +8,7 @@ pub fn serialize<S>(ratio: &Ratio, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let s = ratio.to_string();
+    let s = format_ratio(ratio, RatioFormat::Improper);
     Passthrough::serialize(&s, serializer)
 }
 
@@ -0,0 +1,64 @@
+use crate::{Beacon, Fluid, Item, ItemID, Machine, MachineID, Module, Recipe, Str};
+use serde::{Serialize, Serializer};
+use std::collections::HashSet;
+
+/// The key by which the entries of a set are sorted when it's serialized,
+/// so that equal game data is always stored in the same way, rather than
+/// in the arbitrary order of the hash set.
+pub trait SortKey {
+    fn sort_key(&self) -> &'static str;
+}
+
+macro_rules! implement_sort_key {
+    ($($object:ty),*) => {
+        $(
+            impl SortKey for $object {
+                fn sort_key(&self) -> &'static str {
+                    self.id.str()
+                }
+            }
+        )*
+    };
+}
+
+implement_sort_key!(Item, Fluid, Recipe, Machine, Beacon, Module);
+
+impl SortKey for ItemID {
+    fn sort_key(&self) -> &'static str {
+        self.str()
+    }
+}
+
+impl SortKey for Str {
+    fn sort_key(&self) -> &'static str {
+        self.str()
+    }
+}
+
+impl SortKey for MachineID {
+    fn sort_key(&self) -> &'static str {
+        self.str()
+    }
+}
+
+/// The entries of a set, sorted by their key.
+pub fn sorted<T: SortKey>(set: &HashSet<T>) -> Vec<&T> {
+    let mut entries = set.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.sort_key());
+    entries
+}
+
+/// Like `sorted`, but takes the entries out of the set.
+pub fn into_sorted<T: SortKey>(set: HashSet<T>) -> Vec<T> {
+    let mut entries = set.into_iter().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.sort_key());
+    entries
+}
+
+pub fn serialize<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize + SortKey,
+    S: Serializer,
+{
+    serializer.collect_seq(sorted(set))
+}
@@ -0,0 +1,16 @@
+//! Data shared by the tests of the different modules.
+
+use crate::{parse_prototypes, GameData, TransformOptions};
+
+/// The records of a small export, as stored in `prototypes.json`, which the
+/// integration tests use as well.
+const PROTOTYPES: &str = include_str!("../tests/fixtures/prototypes.json");
+
+pub fn prototypes() -> Vec<String> {
+    serde_json::from_str(PROTOTYPES).unwrap()
+}
+
+/// The game data that the fixture transforms into.
+pub fn game_data() -> GameData {
+    parse_prototypes(prototypes(), TransformOptions::default()).unwrap()
+}
@@ -0,0 +1,277 @@
+use crate::serde_sorted_set;
+use crate::{
+    FluidID, GameData, GameObject, IngredientResource, ItemID, MachineID, ProductResource,
+    ID,
+};
+use num_traits::identities::Zero;
+use num_traits::Signed;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// An object refers to another object, which isn't part of the game data.
+    UnresolvedReference { from: ID, to: ID },
+    /// An object lists an item as a supported module, but that item isn't a module.
+    NotAModule { from: ID, module: ItemID },
+    /// A recipe takes no time, or a negative amount of time, to craft.
+    NonPositiveRecipeTime { recipe: ID },
+    /// A machine has no crafting speed, or a negative one.
+    NonPositiveCraftingSpeed { machine: ID },
+    /// A machine has a negative amount of module slots.
+    NegativeModuleSlots { machine: ID },
+    /// An object has an icon, but there's no tile metadata, or the
+    /// icon lies outside of the tiles described by it.
+    IconOutOfRange { id: ID },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::UnresolvedReference { from, to } => write!(
+                f,
+                "{} refers to {}, which doesn't exist",
+                from.str(),
+                to.str()
+            ),
+            ValidationError::NotAModule { from, module } => write!(
+                f,
+                "{} supports module {}, which isn't a module",
+                from.str(),
+                module.str()
+            ),
+            ValidationError::NonPositiveRecipeTime { recipe } => {
+                write!(f, "recipe {} doesn't take a positive amount of time", recipe.str())
+            }
+            ValidationError::NonPositiveCraftingSpeed { machine } => {
+                write!(f, "machine {} doesn't have a positive crafting speed", machine.str())
+            }
+            ValidationError::NegativeModuleSlots { machine } => {
+                write!(f, "machine {} has a negative amount of module slots", machine.str())
+            }
+            ValidationError::IconOutOfRange { id } => {
+                write!(f, "{} has an icon outside of the tile set", id.str())
+            }
+        }
+    }
+}
+
+/// Something in the game data that's likely a mistake, but that the game
+/// data is still valid with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationWarning {
+    /// A module has no effects at all, which means that every machine and
+    /// beacon supports it.
+    NoOpModule { id: ItemID },
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationWarning::NoOpModule { id } => write!(
+                f,
+                "module {} has no effects, so it's supported everywhere",
+                id.str()
+            ),
+        }
+    }
+}
+
+impl GameData {
+    /// Checks the values of the objects for consistency, such as recipes
+    /// taking a positive amount of time, and icons lying within the tile set.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        self.validate_with_warnings().0
+    }
+
+    /// Like `validate`, but also returns the warnings encountered.
+    pub fn validate_with_warnings(&self) -> (Vec<ValidationError>, Vec<ValidationWarning>) {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        for recipe in &self.recipes {
+            if !recipe.time.is_positive() {
+                errors.push(ValidationError::NonPositiveRecipeTime {
+                    recipe: recipe.id.into(),
+                });
+            }
+        }
+        for machine in &self.machines {
+            if !machine.crafting_speed.is_positive() {
+                errors.push(ValidationError::NonPositiveCraftingSpeed {
+                    machine: machine.id.into(),
+                });
+            }
+            if machine.module_slots.is_negative() {
+                errors.push(ValidationError::NegativeModuleSlots {
+                    machine: machine.id.into(),
+                });
+            }
+        }
+        for module in serde_sorted_set::sorted(&self.modules) {
+            if module.modifier_energy.is_zero()
+                && module.modifier_speed.is_zero()
+                && module.modifier_productivity.is_zero()
+                && module.modifier_pollution.is_zero()
+            {
+                warnings.push(ValidationWarning::NoOpModule { id: module.id });
+            }
+        }
+
+        let tile_count = self
+            .tile_metadata
+            .as_ref()
+            .map_or(0, |tile_metadata| tile_metadata.tile_count as usize);
+        for id in self.all_ids() {
+            let icon = self.metadata_for(id).and_then(|metadata| metadata.icon);
+            if let Some(icon) = icon {
+                if icon.index() >= tile_count {
+                    errors.push(ValidationError::IconOutOfRange { id });
+                }
+            }
+        }
+
+        (errors, warnings)
+    }
+
+    /// Checks that every ID that's referred to, such as the ingredients
+    /// and products of recipes, resolves to an object in the game data.
+    pub fn resolve_all(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        {
+            let mut check = |from: ID, to: ID, exists: bool| {
+                if !exists {
+                    errors.push(ValidationError::UnresolvedReference { from, to });
+                }
+            };
+            let item = |id: ItemID| (ID::Item(id), id.try_resolve(self).is_some());
+            let fluid = |id: FluidID| (ID::Fluid(id), id.try_resolve(self).is_some());
+            let machine = |id: MachineID| (ID::Machine(id), id.try_resolve(self).is_some());
+
+            for recipe in &self.recipes {
+                let from = ID::from(recipe.id);
+                for ingredient in &recipe.ingredients {
+                    let (to, exists) = match ingredient.resource {
+                        IngredientResource::Item { id } => item(id),
+                        IngredientResource::Fluid { id, .. } => fluid(id),
+                    };
+                    check(from, to, exists);
+                }
+                let products = recipe.products.iter().map(|product| &product.resource);
+                for resource in products.chain(recipe.main_product.iter()) {
+                    let (to, exists) = match resource {
+                        ProductResource::Item { id } => item(*id),
+                        ProductResource::Fluid { id, .. } => fluid(*id),
+                    };
+                    check(from, to, exists);
+                }
+                for &id in &recipe.crafted_in {
+                    let (to, exists) = machine(id);
+                    check(from, to, exists);
+                }
+                for &id in &recipe.supported_modules {
+                    let (to, exists) = item(id);
+                    check(from, to, exists);
+                }
+            }
+            for machine in &self.machines {
+                for &id in &machine.supported_modules {
+                    let (to, exists) = item(id);
+                    check(machine.id.into(), to, exists);
+                }
+            }
+            for beacon in &self.beacons {
+                for &id in &beacon.supported_modules {
+                    let (to, exists) = item(id);
+                    check(beacon.id.into(), to, exists);
+                }
+            }
+            for module in &self.modules {
+                let (to, exists) = item(module.id);
+                check(to, to, exists);
+            }
+        }
+        errors
+    }
+
+    /// Checks that every module that is listed as supported by a recipe,
+    /// machine, or beacon, is actually a module.
+    pub fn verify_module_support(&self) -> Vec<ValidationError> {
+        let supported_modules = self
+            .recipes
+            .iter()
+            .flat_map(|recipe| {
+                let from = ID::from(recipe.id);
+                recipe.supported_modules.iter().map(move |&id| (from, id))
+            })
+            .chain(self.machines.iter().flat_map(|machine| {
+                let from = ID::from(machine.id);
+                machine.supported_modules.iter().map(move |&id| (from, id))
+            }))
+            .chain(self.beacons.iter().flat_map(|beacon| {
+                let from = ID::from(beacon.id);
+                beacon.supported_modules.iter().map(move |&id| (from, id))
+            }));
+
+        supported_modules
+            .filter(|(_, module)| !self.modules.contains(module))
+            .map(|(from, module)| ValidationError::NotAModule { from, module })
+            .collect()
+    }
+
+    /// Performs every check, being `validate`, `resolve_all`, and `verify_module_support`.
+    pub fn check_all(&self) -> Vec<ValidationError> {
+        self.check_all_with_warnings().0
+    }
+
+    /// Like `check_all`, but also returns the warnings encountered.
+    pub fn check_all_with_warnings(&self) -> (Vec<ValidationError>, Vec<ValidationWarning>) {
+        let (mut errors, warnings) = self.validate_with_warnings();
+        errors.extend(self.resolve_all());
+        errors.extend(self.verify_module_support());
+        (errors, warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::{Icon, Str};
+
+    #[test]
+    fn fixture_passes_every_check() {
+        let (errors, warnings) = test_support::game_data().check_all_with_warnings();
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn errors_of_every_check_are_combined() {
+        let mut game_data = test_support::game_data();
+        let mut gear = game_data.recipe_by_str("iron-gear-wheel").unwrap().clone();
+        gear.time = Zero::zero();
+        gear.crafted_in.insert(MachineID(Str::new("furnace")));
+        gear.supported_modules.insert(ItemID(Str::new("iron-plate")));
+        gear.metadata.icon = Some(Icon::new(0));
+        game_data.recipes.replace(gear);
+
+        let gear = ID::Recipe(game_data.recipe_by_str("iron-gear-wheel").unwrap().id);
+        let plate = ItemID(Str::new("iron-plate"));
+        let (errors, warnings) = game_data.check_all_with_warnings();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::NonPositiveRecipeTime { recipe: gear },
+                ValidationError::IconOutOfRange { id: gear },
+                ValidationError::UnresolvedReference {
+                    from: gear,
+                    to: MachineID(Str::new("furnace")).into(),
+                },
+                ValidationError::NotAModule { from: gear, module: plate },
+            ]
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(game_data.check_all(), errors);
+    }
+}
@@ -0,0 +1,96 @@
+//! Tests of the global interner, which are kept apart from the other tests,
+//! as those intern strings concurrently, and would change its size.
+
+extern crate graphio_rs_data;
+#[macro_use]
+extern crate lazy_static;
+extern crate serde_json;
+
+use graphio_rs_data::{interner_len, parse_prototypes, GameData, Str, TransformOptions};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+lazy_static! {
+    /// Held by every test while it measures the interner, as the tests in
+    /// this file intern strings of their own as well.
+    static ref MEASURING: Mutex<()> = Mutex::new(());
+}
+
+fn measure() -> MutexGuard<'static, ()> {
+    MEASURING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn game_data() -> GameData {
+    let prototypes = serde_json::from_str(include_str!("fixtures/prototypes.json")).unwrap();
+    parse_prototypes(prototypes, TransformOptions::default()).unwrap()
+}
+
+#[test]
+fn failed_lookups_dont_grow_the_interner() {
+    let _measuring = measure();
+    let existing = Str::new("iron-plate");
+    let len = interner_len();
+    let byte_size = Str::interner_byte_size();
+
+    for index in 0..1000 {
+        assert_eq!(Str::get_existing(&format!("missing-{}", index)), None);
+    }
+    assert_eq!(Str::get_existing("iron-plate"), Some(existing));
+    assert_eq!(interner_len(), len);
+    assert_eq!(Str::interner_byte_size(), byte_size);
+
+    // Interning one of them does add it.
+    let interned = Str::new("missing-0");
+    assert_eq!(interner_len(), len + 1);
+    assert_eq!(Str::get_existing("missing-0"), Some(interned));
+}
+
+#[test]
+fn lookups_by_str_dont_intern_the_string() {
+    let game_data = game_data();
+    let _measuring = measure();
+    let len = interner_len();
+
+    assert_eq!(game_data.item_by_str("iron-plate").unwrap().id.str(), "iron-plate");
+    assert_eq!(game_data.fluid_by_str("water").unwrap().id.str(), "water");
+    assert_eq!(game_data.recipe_by_str("iron-gear-wheel").unwrap().id.str(), "iron-gear-wheel");
+    assert_eq!(game_data.machine_by_str("chemical-plant").unwrap().id.str(), "chemical-plant");
+    assert_eq!(game_data.beacon_by_str("beacon").unwrap().id.str(), "beacon");
+
+    assert!(game_data.item_by_str("lookup-missing-item").is_none());
+    assert!(game_data.fluid_by_str("lookup-missing-fluid").is_none());
+    assert!(game_data.recipe_by_str("lookup-missing-recipe").is_none());
+    assert!(game_data.machine_by_str("lookup-missing-machine").is_none());
+    assert!(game_data.beacon_by_str("lookup-missing-beacon").is_none());
+    // A string that's interned, but names an object of a different kind.
+    assert!(game_data.fluid_by_str("iron-plate").is_none());
+
+    assert_eq!(interner_len(), len);
+    assert_eq!(Str::get_existing("lookup-missing-item"), None);
+}
+
+#[test]
+fn membership_by_str_doesnt_intern_the_string() {
+    let game_data = game_data();
+    let gear = game_data.recipe_by_str("iron-gear-wheel").unwrap();
+    let assembler = game_data.machine_by_str("assembling-machine-2").unwrap();
+    let beacon = game_data.beacon_by_str("beacon").unwrap();
+    let _measuring = measure();
+    let len = interner_len();
+
+    assert!(gear.crafted_in_contains_str("assembling-machine-2"));
+    assert!(gear.supports_module_str("speed-module"));
+    assert!(assembler.supports_module_str("speed-module"));
+    assert!(beacon.supports_module_str("speed-module"));
+
+    assert!(!gear.crafted_in_contains_str("membership-missing-machine"));
+    assert!(!gear.supports_module_str("membership-missing-module"));
+    assert!(!assembler.supports_module_str("membership-missing-module"));
+    assert!(!beacon.supports_module_str("membership-missing-module"));
+    // Strings that are interned, but aren't part of the sets.
+    assert!(!gear.crafted_in_contains_str("chemical-plant"));
+    assert!(!beacon.supports_module_str("iron-plate"));
+
+    assert_eq!(interner_len(), len);
+    assert_eq!(Str::get_existing("membership-missing-machine"), None);
+    assert_eq!(Str::get_existing("membership-missing-module"), None);
+}
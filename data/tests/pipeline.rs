@@ -0,0 +1,331 @@
+extern crate graphio_rs_data;
+extern crate serde_json;
+
+use graphio_rs_data::{
+    parse_prototypes, GameData, IngredientResource, Int, ItemID, ParseError, ProductAmount, ProductResource,
+    Ratio, Str, TransformOptions,
+};
+
+/// The records of a small export, in the shape of `prototypes.json`, with one
+/// of each kind of object, fluid ingredients and products, and probability
+/// products.
+const PROTOTYPES: &str = include_str!("fixtures/prototypes.json");
+
+/// An export whose only machine has a crafting speed that isn't a number.
+const MALFORMED_PROTOTYPES: &str = include_str!("fixtures/malformed_prototypes.json");
+
+fn read_fixture(fixture: &str) -> Vec<String> {
+    serde_json::from_str(fixture).unwrap()
+}
+
+fn ratio(numerator: i64, denominator: i64) -> Ratio {
+    Ratio::new(Int::from(numerator), Int::from(denominator))
+}
+
+fn transform(fixture: &str) -> GameData {
+    parse_prototypes(read_fixture(fixture), TransformOptions::default()).unwrap()
+}
+
+#[test]
+fn fixture_parses_into_every_collection() {
+    let game_data = transform(PROTOTYPES);
+    assert_eq!(game_data.machines.len(), 3);
+    assert_eq!(game_data.beacons.len(), 1);
+    assert_eq!(game_data.recipes.len(), 5);
+    assert_eq!(game_data.items.len(), 11);
+    assert_eq!(game_data.fluids.len(), 2);
+    assert_eq!(game_data.modules.len(), 2);
+}
+
+#[test]
+fn fixture_machines_and_beacons() {
+    let game_data = transform(PROTOTYPES);
+
+    let machine = game_data.machine_by_str("assembling-machine-2").unwrap();
+    assert_eq!(machine.metadata.localised_name.str(), "Assembling machine 2");
+    // Untranslated descriptions are dropped by default.
+    assert_eq!(machine.metadata.localised_description, None);
+    assert_eq!(machine.crafting_speed, ratio(3, 4));
+    assert_eq!(machine.energy_consumption, ratio(150_000, 1));
+    assert_eq!(machine.module_slots, Int::from(2));
+    assert_eq!(machine.crafting_categories.len(), 3);
+    assert!(machine.crafting_categories.contains(&Str::new("advanced-crafting")));
+    assert_eq!(machine.supported_modules.len(), 2);
+    assert_eq!(machine.metadata.order.map(|order| order.str()), Some("b[assembling-machine-2]"));
+
+    // Beacons don't allow productivity, so only the speed module remains.
+    let beacon = game_data.beacon_by_str("beacon").unwrap();
+    assert_eq!(beacon.distribution_effectivity, ratio(1, 2));
+    assert_eq!(beacon.supported_modules.len(), 1);
+    assert!(beacon.supports_module_str("speed-module"));
+}
+
+#[test]
+fn fixture_recipes() {
+    let game_data = transform(PROTOTYPES);
+
+    let circuit = game_data.recipe_by_str("electronic-circuit").unwrap();
+    let ingredients = circuit
+        .ingredients
+        .iter()
+        .map(|ingredient| (ingredient.resource.id().str(), ingredient.amount.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ingredients,
+        vec![("iron-plate", ratio(1, 1)), ("copper-cable", ratio(3, 1))]
+    );
+    assert_eq!(circuit.crafted_in.len(), 2);
+    assert!(circuit.crafted_in_contains_str("assembling-machine-1"));
+    // Limited to intermediate products, which the circuit is.
+    assert!(circuit.supports_module_str("productivity-module"));
+
+    let acid = game_data.recipe_by_str("sulfuric-acid").unwrap();
+    assert_eq!(acid.time, ratio(1, 1));
+    assert_eq!(acid.category.map(|category| category.str()), Some("chemistry"));
+    match &acid.ingredients[2].resource {
+        IngredientResource::Fluid {
+            id,
+            minimum_temperature,
+            maximum_temperature,
+        } => {
+            assert_eq!(id.str(), "water");
+            assert_eq!(*minimum_temperature, None);
+            assert_eq!(*maximum_temperature, None);
+        }
+        resource => panic!("expected water, got {:?}", resource),
+    }
+    let product = &acid.products[0];
+    match &product.resource {
+        ProductResource::Fluid { id, temperature } => {
+            assert_eq!(id.str(), "sulfuric-acid");
+            assert_eq!(*temperature, ratio(25, 1));
+        }
+        resource => panic!("expected sulfuric acid, got {:?}", resource),
+    }
+    assert_eq!(acid.main_product.as_ref(), Some(&product.resource));
+    assert!(!acid.supports_module_str("productivity-module"));
+    assert!(acid.supports_module_str("speed-module"));
+
+    let uranium = game_data.recipe_by_str("uranium-processing").unwrap();
+    assert!(uranium.crafted_in.is_empty());
+    match &uranium.products[0].amount {
+        ProductAmount::Probability { probability, .. } => assert_eq!(*probability, ratio(7, 1000)),
+        amount => panic!("expected a probability, got {:?}", amount),
+    }
+}
+
+#[test]
+fn fixture_items_and_fluids() {
+    let game_data = transform(PROTOTYPES);
+
+    let plate = game_data.item_by_str("iron-plate").unwrap();
+    assert_eq!(plate.metadata.localised_name.str(), "Iron plate");
+    assert!(!plate.metadata.untranslated_name);
+    assert_eq!(plate.metadata.subgroup.map(|subgroup| subgroup.str()), Some("raw-material"));
+
+    let module = game_data
+        .modules
+        .iter()
+        .find(|module| module.id == ItemID(Str::new("productivity-module")))
+        .unwrap();
+    assert_eq!(module.modifier_speed, ratio(-1, 20));
+    assert_eq!(module.modifier_productivity, ratio(1, 25));
+
+    let water = game_data.fluid_by_str("water").unwrap();
+    assert_eq!(water.metadata.localised_name.str(), "Water");
+}
+
+fn transform_at_most(fixture: &str, max_records: usize) -> GameData {
+    let options = TransformOptions {
+        max_records: Some(max_records),
+        ..TransformOptions::default()
+    };
+    parse_prototypes(read_fixture(fixture), options).unwrap()
+}
+
+#[test]
+fn limiting_to_one_record_keeps_the_first_of_each_kind() {
+    let game_data = transform_at_most(PROTOTYPES, 1);
+    assert_eq!(game_data.items.len(), 1);
+    let plate = game_data.item_by_str("iron-plate").unwrap();
+    assert_eq!(plate.metadata.localised_name.str(), "Iron plate");
+    assert_eq!(plate.metadata.subgroup.map(|subgroup| subgroup.str()), Some("raw-material"));
+    assert!(game_data.modules.is_empty());
+
+    // The sections after the skipped records are still read from the
+    // right lines.
+    assert_eq!(game_data.fluids.len(), 1);
+    assert_eq!(game_data.fluid_by_str("water").unwrap().metadata.localised_name.str(), "Water");
+    assert_eq!(game_data.machines.len(), 1);
+    let machine = game_data.machine_by_str("assembling-machine-1").unwrap();
+    assert_eq!(machine.crafting_speed, ratio(1, 2));
+    assert!(machine.supported_modules.is_empty());
+    assert_eq!(game_data.beacons.len(), 1);
+
+    // The only recipe that was read produces gears, which were skipped.
+    assert!(game_data.recipes.is_empty());
+    assert!(game_data.resolve_all().is_empty());
+}
+
+#[test]
+fn limiting_records_keeps_recipes_that_only_refer_to_kept_objects() {
+    let game_data = transform_at_most(PROTOTYPES, 3);
+    let names = |mut names: Vec<&'static str>| {
+        names.sort();
+        names
+    };
+    assert_eq!(
+        names(game_data.items.iter().map(|item| item.id.str()).collect()),
+        vec!["copper-cable", "copper-plate", "iron-plate"]
+    );
+    assert_eq!(game_data.fluids.len(), 2);
+    assert_eq!(game_data.machines.len(), 3);
+
+    // Of the first three recipes, only the cable has all of its ingredients
+    // and products.
+    assert_eq!(game_data.recipes.len(), 1);
+    let cable = game_data.recipe_by_str("copper-cable").unwrap();
+    assert_eq!(cable.crafted_in.len(), 2);
+    assert!(cable.supported_modules.is_empty());
+    assert!(game_data.resolve_all().is_empty());
+}
+
+#[test]
+fn malformed_fixture_fails_to_parse() {
+    let error = parse_prototypes(read_fixture(MALFORMED_PROTOTYPES), TransformOptions::default()).unwrap_err();
+    assert_eq!(error, ParseError::Message("unexpected non-digit in string to ratio"));
+}
+
+/// Lines written out by hand, with `|` separating the parts of a line: an
+/// assembler, a gear recipe, and a speed module limited to it.
+fn hand_written_export() -> GameData {
+    let lines = [
+        "1|0|1|3|0",
+        // Machines
+        "1|allowed_modules|crafting_categories|group",
+        "assembler",
+        "entity-name.assembler|Assembler",
+        "entity-description.assembler|Makes gears",
+        "1.25",
+        "75000",
+        "2500",
+        "2",
+        "1100",
+        "",
+        "2|crafting|advanced-crafting",
+        "production",
+        // Beacons
+        "1",
+        // Recipes
+        "1|emissions_multiplier|main_product|category",
+        "gear",
+        "recipe-name.gear|Gear",
+        "recipe-description.gear|Unknown key: \"recipe-description.gear\"",
+        "0.5",
+        "1",
+        "item",
+        "plate",
+        "2",
+        "0",
+        "1",
+        "item",
+        "gear",
+        "fixed",
+        "1",
+        "0",
+        "1",
+        "assembler",
+        "1.5",
+        "item|gear",
+        "crafting",
+        // Items
+        "1",
+        "plate",
+        "item-name.plate|Plate",
+        "item-description.plate|Unknown key: \"item-description.plate\"",
+        "0",
+        "gear",
+        "item-name.gear|Gear",
+        "item-description.gear|Unknown key: \"item-description.gear\"",
+        "0",
+        "speed-module",
+        "item-name.speed-module|Speed module",
+        "item-description.speed-module|Faster",
+        "1",
+        "0.5",
+        "0.2",
+        "0",
+        "0",
+        "1",
+        "1",
+        "gear",
+        // Fluids
+        "1",
+    ];
+    let lines = lines.iter().map(|line| line.replace('|', "\x1f")).collect();
+    parse_prototypes(lines, TransformOptions::default()).unwrap()
+}
+
+#[test]
+fn hand_written_machine() {
+    let game_data = hand_written_export();
+    let assembler = game_data.machine_by_str("assembler").unwrap();
+    assert_eq!(assembler.metadata.localised_name.str(), "Assembler");
+    assert_eq!(assembler.metadata.localised_description.map(|s| s.str()), Some("Makes gears"));
+    assert_eq!(assembler.metadata.group.map(|group| group.str()), Some("production"));
+    assert_eq!(assembler.metadata.subgroup, None);
+    assert_eq!(assembler.crafting_speed, ratio(5, 4));
+    assert_eq!(assembler.energy_consumption, ratio(75_000, 1));
+    assert_eq!(assembler.energy_drain, ratio(2_500, 1));
+    assert_eq!(assembler.module_slots, Int::from(2));
+    let mut categories = assembler.crafting_categories.iter().map(|s| s.str()).collect::<Vec<_>>();
+    categories.sort();
+    assert_eq!(categories, vec!["advanced-crafting", "crafting"]);
+    assert_eq!(assembler.supported_modules.len(), 1);
+    assert!(assembler.supports_module_str("speed-module"));
+}
+
+#[test]
+fn hand_written_recipe() {
+    let game_data = hand_written_export();
+    let gear = game_data.recipe_by_str("gear").unwrap();
+    assert_eq!(gear.metadata.localised_name.str(), "Gear");
+    // Untranslated descriptions are dropped by default.
+    assert_eq!(gear.metadata.localised_description, None);
+    assert_eq!(gear.time, ratio(1, 2));
+    assert_eq!(gear.ingredients.len(), 1);
+    assert_eq!(gear.ingredients[0].resource, IngredientResource::Item { id: ItemID(Str::new("plate")) });
+    assert_eq!(gear.ingredients[0].amount, ratio(2, 1));
+    assert_eq!(gear.products.len(), 1);
+    let gear_item = ProductResource::Item { id: ItemID(Str::new("gear")) };
+    assert_eq!(gear.products[0].resource, gear_item);
+    assert_eq!(
+        gear.products[0].amount,
+        ProductAmount::Fixed {
+            amount: ratio(1, 1),
+            catalyst_amount: ratio(0, 1),
+        }
+    );
+    assert_eq!(gear.crafted_in.len(), 1);
+    assert!(gear.crafted_in_contains_str("assembler"));
+    assert_eq!(gear.emissions_multiplier, Some(ratio(3, 2)));
+    assert_eq!(gear.main_product, Some(gear_item));
+    assert_eq!(gear.category.map(|category| category.str()), Some("crafting"));
+    assert!(gear.supports_module_str("speed-module"));
+}
+
+#[test]
+fn hand_written_module() {
+    let game_data = hand_written_export();
+    assert_eq!(game_data.items.len(), 3);
+    let item = game_data.item_by_str("speed-module").unwrap();
+    assert_eq!(item.metadata.localised_description.map(|s| s.str()), Some("Faster"));
+
+    assert_eq!(game_data.modules.len(), 1);
+    let module = game_data.modules.iter().next().unwrap();
+    assert_eq!(module.id, item.id);
+    assert_eq!(module.modifier_energy, ratio(1, 2));
+    assert_eq!(module.modifier_speed, ratio(1, 5));
+    assert_eq!(module.modifier_productivity, ratio(0, 1));
+    assert_eq!(module.modifier_pollution, ratio(0, 1));
+}
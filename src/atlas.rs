@@ -0,0 +1,353 @@
+use graphio_rs_data::{GameData, Icon, Metadata, Reporter, TileMetadata};
+use image::RgbaImage;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+/// How the icons are laid out in the tile set.
+pub struct AtlasLayout {
+    pub tile_size: (u32, u32),
+    /// The amount of pixels on each side of a tile, separating it from the others.
+    pub padding: u32,
+    /// Whether the padding is filled with the edge pixels of the tile.
+    pub bleed: bool,
+}
+
+/// Packs RGBA tiles of `layout.tile_size` into a tile set, in the order in
+/// which they're given, so that the index of a tile is the index of its
+/// `Icon`. There are as many columns as are needed to make the tile set
+/// roughly square.
+///
+/// The tiles are consumed as they're copied into the tile set, so that they
+/// don't take up memory along with the entire tile set.
+pub fn pack_tiles(tiles: Vec<Vec<u8>>, layout: &AtlasLayout) -> (TileMetadata, Vec<u8>) {
+    assert!(!tiles.is_empty());
+    let columns = ((tiles.len() as f64).sqrt().ceil()) as u32;
+    let rows = (tiles.len() as u32 + columns - 1) / columns;
+
+    let (tile_width, tile_height) = layout.tile_size;
+    let padding = layout.padding;
+    let (stride_x, stride_y) = (tile_width + 2 * padding, tile_height + 2 * padding);
+    let target_width = columns * stride_x;
+    let target_height = rows * stride_y;
+    let tile_count = tiles.len() as u32;
+    let mut tileset = Vec::new();
+    tileset.resize((4 * target_width * target_height) as usize, 0);
+
+    for (index, tile) in tiles.into_iter().enumerate() {
+        let index = index as u32;
+        let bx = (index % columns) * stride_x;
+        let by = (index / columns) * stride_y;
+        for y in 0..stride_y {
+            for x in 0..stride_x {
+                let is_padding = x < padding
+                    || y < padding
+                    || x >= padding + tile_width
+                    || y >= padding + tile_height;
+                if is_padding && !layout.bleed {
+                    continue;
+                }
+                // Pixels in the padding take the color of the closest edge pixel
+                let sx = ::std::cmp::min(x.saturating_sub(padding), tile_width - 1);
+                let sy = ::std::cmp::min(y.saturating_sub(padding), tile_height - 1);
+                for b in 0..4 {
+                    let src = tile[((sy * tile_width + sx) * 4 + b) as usize];
+                    tileset[(((y + by) * target_width + x + bx) * 4 + b) as usize] = src;
+                }
+            }
+        }
+    }
+
+    let tile_metadata = TileMetadata {
+        tile_size: layout.tile_size,
+        tile_count,
+        image_size: (target_width, target_height),
+        padding,
+    };
+    (tile_metadata, tileset)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Copies the tiles that the icons of `game_data` refer to out of `atlas`,
+/// adding the ones that aren't in `tiles` yet, and points the icons at the
+/// index of their tile in `tiles` instead. The tile metadata is removed, as
+/// it doesn't describe the icons anymore.
+fn collect_tiles(
+    game_data: &mut GameData,
+    atlas: &RgbaImage,
+    tile_size: (u32, u32),
+    tiles: &mut HashMap<Vec<u8>, usize>,
+) -> io::Result<()> {
+    let tile_metadata = match game_data.tile_metadata.take() {
+        Some(tile_metadata) => tile_metadata,
+        None if game_data.all_ids().all(|id| game_data.metadata_for(id).unwrap().icon.is_none()) => {
+            return Ok(());
+        }
+        None => return Err(invalid_data("game data has icons, but no tile metadata".to_owned())),
+    };
+    if tile_metadata.tile_size != tile_size {
+        return Err(invalid_data(format!(
+            "expected tiles to be {}x{}, but they're {}x{}",
+            tile_size.0, tile_size.1, tile_metadata.tile_size.0, tile_metadata.tile_size.1
+        )));
+    }
+    if atlas.dimensions() != tile_metadata.image_size {
+        return Err(invalid_data(format!(
+            "expected the tile set to be {}x{}, as described by its tile metadata",
+            tile_metadata.image_size.0, tile_metadata.image_size.1
+        )));
+    }
+
+    let (tile_width, tile_height) = tile_size;
+    let atlas_width = atlas.width();
+    let pixels: &[u8] = atlas;
+    let mut copy_tile = |icon: Icon| {
+        if icon.index() >= tile_metadata.tile_count as usize {
+            return Err(invalid_data(format!("icon {} lies outside of the tile set", icon.index())));
+        }
+        let (x, y) = icon.position(&tile_metadata);
+        let mut tile = Vec::with_capacity((4 * tile_width * tile_height) as usize);
+        for row in y..y + tile_height {
+            let start = (4 * (row * atlas_width + x)) as usize;
+            tile.extend_from_slice(&pixels[start..start + 4 * tile_width as usize]);
+        }
+        let tile_count = tiles.len();
+        Ok(*tiles.entry(tile).or_insert(tile_count))
+    };
+
+    // The tiles are collected up front, as `modify_metadata` can't mutate
+    // what it captures.
+    let mut indices = HashMap::new();
+    for id in game_data.all_ids() {
+        if let Some(icon) = game_data.metadata_for(id).unwrap().icon {
+            indices.insert(id, copy_tile(icon)?);
+        }
+    }
+    game_data.modify_metadata::<(), _>(|id, metadata| {
+        let icon = indices.get(&id).map(|&index| Icon::new(index));
+        Ok(Metadata { icon, ..*metadata })
+    })
+    .unwrap();
+    Ok(())
+}
+
+/// Merges the game data of `others` into that of `base`, as described by
+/// `GameData::merge`, and repacks the icons of all of them into a single tile
+/// set, laid out like the one of `base`. Tiles that are identical are stored
+/// once, regardless of which data set they're from, and tiles that only the
+/// dropped objects used are left out.
+///
+/// The tile sets all have to consist of tiles of the same size.
+pub fn merge_with_atlases(
+    base: (GameData, RgbaImage),
+    others: Vec<(GameData, RgbaImage)>,
+    reporter: &mut dyn Reporter,
+) -> io::Result<(GameData, RgbaImage)> {
+    let layout = match &base.0.tile_metadata {
+        Some(tile_metadata) => AtlasLayout {
+            tile_size: tile_metadata.tile_size,
+            padding: tile_metadata.padding,
+            bleed: false,
+        },
+        None => return Err(invalid_data("the base game data has no tile metadata".to_owned())),
+    };
+
+    let mut tiles = HashMap::new();
+    let mut data_sets = Vec::with_capacity(others.len() + 1);
+    for (mut game_data, atlas) in ::std::iter::once(base).chain(others) {
+        collect_tiles(&mut game_data, &atlas, layout.tile_size, &mut tiles)?;
+        data_sets.push(game_data);
+    }
+    let mut data_sets = data_sets.into_iter();
+    let mut merged = data_sets.next().unwrap();
+    let mut dropped = 0;
+    for game_data in data_sets {
+        dropped += merged.merge(game_data).len();
+    }
+    reporter.info(&format!(
+        "merged the game data, dropping {} object(s) that were already present",
+        dropped
+    ));
+
+    // Renumbers the tiles that are still used, in the order in which they
+    // were collected.
+    let used = merged
+        .all_ids()
+        .filter_map(|id| merged.metadata_for(id).unwrap().icon)
+        .map(|icon| icon.index())
+        .collect::<BTreeSet<_>>();
+    if used.is_empty() {
+        return Err(invalid_data("none of the game data has icons".to_owned()));
+    }
+    let renumbered = used
+        .iter()
+        .enumerate()
+        .map(|(index, &previous)| (previous, index))
+        .collect::<HashMap<_, _>>();
+    let mut packed = vec![Vec::new(); used.len()];
+    for (tile, index) in tiles {
+        if let Some(&index) = renumbered.get(&index) {
+            packed[index] = tile;
+        }
+    }
+    merged
+        .modify_metadata::<(), _>(|_, metadata| {
+            let icon = metadata.icon.map(|icon| Icon::new(renumbered[&icon.index()]));
+            Ok(Metadata { icon, ..*metadata })
+        })
+        .unwrap();
+
+    let (tile_metadata, pixels) = pack_tiles(packed, &layout);
+    let (width, height) = tile_metadata.image_size;
+    merged.tile_metadata = Some(tile_metadata);
+    reporter.info(&format!("packed {} distinct icon(s) into the merged tile set", used.len()));
+    Ok((merged, RgbaImage::from_raw(width, height, pixels).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, distinct_icon_count, icon_color, RecordingReporter};
+    use std::collections::HashMap;
+
+    const TILE_SIZE: (u32, u32) = (4, 4);
+
+    /// Gives every object of `game_data` a solid tile in the color given by
+    /// `icon_color`, sharing the tiles between objects with the same name,
+    /// and packs them into a tile set with `padding`.
+    fn with_atlas(mut game_data: GameData, padding: u32) -> (GameData, RgbaImage) {
+        let mut names = game_data.all_ids().map(|id| id.str()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let indices = names
+            .iter()
+            .enumerate()
+            .map(|(index, &name)| (name, index))
+            .collect::<HashMap<_, _>>();
+        game_data
+            .modify_metadata::<(), _>(|id, metadata| {
+                let icon = Some(Icon::new(indices[id.str()]));
+                Ok(Metadata { icon, ..metadata.clone() })
+            })
+            .unwrap();
+
+        let tiles = names
+            .iter()
+            .map(|name| RgbaImage::from_pixel(TILE_SIZE.0, TILE_SIZE.1, image::Rgba(icon_color(name))).into_raw())
+            .collect();
+        let layout = AtlasLayout {
+            tile_size: TILE_SIZE,
+            padding,
+            bleed: false,
+        };
+        let (tile_metadata, pixels) = pack_tiles(tiles, &layout);
+        let (width, height) = tile_metadata.image_size;
+        game_data.tile_metadata = Some(tile_metadata);
+        (game_data, RgbaImage::from_raw(width, height, pixels).unwrap())
+    }
+
+    /// Packs 5 solid tiles, colored after their index, with a padding of 2.
+    fn padded_tiles(bleed: bool) -> (TileMetadata, RgbaImage) {
+        let tiles = (0..5)
+            .map(|index| RgbaImage::from_pixel(TILE_SIZE.0, TILE_SIZE.1, image::Rgba(icon_color(&index.to_string()))).into_raw())
+            .collect();
+        let layout = AtlasLayout {
+            tile_size: TILE_SIZE,
+            padding: 2,
+            bleed,
+        };
+        let (tile_metadata, pixels) = pack_tiles(tiles, &layout);
+        let (width, height) = tile_metadata.image_size;
+        (tile_metadata, RgbaImage::from_raw(width, height, pixels).unwrap())
+    }
+
+    #[test]
+    fn padded_tiles_are_separated_by_a_gutter() {
+        let (tile_metadata, atlas) = padded_tiles(false);
+        // 3 columns and 2 rows of 8x8 pixels, being the tile and 2 pixels on
+        // each side.
+        assert_eq!(tile_metadata.image_size, (24, 16));
+        assert_eq!(tile_metadata.padding, 2);
+
+        let mut content = 0;
+        for index in 0..5 {
+            let (x, y) = Icon::new(index).position(&tile_metadata);
+            assert_eq!((x, y), ((index as u32 % 3) * 8 + 2, (index as u32 / 3) * 8 + 2));
+            for dy in 0..TILE_SIZE.1 {
+                for dx in 0..TILE_SIZE.0 {
+                    assert_eq!(atlas.get_pixel(x + dx, y + dy).data, icon_color(&index.to_string()));
+                }
+            }
+            content += TILE_SIZE.0 * TILE_SIZE.1;
+        }
+        // Everything else, including the unused sixth tile, is transparent.
+        let transparent = atlas.pixels().filter(|pixel| pixel.data[3] == 0).count() as u32;
+        assert_eq!(transparent, 24 * 16 - content);
+    }
+
+    #[test]
+    fn bleeding_fills_the_gutter_with_the_edge_pixels() {
+        let (tile_metadata, atlas) = padded_tiles(true);
+        for index in 0..5 {
+            let (x, y) = Icon::new(index).position(&tile_metadata);
+            let color = icon_color(&index.to_string());
+            for dy in 0..TILE_SIZE.1 + 4 {
+                for dx in 0..TILE_SIZE.0 + 4 {
+                    assert_eq!(atlas.get_pixel(x + dx - 2, y + dy - 2).data, color);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merged_objects_resolve_to_their_own_tiles() {
+        let mut base = test_support::game_data();
+        base.remove_hidden(|id, _| id.str().starts_with("uranium"));
+        let other = test_support::game_data();
+        let expected_count = distinct_icon_count(&other);
+        let expected_ids = other.all_ids().collect::<BTreeSet<_>>();
+
+        let mut reporter = RecordingReporter::default();
+        let (merged, atlas) =
+            merge_with_atlases(with_atlas(base, 0), vec![with_atlas(other, 1)], &mut reporter).unwrap();
+
+        assert_eq!(merged.all_ids().collect::<BTreeSet<_>>(), expected_ids);
+        assert_eq!(
+            reporter.infos.last().unwrap(),
+            &format!("packed {} distinct icon(s) into the merged tile set", expected_count)
+        );
+        let tile_metadata = merged.tile_metadata.clone().unwrap();
+        assert_eq!(tile_metadata.tile_count as usize, expected_count);
+        assert_eq!(tile_metadata.padding, 0);
+        assert_eq!(atlas.dimensions(), tile_metadata.image_size);
+        for id in merged.all_ids() {
+            let icon = merged.metadata_for(id).unwrap().icon.unwrap();
+            assert!(icon.index() < tile_metadata.tile_count as usize, "{} has no tile", id.str());
+            let (x, y) = icon.position(&tile_metadata);
+            for dy in 0..TILE_SIZE.1 {
+                for dx in 0..TILE_SIZE.0 {
+                    assert_eq!(atlas.get_pixel(x + dx, y + dy).data, icon_color(id.str()), "{}", id.str());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tile_sets_with_different_tile_sizes_are_rejected() {
+        let base = with_atlas(test_support::game_data(), 0);
+        let (mut other, _) = with_atlas(test_support::game_data(), 0);
+        let atlas = RgbaImage::new(8, 8);
+        other.tile_metadata = Some(TileMetadata {
+            tile_size: (8, 8),
+            tile_count: 1,
+            image_size: (8, 8),
+            padding: 0,
+        });
+
+        let error = merge_with_atlases(base, vec![(other, atlas)], &mut RecordingReporter::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "expected tiles to be 4x4, but they're 8x8");
+    }
+}
@@ -0,0 +1,145 @@
+use clap::ArgMatches;
+use graphio_rs_data::Reporter;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml::Value;
+
+/// Default values for the command line options, read from the file given
+/// with `--config`. It's a TOML file, mapping the long names of options to
+/// their values, such as:
+///
+/// ```toml
+/// atlas_padding = 2
+/// strict = true
+/// ```
+///
+/// The directory, and the other arguments that are required unless another
+/// is given, can't be read from the file, as the command line is checked
+/// before the file is read.
+pub struct Config {
+    options: toml::value::Table,
+}
+
+impl Config {
+    pub fn load(path: &Path, reporter: &mut dyn Reporter) -> io::Result<Config> {
+        reporter.info(&format!("loading options from: {}", path.to_string_lossy()));
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Config> {
+        let options = toml::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Config { options })
+    }
+
+    /// The command line arguments for the options in the file that weren't
+    /// given on the command line, so that the command line takes precedence.
+    /// Flags are set with `true`, options that can be repeated take an array,
+    /// and options set to `false` are left out.
+    pub fn arguments(&self, matches: &ArgMatches) -> io::Result<Vec<String>> {
+        let mut arguments = Vec::new();
+        for (name, value) in &self.options {
+            if name == "config" || matches.occurrences_of(name) > 0 {
+                continue;
+            }
+            let values = match value {
+                Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                match value {
+                    Value::Boolean(true) => arguments.push(format!("--{}", name)),
+                    Value::Boolean(false) => {}
+                    Value::String(s) => arguments.push(format!("--{}={}", name, s)),
+                    Value::Integer(n) => arguments.push(format!("--{}={}", name, n)),
+                    Value::Float(n) => arguments.push(format!("--{}={}", name, n)),
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("invalid value for option {} in the config file", name),
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_definition, apply_config};
+    use std::ffi::OsString;
+
+    fn command_line(args: &[&str]) -> Vec<OsString> {
+        ["graphio_rs_extractor", "factorio"]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect()
+    }
+
+    fn parse_with_config(args: &[&str], config: &str) -> io::Result<ArgMatches<'static>> {
+        let mut definition = app_definition();
+        let args = command_line(args);
+        let matches = definition.get_matches_from_safe_borrow(&args).unwrap();
+        apply_config(&mut definition, args, &matches, &Config::parse(config)?)
+    }
+
+    #[test]
+    fn command_line_overrides_config_values() {
+        let config = r#"
+            atlas_padding = 2
+            strict = true
+            max_records = 5
+            prune_keep = "iron-plate,copper-plate"
+            factorio_arg = ["--mod-directory", "mods"]
+            atlas_bleed = false
+        "#;
+        let matches = parse_with_config(&["--atlas_padding=4", "--factorio_arg=--verbose"], config).unwrap();
+        assert_eq!(matches.value_of("atlas_padding"), Some("4"));
+        assert_eq!(matches.values_of("factorio_arg").unwrap().collect::<Vec<_>>(), vec!["--verbose"]);
+        assert!(matches.is_present("strict"));
+        assert!(!matches.is_present("atlas_bleed"));
+        assert_eq!(matches.value_of("max_records"), Some("5"));
+        assert_eq!(matches.value_of("prune_keep"), Some("iron-plate,copper-plate"));
+        assert_eq!(matches.value_of_os("directory"), Some("factorio".as_ref()));
+
+        let matches = parse_with_config(&[], config).unwrap();
+        assert_eq!(matches.value_of("atlas_padding"), Some("2"));
+        assert_eq!(
+            matches.values_of("factorio_arg").unwrap().collect::<Vec<_>>(),
+            vec!["--mod-directory", "mods"]
+        );
+    }
+
+    #[test]
+    fn unknown_config_key_is_rejected() {
+        let error = parse_with_config(&[], "atlas_padding = 2\nnot_an_option = 1").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "unknown option in the config file: not_an_option");
+
+        let error = parse_with_config(&[], "not_a_flag = true").unwrap_err();
+        assert_eq!(error.to_string(), "unknown option in the config file: not_a_flag");
+    }
+
+    #[test]
+    fn invalid_config_files_are_rejected() {
+        let error = parse_with_config(&[], "atlas_padding = ").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = parse_with_config(&[], "[atlas_padding]\nvalue = 2").unwrap_err();
+        assert_eq!(error.to_string(), "invalid value for option atlas_padding in the config file");
+    }
+
+    #[test]
+    fn zero_max_records_is_rejected() {
+        let result = app_definition().get_matches_from_safe(command_line(&["--max_records", "0"]));
+        assert_eq!(result.unwrap_err().kind, clap::ErrorKind::ValueValidation);
+        let matches = app_definition()
+            .get_matches_from_safe(command_line(&["--max_records", "1"]))
+            .unwrap();
+        assert_eq!(matches.value_of("max_records"), Some("1"));
+    }
+}
@@ -0,0 +1,141 @@
+use graphio_rs_data::Reporter;
+use std::io;
+
+/// A file written by a stage, named relative to the script output directory.
+pub struct Output {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+/// Compares the outputs of two runs of the same stage. Reports a summary of
+/// how each output that differs does, and fails if any of them do.
+pub fn compare(first: &[Output], second: &[Output], reporter: &mut dyn Reporter) -> io::Result<()> {
+    if first.len() != second.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the runs wrote a different amount of files, {} and {}",
+                first.len(),
+                second.len()
+            ),
+        ));
+    }
+
+    let mut differences = 0;
+    for (first, second) in first.iter().zip(second) {
+        if first.name != second.name {
+            reporter.info(&format!("{}: stored as {} by the second run", first.name, second.name));
+            differences += 1;
+        } else if let Some(difference) = describe_difference(&first.contents, &second.contents) {
+            reporter.info(&format!("{}: {}", first.name, difference));
+            differences += 1;
+        } else {
+            reporter.info(&format!("{}: identical", first.name));
+        }
+    }
+
+    if differences == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} output(s) differ between runs", differences),
+        ))
+    }
+}
+
+/// Describes where two versions of a file start to differ, by line for
+/// text, and by byte otherwise. Returns `None` if they're identical.
+fn describe_difference(first: &[u8], second: &[u8]) -> Option<String> {
+    if first == second {
+        return None;
+    }
+    if let (Ok(first), Ok(second)) = (std::str::from_utf8(first), std::str::from_utf8(second)) {
+        let first_lines = first.lines().collect::<Vec<_>>();
+        let second_lines = second.lines().collect::<Vec<_>>();
+        let differing = first_lines
+            .iter()
+            .zip(&second_lines)
+            .filter(|(first, second)| first != second)
+            .count()
+            + (first_lines.len() as isize - second_lines.len() as isize).unsigned_abs();
+        let line = first_lines
+            .iter()
+            .zip(&second_lines)
+            .position(|(first, second)| first != second)
+            .unwrap_or_else(|| first_lines.len().min(second_lines.len()));
+        return Some(format!(
+            "{} line(s) differ, starting at line {}:\n    {}\n    {}",
+            differing,
+            line + 1,
+            first_lines.get(line).unwrap_or(&"(end of file)").trim(),
+            second_lines.get(line).unwrap_or(&"(end of file)").trim(),
+        ));
+    }
+    let offset = first
+        .iter()
+        .zip(second)
+        .position(|(first, second)| first != second)
+        .unwrap_or_else(|| first.len().min(second.len()));
+    Some(format!(
+        "{} and {} bytes, differing from byte {}",
+        first.len(),
+        second.len(),
+        offset
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, RecordingReporter};
+
+    fn output(name: &str, contents: &[u8]) -> Output {
+        Output {
+            name: name.to_owned(),
+            contents: contents.to_vec(),
+        }
+    }
+
+    fn transform() -> Vec<Output> {
+        let game_data = test_support::game_data();
+        vec![output(
+            "game_data.json",
+            serde_json::ser::to_string_pretty(&game_data).unwrap().as_bytes(),
+        )]
+    }
+
+    #[test]
+    fn transforms_of_the_same_prototypes_are_identical() {
+        let mut reporter = RecordingReporter::default();
+        compare(&transform(), &transform(), &mut reporter).unwrap();
+        assert_eq!(reporter.infos, vec!["game_data.json: identical"]);
+    }
+
+    #[test]
+    fn differing_outputs_are_errors() {
+        let mut changed = transform();
+        changed[0].contents = String::from_utf8(changed[0].contents.clone())
+            .unwrap()
+            .replace("Iron plate", "Steel plate")
+            .into_bytes();
+        assert!(compare(&transform(), &changed, &mut RecordingReporter::default()).is_err());
+
+        let renamed = vec![output("game_data_1.json", &transform()[0].contents)];
+        assert!(compare(&transform(), &renamed, &mut RecordingReporter::default()).is_err());
+        assert!(compare(&transform(), &[], &mut RecordingReporter::default()).is_err());
+    }
+
+    #[test]
+    fn difference_is_described_by_line_or_byte() {
+        assert_eq!(describe_difference(b"a\nb\nc", b"a\nb\nc"), None);
+        assert_eq!(
+            describe_difference(b"a\nb\nc", b"a\nx\nc\nd"),
+            Some("2 line(s) differ, starting at line 2:\n    b\n    x".to_owned())
+        );
+        assert_eq!(
+            describe_difference(&[0, 1, 0xff], &[0, 2, 0xff, 3]),
+            Some("3 and 4 bytes, differing from byte 1".to_owned())
+        );
+    }
+}
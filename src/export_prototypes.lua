@@ -1,4 +1,5 @@
 local prune_level = prune_level == nil and 1 or prune_level
+local prune_keep = prune_keep or {}
 
 script.on_init(function ()
 
@@ -259,22 +260,110 @@ local function prune_prototypes()
         out(string.format('pruned fluid %q', fluid_prototype.name))
         return false
     end)
+
+    -- Restore the prototypes that are kept regardless of pruning, along with
+    -- what's needed to make them, being the ingredients of kept recipes, and
+    -- the recipes producing kept items and fluids.
+    if next(prune_keep) == nil then return end
+    local producers = { item = {}, fluid = {} }
+    for _, recipe_prototype in pairs(game.recipe_prototypes) do
+        for _, product in ipairs(recipe_prototype.products) do
+            local recipes = producers[product.type][product.name] or {}
+            recipes[#recipes + 1] = recipe_prototype
+            producers[product.type][product.name] = recipes
+        end
+    end
+
+    local pending = {}
+    local function keep(type, name)
+        local prototypes, all_prototypes
+        if type == 'item' then
+            prototypes, all_prototypes = item_prototypes, game.item_prototypes
+        elseif type == 'fluid' then
+            prototypes, all_prototypes = fluid_prototypes, game.fluid_prototypes
+        else
+            prototypes, all_prototypes = recipe_prototypes, game.recipe_prototypes
+        end
+        if prototypes[name] or not all_prototypes[name] then return end
+        out(string.format('kept %s %q', type, name))
+        prototypes[name] = all_prototypes[name]
+        pending[#pending + 1] = { type, name }
+    end
+
+    for name in pairs(prune_keep) do
+        keep('item', name)
+        keep('fluid', name)
+        keep('recipe', name)
+        local entity_prototype = game.entity_prototypes[name]
+        if entity_prototype and entity_prototype.crafting_speed ~= nil then
+            out(string.format('kept crafting machine %q', name))
+            crafting_machine_prototypes[name] = entity_prototype
+        elseif entity_prototype and entity_prototype.distribution_effectivity ~= nil then
+            out(string.format('kept beacon %q', name))
+            beacon_prototypes[name] = entity_prototype
+        end
+    end
+    while #pending > 0 do
+        local type, name = table.unpack(table.remove(pending))
+        if type == 'recipe' then
+            local recipe_prototype = recipe_prototypes[name]
+            for _, ingredient in ipairs(recipe_prototype.ingredients) do
+                keep(ingredient.type, ingredient.name)
+            end
+            for _, product in ipairs(recipe_prototype.products) do
+                keep(product.type, product.name)
+            end
+        else
+            for _, recipe_prototype in ipairs(producers[type][name] or {}) do
+                keep('recipe', recipe_prototype.name)
+            end
+        end
+    end
 end
 
 prune_prototypes()
 
-local write_template_str = { '', '\x02', nil, '\x03' }
-local function write_str(entry)
+-- Delimiters occurring in the data are written as the escape character,
+-- followed by the delimiter offset by escape_offset, so they can't be
+-- mistaken for the framing of the records.
+local escape_pattern = '[' .. delimiters.start .. delimiters.record_start
+    .. delimiters.record_end .. delimiters.finish .. delimiters.escape
+    .. delimiters.separator .. ']'
+local function escape(s)
+    return (string.gsub(s, escape_pattern, function (c)
+        return delimiters.escape .. string.char(string.byte(c) + delimiters.escape_offset)
+    end))
+end
+local function to_str(entry, level)
     if type(entry) == 'number' then
         entry = tostring(entry)
     end
     if type(entry) ~= 'string' then
-        error('expected string, got something else', 2)
+        error('expected string, got something else', level + 1)
     end
+    return entry
+end
+-- Joins multiple parts into a single line, escaping each of them.
+local function join_parts(parts)
+    local escaped = {}
+    for i, part in ipairs(parts) do
+        escaped[i] = escape(to_str(part, 3))
+    end
+    return table.concat(escaped, delimiters.separator)
+end
+
+local write_template_str = { '', delimiters.record_start, nil, delimiters.record_end }
+local function write_raw(entry)
     write_template_str[3] = entry
     out(write_template_str)
 end
-local write_template_loc = { '', '\x02', nil, '\x1f', nil, '\x03' }
+local function write_str(entry)
+    write_raw(escape(to_str(entry, 2)))
+end
+-- The key is escaped, but the value is translated by the game as it's
+-- logged, so it's written as is. The parser treats everything after the
+-- first separator as the value.
+local write_template_loc = { '', delimiters.record_start, nil, delimiters.separator, nil, delimiters.record_end }
 local function write_loc(entry)
     if type(entry) ~= 'table' then
         error('expected table, got something else', 2)
@@ -282,7 +371,7 @@ local function write_loc(entry)
     if type(entry[1]) ~= 'string' then
         error('expected first entry to be a string', 2)
     end
-    write_template_loc[3] = entry[1]
+    write_template_loc[3] = escape(entry[1])
     write_template_loc[5] = entry
     out(write_template_loc)
 end
@@ -298,13 +387,111 @@ local function write_allowed_effects(allowed_effects)
     write_str(energy .. speed .. productivity .. pollution)
 end
 
+-- Every section starts with a header, containing the record format version,
+-- followed by the names of the optional fields. Each record in the section is
+-- then followed by exactly one line per optional field, in the same order.
+-- Optional fields are described by { name, function (prototype) ... end } pairs,
+-- where the function returns the value to write, or a table of its parts.
+local function write_section_header(optional_fields)
+    local header = { tostring(record_format_version) }
+    for _, field in ipairs(optional_fields) do
+        header[#header + 1] = field[1]
+    end
+    write_raw(join_parts(header))
+end
+local function write_optional_fields(optional_fields, prototype)
+    for _, field in ipairs(optional_fields) do
+        local value = field[2](prototype)
+        if type(value) == 'table' then
+            write_raw(join_parts(value))
+        else
+            write_str(value)
+        end
+    end
+end
+
+-- Reads a property that may not exist in every version of the game,
+-- returning an empty string when it's absent.
+local function try_read(prototype, property)
+    local ok, value = pcall(function () return prototype[property] end)
+    if not ok or value == nil then return '' end
+    return value
+end
+
+-- The group, subgroup, and order of a prototype, by which the game lays out
+-- its user interface. Every kind of prototype has them.
+local function read_group_name(prototype, property)
+    local group = try_read(prototype, property)
+    if group == '' then return '' end
+    return group.name
+end
+local ordering_fields = {
+    { 'group', function (prototype) return read_group_name(prototype, 'group') end },
+    { 'subgroup', function (prototype) return read_group_name(prototype, 'subgroup') end },
+    { 'order', function (prototype) return try_read(prototype, 'order') end },
+}
+local function with_ordering_fields(fields)
+    for _, field in ipairs(ordering_fields) do
+        fields[#fields + 1] = field
+    end
+    return fields
+end
+
+local machine_fields = with_ordering_fields({
+    -- Machines that restrict modules by category, rather than only by their
+    -- effects. Written as the amount of allowed modules, followed by their
+    -- names, so that an empty list can be told apart from an absent one.
+    { 'allowed_modules', function (crafting_machine_prototype)
+        local categories = try_read(crafting_machine_prototype, 'allowed_module_categories')
+        if categories == '' then return '' end
+        local allowed = { 0 }
+        for name, item_prototype in pairs(game.item_prototypes) do
+            if item_prototype.module_effects and categories[item_prototype.category] then
+                allowed[#allowed + 1] = name
+            end
+        end
+        allowed[1] = #allowed - 1
+        return allowed
+    end },
+    -- Written as the amount of categories, followed by their names.
+    { 'crafting_categories', function (crafting_machine_prototype)
+        local categories = { 0 }
+        for category in pairs(crafting_machine_prototype.crafting_categories) do
+            categories[#categories + 1] = category
+        end
+        categories[1] = #categories - 1
+        return categories
+    end },
+})
+local beacon_fields = with_ordering_fields({})
+local recipe_fields = with_ordering_fields({
+    { 'emissions_multiplier', function (recipe_prototype)
+        return try_read(recipe_prototype, 'emissions_multiplier')
+    end },
+    { 'main_product', function (recipe_prototype)
+        local product = try_read(recipe_prototype, 'main_product')
+        if product == '' then return '' end
+        if product.type == 'fluid' then
+            local temperature = product.temperature
+                or fluid_prototypes[product.name].default_temperature
+            return { 'fluid', product.name, temperature }
+        end
+        return { product.type, product.name }
+    end },
+    { 'category', function (recipe_prototype)
+        return try_read(recipe_prototype, 'category')
+    end },
+})
+local item_fields = with_ordering_fields({})
+local fluid_fields = with_ordering_fields({})
+
 out({ '',
-    '\x01\x02',
-    tostring(count(crafting_machine_prototypes)), '\x1f',
-    tostring(count(beacon_prototypes)), '\x1f',
-    tostring(count(recipe_prototypes)), '\x1f',
-    tostring(count(item_prototypes)), '\x1f',
-    tostring(count(fluid_prototypes)), '\x03'})
+    delimiters.start, delimiters.record_start,
+    tostring(count(crafting_machine_prototypes)), delimiters.separator,
+    tostring(count(beacon_prototypes)), delimiters.separator,
+    tostring(count(recipe_prototypes)), delimiters.separator,
+    tostring(count(item_prototypes)), delimiters.separator,
+    tostring(count(fluid_prototypes)), delimiters.record_end })
 
 local crafting_machine_categories = {}
 local function add_crafting_machine_category(crafting_machine_name, category)
@@ -322,6 +509,7 @@ local function add_crafting_machine_category(crafting_machine_name, category)
     table.sort(machines)
 end
 
+write_section_header(machine_fields)
 for _, crafting_machine_prototype in pairs(crafting_machine_prototypes) do
     write_str(crafting_machine_prototype.name)
     write_loc(crafting_machine_prototype.localised_name)
@@ -349,19 +537,24 @@ for _, crafting_machine_prototype in pairs(crafting_machine_prototypes) do
 
     write_allowed_effects(crafting_machine_prototype.allowed_effects)
 
+    write_optional_fields(machine_fields, crafting_machine_prototype)
+
     for category in pairs(crafting_machine_prototype.crafting_categories) do
         add_crafting_machine_category(crafting_machine_prototype.name, category)
     end
 end
 
+write_section_header(beacon_fields)
 for _, beacon_prototype in pairs(beacon_prototypes) do
     write_str(beacon_prototype.name)
     write_loc(beacon_prototype.localised_name)
     write_loc(beacon_prototype.localised_description)
     write_str(beacon_prototype.distribution_effectivity)
     write_allowed_effects(beacon_prototype.allowed_effects)
+    write_optional_fields(beacon_fields, beacon_prototype)
 end
 
+write_section_header(recipe_fields)
 for _, recipe_prototype in pairs(recipe_prototypes) do
     write_str(recipe_prototype.name)
     write_loc(recipe_prototype.localised_name)
@@ -448,8 +641,11 @@ for _, recipe_prototype in pairs(recipe_prototypes) do
     for _, machine in ipairs(filtered_machines) do
         write_str(machine)
     end
+
+    write_optional_fields(recipe_fields, recipe_prototype)
 end
 
+write_section_header(item_fields)
 for _, item_prototype in pairs(item_prototypes) do
     write_str(item_prototype.name)
     write_loc(item_prototype.localised_name)
@@ -479,15 +675,19 @@ for _, item_prototype in pairs(item_prototypes) do
             end
         end
     end
+
+    write_optional_fields(item_fields, item_prototype)
 end
 
+write_section_header(fluid_fields)
 for _, fluid_prototype in pairs(fluid_prototypes) do
     write_str(fluid_prototype.name)
     write_loc(fluid_prototype.localised_name)
     write_loc(fluid_prototype.localised_description)
+    write_optional_fields(fluid_fields, fluid_prototype)
 end
 
-out('\x04')
+out(delimiters.finish)
 
 error('done')
 
@@ -135,7 +135,7 @@ start_export = function ()
                 by_player = player,
                 surface = player.surface,
                 position = { 0, 0 },
-                resolution = { 32, 32 },
+                resolution = { 32 * icon_scale, 32 * icon_scale },
                 zoom = 1,
                 path = frames[frame_index].path,
                 show_gui = true,
@@ -166,10 +166,17 @@ start_export = function ()
 
         frame_index = frame_index + 1
         if frame_index <= #frames then
-            player.gui.top.add({
+            local sprite = player.gui.top.add({
                 type = 'sprite',
                 sprite = frames[frame_index].sprite,
             })
+            -- Rendering the sprite larger makes the game pick a higher
+            -- resolution mipmap, rather than upscaling the 32x32 one.
+            if icon_scale ~= 1 then
+                sprite.style.width = 32 * icon_scale
+                sprite.style.height = 32 * icon_scale
+                sprite.style.stretch_image_to_widget_size = true
+            end
             wait_frames = extract_interval
         end
     end)
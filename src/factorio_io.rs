@@ -4,26 +4,68 @@ use std::fs::{self};
 
 #[derive(Debug)]
 pub struct FactorioPaths {
-    pub executable: PathBuf,
+    /// The paths that were checked for the executable, in order.
+    pub executable_candidates: Vec<PathBuf>,
     pub scenarios_directory: PathBuf,
     pub script_output_directory: PathBuf,
 }
 
+/// The directories within `bin` that the executable is looked for in, being
+/// the 64-bit build, followed by the directories of older 32-bit builds.
+const ARCHITECTURE_DIRECTORIES: &[&str] = &["x64", "Win32", "i386"];
+
+impl FactorioPaths {
+    /// The executable of the game, being the first of the candidates that
+    /// exists. It's only looked up when it's needed, so that the stages that
+    /// don't run the game also work without it.
+    pub fn executable(&self) -> Result<&Path> {
+        if let Some(executable) = self.executable_candidates.iter().find(|path| path.is_file()) {
+            return Ok(executable);
+        }
+        let checked = self
+            .executable_candidates
+            .iter()
+            .map(|path| path.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("couldn't find the factorio executable, checked: {}", checked),
+        ))
+    }
+}
+
 type Result<T> = std::io::Result<T>;
 
 /// Gets the important paths of the Factorio game.
 /// 
+/// If `user_data_dir` is specified, it is used as the directory for the
+/// scenarios and script output, instead of deriving it from the install.
+/// 
 /// # Remark
 /// Uses `config-path.cfg` to determine in which relative directory to scan for.
 /// This isn't a perfect heuristic, as the game itself will create a `config.ini`
 /// file on first run. If there's any decent reason that'd warrant complicating
 /// this code, a more accuracy solution can be implemented later.
-pub fn get_factorio_paths(root_dir: &::std::ffi::OsStr) -> Result<FactorioPaths> {
+pub fn get_factorio_paths(root_dir: &::std::ffi::OsStr, user_data_dir: Option<&::std::ffi::OsStr>) -> Result<FactorioPaths> {
+    get_factorio_paths_with(root_dir, user_data_dir, platform_data_directory)
+}
+
+/// Like `get_factorio_paths`, but gets the system data directory from
+/// `system_data_directory`, rather than from the platform.
+fn get_factorio_paths_with<F>(
+    root_dir: &::std::ffi::OsStr,
+    user_data_dir: Option<&::std::ffi::OsStr>,
+    system_data_directory: F,
+) -> Result<FactorioPaths>
+where
+    F: FnOnce() -> Option<PathBuf>,
+{
     let root_dir = canonicalize(root_dir)?;
-    let mut executable = root_dir.clone();
-    executable.push("bin");
-    executable.push("x64");
-    executable.push("factorio.exe");
+    let executable_candidates = ARCHITECTURE_DIRECTORIES
+        .iter()
+        .map(|architecture| root_dir.join("bin").join(architecture).join("factorio.exe"))
+        .collect();
 
     let mut config_path = root_dir.clone();
     config_path.push("config-path.cfg");
@@ -42,8 +84,11 @@ pub fn get_factorio_paths(root_dir: &::std::ffi::OsStr) -> Result<FactorioPaths>
         return Err(io::Error::new(io::ErrorKind::InvalidData, "cannot get use-system-read-write-data-directories from config-path.cfg"))
     };
 
-    let data_root = if use_system_data_directory {
-        canonicalize(get_system_data_directory())?
+    let data_root = if let Some(user_data_dir) = user_data_dir {
+        canonicalize(user_data_dir)?
+    }
+    else if use_system_data_directory {
+        canonicalize(get_system_data_directory(system_data_directory)?)?
     }
     else {
         root_dir
@@ -55,7 +100,7 @@ pub fn get_factorio_paths(root_dir: &::std::ffi::OsStr) -> Result<FactorioPaths>
     script_output_directory.push("script-output");
 
     Ok(FactorioPaths {
-        executable,
+        executable_candidates,
         scenarios_directory,
         script_output_directory,
     })
@@ -137,6 +182,16 @@ impl Drop for TempFile {
     }
 }
 
+/// Checks whether files can be created in `directory`, by creating and
+/// removing a marker file in it. The directory is created if needed, and
+/// removed again afterwards if it was.
+pub fn probe_writable<P: Into<PathBuf>>(directory: P) -> Result<()> {
+    let directory = TempDirectory::ensure(directory)?;
+    let marker = directory.path().join(".graphio_write_probe");
+    fs::File::create(&marker)?;
+    fs::remove_file(&marker)
+}
+
 /// Ensures that a directory exists.
 /// 
 /// Returns whether the directory had to be created.
@@ -181,6 +236,14 @@ pub fn create_dir_safely<P: Into<PathBuf>>(parent: P, directory_name: &str) -> R
 /// 
 /// The `extension` parameter should not have a leading `.`
 pub fn write_file_safely<P: Into<PathBuf>>(parent: P, file_name: &str, extension: &str, contents: &[u8]) -> Result<PathBuf> {
+    let (path, mut file) = create_file_safely(parent, file_name, extension)?;
+    file.write_all(contents)?;
+    Ok(path)
+}
+
+/// Creates a new file, appending a suffix to the file name in the same way
+/// as `write_file_safely`, for contents that are written bit by bit.
+pub fn create_file_safely<P: Into<PathBuf>>(parent: P, file_name: &str, extension: &str) -> Result<(PathBuf, fs::File)> {
     let mut root_path = parent.into();
     let mut file_name_buf = String::with_capacity(file_name.len() + extension.len() + 5);
     let mut current_appendix: Option<usize> = None;
@@ -206,10 +269,7 @@ pub fn write_file_safely<P: Into<PathBuf>>(parent: P, file_name: &str, extension
             .write(true)
             .create_new(true)
             .open(&root_path) {
-            Ok(mut file) => {
-                file.write_all(contents)?;
-                return Ok(root_path);
-            },
+            Ok(file) => return Ok((root_path, file)),
             Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => (),
             Err(err) => return Err(err),
         }
@@ -219,27 +279,37 @@ pub fn write_file_safely<P: Into<PathBuf>>(parent: P, file_name: &str, extension
     }
 }
 
-fn get_system_data_directory() -> PathBuf {
+/// Gets the directory in which Factorio stores its data when it's configured
+/// to use the system read-write data directories, as found by `lookup`.
+/// 
+/// Fails if the platform directory cannot be determined, which can happen in
+/// stripped down environments, such as containers without a home directory.
+fn get_system_data_directory<F: FnOnce() -> Option<PathBuf>>(lookup: F) -> Result<PathBuf> {
+    lookup().ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        "cannot determine system data directory; pass --user_data_dir",
+    ))
+}
+
+/// The system read-write data directory of Factorio on this platform, if
+/// the platform directory it's in can be determined.
+fn platform_data_directory() -> Option<PathBuf> {
     // Warning: This code has only been tested on Windows.
-    if cfg!(target_os = "windows") {
-        let mut buf = dirs::data_dir().unwrap();
-        buf.push("Factorio");
-        buf
+    let (base, name) = if cfg!(target_os = "windows") {
+        (dirs::data_dir(), "Factorio")
     }
     else if cfg!(target_os = "macos") {
-        let mut buf = dirs::data_dir().unwrap();
-        buf.push("factorio");
-        buf
+        (dirs::data_dir(), "factorio")
     }
     else if cfg!(target_os = "linux") {
-        let mut buf = dirs::home_dir().unwrap();
-        buf.push(".factorio");
-        buf
+        (dirs::home_dir(), ".factorio")
     }
     else {
         // Factorio only runs on Windows, Linux and MacOS
         unreachable!()
-    }
+    };
+
+    base.map(|base| base.join(name))
 }
 
 /// Canonicalizes a path similar to `std::fs::canonicalize`,
@@ -275,3 +345,133 @@ pub fn canonicalize<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
         fs::canonicalize(path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config_path(root: &Path, use_system_data_directory: bool) {
+        let config = format!(
+            "config-path=__PATH__executable__/../../config\n\
+             use-system-read-write-data-directories={}\n",
+            use_system_data_directory
+        );
+        fs::write(root.join("config-path.cfg"), config).unwrap();
+    }
+
+    /// A fake install of the game in `root`, which keeps its data next to
+    /// the install, with an executable in each of the `architectures`.
+    fn fake_install(root: &Path, architectures: &[&str]) -> FactorioPaths {
+        write_config_path(root, false);
+        for architecture in architectures {
+            let directory = root.join("bin").join(architecture);
+            fs::create_dir_all(&directory).unwrap();
+            fs::write(directory.join("factorio.exe"), b"").unwrap();
+        }
+        get_factorio_paths(root.as_os_str(), None).unwrap()
+    }
+
+    #[test]
+    fn executable_is_found_for_each_architecture() {
+        for architecture in ARCHITECTURE_DIRECTORIES {
+            let root = tempfile::tempdir().unwrap();
+            let paths = fake_install(root.path(), &[architecture]);
+            let root = canonicalize(root.path()).unwrap();
+            let expected = root.join("bin").join(architecture).join("factorio.exe");
+            assert_eq!(paths.executable().unwrap(), expected);
+            assert_eq!(paths.script_output_directory, root.join("script-output"));
+        }
+    }
+
+    #[test]
+    fn x64_executable_is_preferred() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = fake_install(root.path(), &["i386", "x64"]);
+        assert!(paths.executable().unwrap().ends_with(Path::new("bin").join("x64").join("factorio.exe")));
+    }
+
+    #[test]
+    fn missing_executable_lists_the_checked_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = fake_install(root.path(), &[]);
+        let error = paths.executable().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        let message = error.to_string();
+        assert!(message.starts_with("couldn't find the factorio executable, checked: "));
+        for candidate in &paths.executable_candidates {
+            assert!(message.contains(&*candidate.to_string_lossy()), "{}", message);
+        }
+        assert_eq!(paths.executable_candidates.len(), 3);
+    }
+
+    #[test]
+    fn system_data_directory_is_looked_up() {
+        let root = tempfile::tempdir().unwrap();
+        write_config_path(root.path(), true);
+        let data_directory = root.path().join("system-data");
+        fs::create_dir(&data_directory).unwrap();
+
+        let paths = get_factorio_paths_with(root.path().as_os_str(), None, || Some(data_directory.clone())).unwrap();
+        let data_directory = canonicalize(&data_directory).unwrap();
+        assert_eq!(paths.scenarios_directory, data_directory.join("scenarios"));
+        assert_eq!(paths.script_output_directory, data_directory.join("script-output"));
+    }
+
+    #[test]
+    fn undetermined_system_data_directory_asks_for_user_data_dir() {
+        let root = tempfile::tempdir().unwrap();
+        write_config_path(root.path(), true);
+
+        let error = get_factorio_paths_with(root.path().as_os_str(), None, || None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), "cannot determine system data directory; pass --user_data_dir");
+
+        // The lookup isn't needed when the directory is given.
+        let user_data_dir = root.path().join("user-data");
+        fs::create_dir(&user_data_dir).unwrap();
+        let paths = get_factorio_paths_with(root.path().as_os_str(), Some(user_data_dir.as_os_str()), || {
+            panic!("the system data directory shouldn't be looked up")
+        })
+        .unwrap();
+        assert_eq!(paths.script_output_directory, canonicalize(&user_data_dir).unwrap().join("script-output"));
+    }
+
+    #[test]
+    fn writable_directory_is_left_as_is() {
+        let root = tempfile::tempdir().unwrap();
+        probe_writable(root.path()).unwrap();
+        assert!(root.path().is_dir());
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+
+        // A directory that had to be created for the probe is removed again.
+        let missing = root.path().join("missing");
+        probe_writable(&missing).unwrap();
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn directory_under_a_file_isnt_writable() {
+        let root = tempfile::tempdir().unwrap();
+        let file = root.path().join("file");
+        fs::write(&file, b"").unwrap();
+        assert!(probe_writable(file.join("directory")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_directory_isnt_writable() {
+        use std::os::unix::fs::PermissionsExt;
+        let root = tempfile::tempdir().unwrap();
+        let directory = root.path().join("read_only");
+        fs::create_dir(&directory).unwrap();
+        fs::set_permissions(&directory, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Permissions aren't enforced for some users, such as root, in which
+        // case there's nothing to check.
+        let enforced = fs::File::create(directory.join("check")).is_err();
+        if enforced {
+            assert!(probe_writable(&directory).is_err());
+        }
+        fs::set_permissions(&directory, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
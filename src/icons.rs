@@ -0,0 +1,381 @@
+use image::{DynamicImage, RgbImage, RgbaImage};
+use std::io;
+use std::path::Path;
+
+/// How the transparency of the icons is obtained.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IconMode {
+    /// Reconstructed from the renders on a dark and a light background, which
+    /// is how the icons are extracted from the game.
+    Reconstruct,
+    /// Taken from the icons in the light directory, which already have an
+    /// alpha channel, for icons that were rendered some other way.
+    Rgba,
+}
+
+/// Loads an icon from its render on the light background, and for
+/// `IconMode::Reconstruct`, the one on the dark background, which is only
+/// read if the light one has no alpha channel.
+pub fn load_icon(light_path: &Path, dark_path: &Path, tile_size: (u32, u32), mode: IconMode) -> io::Result<RgbaImage> {
+    match mode {
+        IconMode::Reconstruct => {
+            // Icons that already have an alpha channel weren't rendered
+            // on top of a background, and converting them to RGB to be
+            // reconstructed would throw their transparency away.
+            let light_img = load_image(light_path, tile_size)?;
+            if has_alpha(&light_img) {
+                return Ok(light_img.to_rgba());
+            }
+            let dark_img = load_image(dark_path, tile_size)?;
+            if has_alpha(&dark_img) {
+                Ok(dark_img.to_rgba())
+            } else {
+                Ok(combine_image(dark_img.to_rgb(), light_img.to_rgb()))
+            }
+        }
+        IconMode::Rgba => Ok(load_image(light_path, tile_size)?.to_rgba()),
+    }
+}
+
+pub fn check_tile_size(dimensions: (u32, u32), tile_size: (u32, u32)) -> io::Result<()> {
+    if dimensions != tile_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "expected image to be {}x{}, was icon_scale the same during extract_icons?",
+                tile_size.0, tile_size.1
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Loads an icon, keeping the color type it was decoded as, so that an alpha
+/// channel isn't lost before it's known whether the icon has one. Icons with
+/// more than 8 bits per channel, or with a palette that wasn't expanded while
+/// decoding, aren't supported.
+///
+/// The pixels are the bytes stored in the file, which are assumed to be sRGB,
+/// as that's what the game renders the icons in. Color profile and gamma
+/// chunks are ignored by the decoder, rather than applied, so the alpha
+/// reconstruction doesn't depend on the metadata that the icon was saved with.
+fn load_image(path: &Path, tile_size: (u32, u32)) -> io::Result<DynamicImage> {
+    use image::{ColorType, GenericImageView};
+    let image = image::open(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    match image.color() {
+        ColorType::Gray(8)
+        | ColorType::GrayA(8)
+        | ColorType::RGB(8)
+        | ColorType::RGBA(8)
+        | ColorType::BGR(8)
+        | ColorType::BGRA(8) => {}
+        color => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported color type {:?} in image: {}",
+                    color,
+                    path.to_string_lossy()
+                ),
+            ))
+        }
+    }
+    check_tile_size(image.dimensions(), tile_size)?;
+    Ok(image)
+}
+
+fn has_alpha(image: &DynamicImage) -> bool {
+    use image::ColorType;
+    matches!(
+        image.color(),
+        ColorType::GrayA(_) | ColorType::RGBA(_) | ColorType::BGRA(_)
+    )
+}
+
+/// Reconstructs the transparency of an icon, from renders of it on top of a
+/// black (`dark`) and a white (`light`) background. Solving the blending of
+/// both renders for the alpha and color gives two estimates of each, which
+/// are averaged.
+///
+/// Some examples, of an icon composited over both backgrounds, and the pixel
+/// that is recovered from that:
+///
+/// | original (rgba)    | dark            | light           | recovered          |
+/// |--------------------|-----------------|-----------------|--------------------|
+/// | `51, 102, 153, 255`| `51, 102, 153`  | `51, 102, 153`  | `51, 102, 153, 255`|
+/// | `255, 0, 0, 128`   | `128, 0, 0`     | `255, 127, 127` | `255, 0, 0, 128`   |
+/// | `200, 100, 50, 64` | `50, 25, 13`    | `241, 216, 204` | `199, 100, 52, 64` |
+/// | `0, 0, 0, 0`       | `0, 0, 0`       | `255, 255, 255` | `255, 255, 255, 0` |
+///
+/// Opaque pixels are recovered exactly, whilst the color of translucent ones
+/// can be off by a few steps, as both renders are rounded to 8 bits, which
+/// is amplified by dividing by the alpha. Fully transparent pixels have no
+/// color to recover, and come out white.
+pub fn combine_image(dark: RgbImage, light: RgbImage) -> RgbaImage {
+    let mut combined = RgbaImage::new(dark.width(), dark.height());
+    combined.enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let d = dark.get_pixel(x, y);
+        let l = light.get_pixel(x, y);
+        // d = a * rgb
+        // l = a * rgb + (1 - a)
+        // l - d = 1 - a
+        // d - l = a - 1
+        // a = d - l + 1
+        let d = [
+            d.data[0] as f64 / 255f64,
+            d.data[1] as f64 / 255f64,
+            d.data[2] as f64 / 255f64,
+        ];
+        let l = [
+            l.data[0] as f64 / 255f64,
+            l.data[1] as f64 / 255f64,
+            l.data[2] as f64 / 255f64,
+        ];
+
+        let dr = d[0] - l[0] + 1f64;
+        let dg = d[1] - l[1] + 1f64;
+        let db = d[2] - l[2] + 1f64;
+
+        // Average the alpha based on the 3 channels
+        let a = (dr + dg + db) / 3f64;
+
+        // d = a * rgb
+        // rgb = d / a
+        let r1 = d[0] / a;
+        let g1 = d[1] / a;
+        let b1 = d[2] / a;
+
+        // l = a * rgb + (1 - a)
+        // l - 1 + a = a * rgb
+        // rgb = (l - 1 + a) / a
+        //     = (l - 1) / a + 1
+        let r2 = (l[0] - 1f64) / a + 1f64;
+        let g2 = (l[1] - 1f64) / a + 1f64;
+        let b2 = (l[2] - 1f64) / a + 1f64;
+
+        // Average color based on both images
+        let r = (r1 + r2) / 2f64;
+        let g = (g1 + g2) / 2f64;
+        let b = (b1 + b2) / 2f64;
+
+        pixel.data = [
+            f64::max(0f64, f64::min(255f64, r * 255f64)).round() as u8,
+            f64::max(0f64, f64::min(255f64, g * 255f64)).round() as u8,
+            f64::max(0f64, f64::min(255f64, b * 255f64)).round() as u8,
+            f64::max(0f64, f64::min(255f64, a * 255f64)).round() as u8,
+        ];
+    });
+
+    combined
+}
+
+pub fn encode_png(size: (u32, u32), pixels: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut encoded = Vec::new();
+    write_png(&mut encoded, size, pixels)?;
+    Ok(encoded)
+}
+
+/// Encodes RGBA pixels as a PNG into `writer`. The pixels are taken by value,
+/// so that they're dropped as soon as they've been encoded.
+pub fn write_png<W: io::Write>(writer: W, size: (u32, u32), pixels: Vec<u8>) -> io::Result<()> {
+    use image::png::PNGEncoder;
+    if pixels.len() != 4 * size.0 as usize * size.1 as usize {
+        return Err(io::Error::new(io::ErrorKind::Other, "failed to encode image"));
+    }
+    PNGEncoder::new(writer).encode(&pixels, size.0, size.1, image::ColorType::RGBA(8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ColorType, Rgb, Rgba};
+    use std::path::PathBuf;
+
+    /// Saves a 2x2 image with every pixel set to `pixel`, being grayscale
+    /// with alpha, RGB or RGBA depending on its length.
+    fn save_icon(directory: &Path, name: &str, pixel: &[u8]) -> PathBuf {
+        let path = directory.join(name);
+        let color = match pixel.len() {
+            2 => ColorType::GrayA(8),
+            3 => ColorType::RGB(8),
+            _ => ColorType::RGBA(8),
+        };
+        image::save_buffer(&path, &pixel.repeat(4), 2, 2, color).unwrap();
+        path
+    }
+
+    #[test]
+    fn rgba_icon_is_loaded_directly() {
+        let directory = tempfile::tempdir().unwrap();
+        let light = save_icon(directory.path(), "light.png", &[10, 20, 30, 40]);
+        let missing = directory.path().join("dark.png");
+
+        let icon = load_icon(&light, &missing, (2, 2), IconMode::Rgba).unwrap();
+        assert!(icon.pixels().all(|pixel| *pixel == Rgba([10, 20, 30, 40])));
+
+        // Reconstructing leaves an icon with an alpha channel as is as well.
+        let icon = load_icon(&light, &missing, (2, 2), IconMode::Reconstruct).unwrap();
+        assert!(icon.pixels().all(|pixel| *pixel == Rgba([10, 20, 30, 40])));
+    }
+
+    #[test]
+    fn grayscale_alpha_icon_keeps_its_alpha() {
+        let directory = tempfile::tempdir().unwrap();
+        let light = save_icon(directory.path(), "light.png", &[200, 64]);
+        let missing = directory.path().join("dark.png");
+
+        for &mode in &[IconMode::Rgba, IconMode::Reconstruct] {
+            let icon = load_icon(&light, &missing, (2, 2), mode).unwrap();
+            assert!(icon.pixels().all(|pixel| *pixel == Rgba([200, 200, 200, 64])));
+        }
+    }
+
+    #[test]
+    fn rgb_icon_is_opaque_when_loaded_directly() {
+        let directory = tempfile::tempdir().unwrap();
+        let light = save_icon(directory.path(), "light.png", &[255, 127, 127]);
+        let missing = directory.path().join("dark.png");
+
+        let icon = load_icon(&light, &missing, (2, 2), IconMode::Rgba).unwrap();
+        assert!(icon.pixels().all(|pixel| *pixel == Rgba([255, 127, 127, 255])));
+    }
+
+    #[test]
+    fn dark_and_light_pair_is_reconstructed() {
+        let directory = tempfile::tempdir().unwrap();
+        let light = save_icon(directory.path(), "light.png", &[255, 127, 127]);
+        let dark = save_icon(directory.path(), "dark.png", &[128, 0, 0]);
+
+        let icon = load_icon(&light, &dark, (2, 2), IconMode::Reconstruct).unwrap();
+        assert!(icon.pixels().all(|pixel| *pixel == Rgba([255, 0, 0, 128])));
+
+        let missing = directory.path().join("missing.png");
+        assert!(load_icon(&light, &missing, (2, 2), IconMode::Reconstruct).is_err());
+    }
+
+    #[test]
+    fn icon_of_the_wrong_size_is_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let light = save_icon(directory.path(), "light.png", &[0, 0, 0, 0]);
+
+        let error = load_icon(&light, &light, (32, 32), IconMode::Rgba).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// The CRC-32 that PNG chunks are checked with.
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn gamma_chunk_is_not_applied() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = save_icon(directory.path(), "gamma.png", &[10, 128, 250, 200]);
+
+        // A gamma of 1.0, which would brighten every channel if the pixels
+        // were converted to the usual gamma of 1/2.2, is inserted right after
+        // the IHDR chunk, which follows the 8 byte signature.
+        let mut chunk = b"gAMA".to_vec();
+        chunk.extend_from_slice(&100_000u32.to_be_bytes());
+        let crc = crc32(&chunk);
+        let mut gamma = 4u32.to_be_bytes().to_vec();
+        gamma.extend(chunk);
+        gamma.extend_from_slice(&crc.to_be_bytes());
+        let mut png = std::fs::read(&path).unwrap();
+        assert_eq!(&png[12..16], b"IHDR");
+        let ihdr_end = 8 + 4 + 4 + 13 + 4;
+        png.splice(ihdr_end..ihdr_end, gamma);
+        std::fs::write(&path, &png).unwrap();
+
+        let image = load_image(&path, (2, 2)).unwrap();
+        assert!(image.to_rgba().pixels().all(|pixel| *pixel == Rgba([10, 128, 250, 200])));
+        let missing = directory.path().join("dark.png");
+        let icon = load_icon(&path, &missing, (2, 2), IconMode::Rgba).unwrap();
+        assert!(icon.pixels().all(|pixel| *pixel == Rgba([10, 128, 250, 200])));
+    }
+
+    #[test]
+    fn corrupt_gamma_chunk_is_detected() {
+        // Guards the test above: if the decoder didn't read the inserted
+        // chunk, it wouldn't notice a wrong checksum either.
+        let directory = tempfile::tempdir().unwrap();
+        let path = save_icon(directory.path(), "gamma.png", &[10, 128, 250, 200]);
+        let mut gamma = 4u32.to_be_bytes().to_vec();
+        gamma.extend_from_slice(b"gAMA");
+        gamma.extend_from_slice(&100_000u32.to_be_bytes());
+        gamma.extend_from_slice(&0u32.to_be_bytes());
+        let mut png = std::fs::read(&path).unwrap();
+        let ihdr_end = 8 + 4 + 4 + 13 + 4;
+        png.splice(ihdr_end..ihdr_end, gamma);
+        std::fs::write(&path, &png).unwrap();
+
+        assert!(load_image(&path, (2, 2)).is_err());
+    }
+
+    #[test]
+    fn streamed_png_equals_encoded_png() {
+        let size = (3, 2);
+        let pixels = (0..4 * 3 * 2).map(|i| (i * 10) as u8).collect::<Vec<u8>>();
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("streamed.png");
+
+        let mut writer = io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        write_png(&mut writer, size, pixels.clone()).unwrap();
+        io::Write::flush(&mut writer).unwrap();
+        drop(writer);
+
+        let streamed = std::fs::read(&path).unwrap();
+        assert_eq!(streamed, encode_png(size, pixels.clone()).unwrap());
+        assert_eq!(image::open(&path).unwrap().to_rgba().into_raw(), pixels);
+    }
+
+    #[test]
+    fn png_with_the_wrong_amount_of_pixels_is_rejected() {
+        assert!(encode_png((2, 2), vec![0; 4 * 3]).is_err());
+        assert!(write_png(io::sink(), (2, 2), vec![0; 4 * 5]).is_err());
+    }
+
+    /// Combines a single pixel, rendered on the dark and light background.
+    fn combine_pixel(dark: [u8; 3], light: [u8; 3]) -> [u8; 4] {
+        let dark = RgbImage::from_pixel(1, 1, Rgb(dark));
+        let light = RgbImage::from_pixel(1, 1, Rgb(light));
+        combine_image(dark, light).get_pixel(0, 0).data
+    }
+
+    #[test]
+    fn combine_opaque_pixel() {
+        assert_eq!(combine_pixel([51, 102, 153], [51, 102, 153]), [51, 102, 153, 255]);
+    }
+
+    #[test]
+    fn combine_half_transparent_pixel() {
+        assert_eq!(combine_pixel([128, 0, 0], [255, 127, 127]), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn combine_fully_transparent_pixel() {
+        assert_eq!(combine_pixel([0, 0, 0], [255, 255, 255]), [255, 255, 255, 0]);
+    }
+
+    #[test]
+    fn combine_keeps_every_pixel_in_place() {
+        let mut dark = RgbImage::new(2, 1);
+        let mut light = RgbImage::new(2, 1);
+        dark.put_pixel(0, 0, Rgb([51, 102, 153]));
+        light.put_pixel(0, 0, Rgb([51, 102, 153]));
+        dark.put_pixel(1, 0, Rgb([0, 0, 0]));
+        light.put_pixel(1, 0, Rgb([255, 255, 255]));
+
+        let combined = combine_image(dark, light);
+        assert_eq!(combined.dimensions(), (2, 1));
+        assert_eq!(*combined.get_pixel(0, 0), Rgba([51, 102, 153, 255]));
+        assert_eq!(*combined.get_pixel(1, 0), Rgba([255, 255, 255, 0]));
+    }
+}
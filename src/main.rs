@@ -6,39 +6,227 @@ extern crate image;
 extern crate itertools;
 extern crate num_traits;
 extern crate serde_json;
+extern crate sha2;
+#[cfg(test)]
+extern crate tempfile;
 
+mod atlas;
+mod config;
+mod determinism;
 mod factorio_io;
-mod parsing;
-
+mod icons;
+mod manifest;
+mod report;
+mod self_test;
+mod strict;
+#[cfg(test)]
+mod test_support;
+mod transform_cache;
+mod version;
+
+use crate::atlas::AtlasLayout;
+use crate::config::Config;
 use crate::factorio_io::{
-    create_dir_safely, write_file_safely, FactorioPaths, TempDirectory, TempFile,
+    create_dir_safely, create_file_safely, write_file_safely, FactorioPaths, TempDirectory, TempFile,
 };
-use graphio_rs_data::{self as data, GameData};
+use crate::icons::{encode_png, load_icon, write_png, IconMode};
+use crate::manifest::Manifest;
+use crate::report::SummaryReporter;
+use crate::transform_cache::TransformCache;
+use crate::version::{Version, VersionCheck, VersionRange};
+use graphio_rs_data::parsing;
+use graphio_rs_data::{self as data, GameData, Reporter, TransformOptions, ID};
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::ffi::OsString;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::str::FromStr;
 
 fn main() {
-    match main_io() {
+    match main_io(std::env::args_os().collect()) {
         Ok(_) => (),
         Err(err) => {
             eprintln!("{}", err);
+            std::process::exit(1);
         }
     }
 }
 
+#[derive(Clone, Copy)]
 enum PruneLevel {
     NoPruning,
     BasicPruning,
     ExtensivePruning,
 }
 
-fn main_io() -> io::Result<()> {
+impl PruneLevel {
+    /// The spellings that are accepted when parsing a prune level.
+    const SPELLINGS: &'static [&'static str] = &["0", "1", "2", "none", "basic", "extensive"];
+
+    /// How the prune level is passed to the export script.
+    fn as_lua_digit(&self) -> char {
+        match self {
+            PruneLevel::NoPruning => '0',
+            PruneLevel::BasicPruning => '1',
+            PruneLevel::ExtensivePruning => '2',
+        }
+    }
+}
+
+impl TryFrom<&str> for PruneLevel {
+    type Error = String;
+
+    /// Accepts both the digit, and the name of the prune level.
+    fn try_from(s: &str) -> Result<PruneLevel, String> {
+        match s {
+            "0" | "none" => Ok(PruneLevel::NoPruning),
+            "1" | "basic" => Ok(PruneLevel::BasicPruning),
+            "2" | "extensive" => Ok(PruneLevel::ExtensivePruning),
+            _ => Err(format!("unknown prune level: {}", s)),
+        }
+    }
+}
+
+impl FromStr for PruneLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PruneLevel, String> {
+        PruneLevel::try_from(s)
+    }
+}
+
+/// Describes a stage of the extraction, and how it relates to the other stages.
+struct Stage {
+    name: &'static str,
+    /// Files that have to be present in the script-output directory.
+    inputs: &'static [&'static str],
+    /// Files that are written to the script-output directory.
+    outputs: &'static [&'static str],
+    /// The stages that are performed as part of this stage.
+    implies: &'static [&'static str],
+}
+
+const STAGES: &[Stage] = &[
+    Stage {
+        name: "all",
+        inputs: &[],
+        outputs: &["game_data.json", "game_icons.png"],
+        implies: &["extract_data", "transform_data", "extract_icons", "transform_icons"],
+    },
+    Stage {
+        name: "data",
+        inputs: &[],
+        outputs: &["game_data.json"],
+        implies: &["extract_data", "transform_data"],
+    },
+    Stage {
+        name: "icons",
+        inputs: &["game_data.json"],
+        outputs: &["game_data.json", "game_icons.png"],
+        implies: &["extract_icons", "transform_icons"],
+    },
+    Stage {
+        name: "extract_data",
+        inputs: &[],
+        outputs: &["prototypes.json"],
+        implies: &[],
+    },
+    Stage {
+        name: "transform_data",
+        inputs: &["prototypes.json"],
+        outputs: &["game_data.json"],
+        implies: &[],
+    },
+    Stage {
+        name: "extract_icons",
+        inputs: &["game_data.json"],
+        outputs: &["graphio_extracted_icons/"],
+        implies: &[],
+    },
+    Stage {
+        name: "transform_icons",
+        inputs: &["game_data.json", "graphio_extracted_icons/"],
+        outputs: &["game_data.json", "game_icons.png"],
+        implies: &[],
+    },
+    Stage {
+        name: "self_test",
+        inputs: &["game_data.json"],
+        outputs: &[],
+        implies: &[],
+    },
+];
+
+/// Checks upfront whether the stage can write its outputs to the script
+/// output directory, so that a directory that isn't writable is reported
+/// right away, rather than as a failure halfway through the stage.
+fn check_script_output_writable(paths: &FactorioPaths, stage_name: &str) -> io::Result<()> {
+    let stage = match STAGES.iter().find(|stage| stage.name == stage_name) {
+        Some(stage) => stage,
+        None => return Ok(()),
+    };
+    if stage.outputs.is_empty() {
+        return Ok(());
+    }
+    factorio_io::probe_writable(&paths.script_output_directory).map_err(|err| {
+        let mut message = format!(
+            "can't write to the script output directory {}: {}",
+            paths.script_output_directory.to_string_lossy(),
+            err
+        );
+        if stage.name == "extract_icons" || stage.implies.contains(&"extract_icons") {
+            message.push_str(
+                "\nthe icons are always extracted there, as it's the only directory that Factorio lets scripts write to",
+            );
+        }
+        message.push_str(
+            "\nmake the directory writable, or use --user_data_dir to have Factorio use a different data directory",
+        );
+        io::Error::new(err.kind(), message)
+    })
+}
+
+fn print_stages(as_json: bool, reporter: &mut dyn Reporter) -> io::Result<()> {
+    if as_json {
+        let stages = STAGES
+            .iter()
+            .map(|stage| {
+                serde_json::json!({
+                    "name": stage.name,
+                    "inputs": stage.inputs,
+                    "outputs": stage.outputs,
+                    "implies": stage.implies,
+                })
+            })
+            .collect::<Vec<_>>();
+        reporter.info(&serde_json::ser::to_string_pretty(&stages)?);
+    } else {
+        fn join(entries: &[&str]) -> String {
+            if entries.is_empty() {
+                "(none)".to_owned()
+            } else {
+                entries.join(", ")
+            }
+        }
+        for stage in STAGES {
+            reporter.info(stage.name);
+            reporter.info(&format!("    inputs:  {}", join(stage.inputs)));
+            reporter.info(&format!("    outputs: {}", join(stage.outputs)));
+            reporter.info(&format!("    implies: {}", join(stage.implies)));
+        }
+    }
+    Ok(())
+}
+
+/// The command line options.
+fn app_definition() -> clap::App<'static, 'static> {
     use clap::{App, Arg};
-    let app = App::new("graphio_rs_extractor")
+    let stage_names = STAGES.iter().map(|stage| stage.name).collect::<Vec<_>>();
+    App::new("graphio_rs_extractor")
         .version(crate_version!())
         .about("Tool to extract data from the game Factorio, for use in the Graphio tool.")
         .arg(
@@ -46,33 +234,59 @@ fn main_io() -> io::Result<()> {
                 .index(1)
                 .help("The directory of the Factorio game")
                 .takes_value(true)
-                .required(true),
+                .required_unless_one(&["list_stages", "validate_only"]),
+        )
+        .arg(
+            Arg::with_name("user_data_dir")
+                .long("user_data_dir")
+                .help("The directory in which Factorio stores its scenarios and script output. Derived from the game directory when omitted.")
+                .takes_value(true),
         )
         .arg(
             Arg::with_name("stage")
                 .long("stage")
                 .help("What stage of the extraction to perform.")
                 .takes_value(true)
-                .possible_values(&[
-                    "all",
-                    "data",
-                    "icons",
-                    "extract_data",
-                    "transform_data",
-                    "extract_icons",
-                    "transform_icons",
-                ])
+                .possible_values(&stage_names)
                 .default_value("all")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("list_stages")
+                .long("list_stages")
+                .help("Lists the stages, with their inputs, outputs, and implied stages, and exits.")
+                .takes_value(true)
+                .min_values(0)
+                .possible_values(&["text", "json"]),
+        )
         .arg(
             Arg::with_name("prune_level")
                 .long("prune_level")
                 .help("The level of pruning of game data to perform during extract_data.")
                 .takes_value(true)
-                .possible_values(&["0", "1", "2"])
+                .possible_values(PruneLevel::SPELLINGS)
                 .default_value("1"),
         )
+        .arg(
+            Arg::with_name("prune_keep")
+                .long("prune_keep")
+                .help("A comma separated list of names of items, fluids, recipes, machines, and beacons that are never pruned during extract_data. The ingredients of kept recipes, and the recipes producing kept items and fluids, are kept as well.")
+                .takes_value(true)
+                .validator(|value| {
+                    if value.split(',').any(|name| name.trim().is_empty()) {
+                        return Err("should not contain empty names".to_owned());
+                    }
+                    Ok(())
+                }),
+        )
+        .arg(
+            Arg::with_name("factorio_arg")
+                .long("factorio_arg")
+                .help("An additional argument to pass to Factorio during extract_data and extract_icons, such as --mod-directory. Can be repeated, and values starting with a dash have to be passed as --factorio_arg=<arg>.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
         .arg(
             Arg::with_name("no_transform_log")
                 .long("no_transform_log")
@@ -80,6 +294,14 @@ fn main_io() -> io::Result<()> {
                     "Disables printing which entries have been encountered during transform_data.",
                 ),
         )
+        .arg(
+            Arg::with_name("untranslated")
+                .long("untranslated")
+                .help("What to do with descriptions that have no translation during transform_data, either using the key, or dropping them.")
+                .takes_value(true)
+                .possible_values(&["key", "drop"])
+                .default_value("drop"),
+        )
         .arg(
             Arg::with_name("extract_interval")
                 .long("extract_interval")
@@ -93,80 +315,641 @@ fn main_io() -> io::Result<()> {
                 })
                 .default_value("5"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("icon_scale")
+                .long("icon_scale")
+                .help("The scale at which icons are extracted, relative to 32x32, during extract_icons and transform_icons.")
+                .takes_value(true)
+                .possible_values(&["1", "2", "4"])
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("validate_only")
+                .long("validate_only")
+                .help("Checks an existing game data file for consistency, without requiring a Factorio install, and exits.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("merge")
+                .long("merge")
+                .help("Merges the game data and tile set stored in this directory into the ones in the script output directory, keeping the objects that are already there, stores the result next to them, and exits. Can be repeated.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("icon_coverage")
+                .long("icon_coverage")
+                .help("Prints how many objects have an icon, and which ones are missing one."),
+        )
+        .arg(
+            Arg::with_name("atlas_padding")
+                .long("atlas_padding")
+                .help("The amount of pixels of space around each icon in the tile set, during transform_icons.")
+                .takes_value(true)
+                .validator(|value| {
+                    value
+                        .parse::<u8>()
+                        .map_err(|_| "should be a non-negative integer".to_owned())?;
+                    Ok(())
+                })
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("atlas_bleed")
+                .long("atlas_bleed")
+                .help("Fills the padding around each icon with its edge pixels, rather than leaving it transparent."),
+        )
+        .arg(
+            Arg::with_name("icon_output")
+                .long("icon_output")
+                .help("How transform_icons stores the icons, either packed into a single tile set, or as a file per distinct icon, along with an index mapping objects to their file.")
+                .takes_value(true)
+                .possible_values(&["atlas", "individual"])
+                .default_value("atlas"),
+        )
+        .arg(
+            Arg::with_name("icon_map")
+                .long("icon_map")
+                .help("Also writes the index of the tile of every object during transform_icons, counting from 0 and grouped by collection, to icon_map.json."),
+        )
+        .arg(
+            Arg::with_name("icon_mode")
+                .long("icon_mode")
+                .help("How transform_icons obtains transparency, either by reconstructing it from the dark and light renders, or by using the light icons, which already have an alpha channel.")
+                .takes_value(true)
+                .possible_values(&["reconstruct", "rgba"])
+                .default_value("reconstruct"),
+        )
+        .arg(
+            Arg::with_name("icon_light_dir")
+                .long("icon_light_dir")
+                .help("The directory within the extracted icons that transform_icons reads the renders on the light background from, for icons extracted by other tools.")
+                .takes_value(true)
+                .default_value("light"),
+        )
+        .arg(
+            Arg::with_name("icon_dark_dir")
+                .long("icon_dark_dir")
+                .help("The directory within the extracted icons that transform_icons reads the renders on the dark background from, for icons extracted by other tools.")
+                .takes_value(true)
+                .default_value("dark"),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .help("Continues a previous extract_icons, only extracting the icons that aren't present in the extracted icons directory yet."),
+        )
+        .arg(
+            Arg::with_name("factorio_log")
+                .long("factorio_log")
+                .help("Reads the output of a previous run of the export script from the given file during extract_data, rather than launching Factorio.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("expected_version")
+                .long("expected_version")
+                .help("The versions of Factorio that are expected during extract_data, from the first up to, but not including, the second. The upper bound can be left out.")
+                .takes_value(true)
+                .validator(|value| value.parse::<VersionRange>().map(|_| ()))
+                .default_value(version::SUPPORTED_VERSIONS),
+        )
+        .arg(
+            Arg::with_name("drop_untranslated")
+                .long("drop_untranslated")
+                .help("Removes objects without a translated name during transform_data, which are usually hidden, along with the recipes that refer to them."),
+        )
+        .arg(
+            Arg::with_name("check_determinism")
+                .long("check_determinism")
+                .help("Runs transform_data or transform_icons twice on the same input, and fails if the outputs differ.")
+                .hidden(true),
+        )
+        .arg(
+            Arg::with_name("max_records")
+                .long("max_records")
+                .help("Keeps at most this many objects of each kind during transform_data, being the first ones that were exported, along with the recipes that only refer to those, to quickly check the output on large data sets. The other records are skipped without being parsed.")
+                .takes_value(true)
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(max_records) if max_records > 0 => Ok(()),
+                    _ => Err("should be a positive integer".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::with_name("transform_cache")
+                .long("transform_cache")
+                .help("Stores the game data parsed during transform_data in the given directory, and reuses it when the same prototypes are transformed with the same options again.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("icons_from")
+                .long("icons_from")
+                .help("Reuses the tile set of an existing game data file, and the game_icons.png next to it, during transform_data, rather than requiring the icons to be extracted again. Icons are assigned in the order in which transform_icons packed them.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dump_lua")
+                .long("dump_lua")
+                .help("Writes the generated Lua scripts to the given directory, without running Factorio, and exits.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Rejects game data files that contain fields which aren't understood, rather than ignoring them, and fails when the version of Factorio isn't expected, rather than warning."),
+        )
+        .arg(
+            Arg::with_name("export_matrix")
+                .long("export_matrix")
+                .help("Writes which machines can craft which recipes, as CSV if the path ends in .csv, and as JSON otherwise.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .long("manifest")
+                .help("Writes a JSON manifest listing every output file with its size and SHA-256 hash.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("summary_json")
+                .long("summary_json")
+                .help("Writes a JSON report of the run, listing the stages and their duration, the amount of objects and icons, the output files, and any warnings.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("Reads default values for the other options from a TOML file, mapping their long names to their values, such as `atlas_padding = 2`. Options given on the command line take precedence over the file. The directory, and the options that replace it, can't be given in the file.")
+                .takes_value(true),
+        )
+}
+
+/// Parses the command line, along with the config file given with
+/// `--config`. Exits the process if the command line is invalid, or help
+/// was requested.
+fn parse_arguments(args: Vec<OsString>, reporter: &mut dyn Reporter) -> io::Result<clap::ArgMatches<'static>> {
+    let mut definition = app_definition();
+    let matches = definition
+        .get_matches_from_safe_borrow(&args)
+        .unwrap_or_else(|err| err.exit());
+    match matches.value_of_os("config") {
+        Some(path) => {
+            let config = Config::load(Path::new(path), reporter)?;
+            apply_config(&mut definition, args, &matches, &config)
+        }
+        None => Ok(matches),
+    }
+}
+
+/// Parses the command line again, with the options from `config` appended
+/// to it. Only the options that weren't given on the command line are
+/// appended, so those take precedence.
+///
+/// The required arguments have to be given on the command line, as it's
+/// parsed before the config file is known.
+fn apply_config(
+    definition: &mut clap::App<'static, 'static>,
+    mut args: Vec<OsString>,
+    matches: &clap::ArgMatches,
+    config: &Config,
+) -> io::Result<clap::ArgMatches<'static>> {
+    args.extend(config.arguments(matches)?.into_iter().map(Into::into));
+    definition.get_matches_from_safe_borrow(args).or_else(|err| match err.kind {
+        // The command line was valid by itself, so it's an option from the
+        // config file that isn't known.
+        clap::ErrorKind::UnknownArgument => {
+            let argument = err.info.as_ref().and_then(|info| info.first()).cloned().unwrap_or_default();
+            let name = argument.trim_start_matches('-').split('=').next().unwrap_or_default();
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown option in the config file: {}", name),
+            ))
+        }
+        _ => err.exit(),
+    })
+}
+
+fn main_io(args: Vec<OsString>) -> io::Result<()> {
+    let mut reporter = SummaryReporter::new();
+    let app = parse_arguments(args, &mut reporter)?;
+
+    if app.is_present("list_stages") {
+        return print_stages(app.value_of("list_stages") == Some("json"), &mut reporter);
+    }
+    let strict = app.is_present("strict");
+    if let Some(path) = app.value_of_os("validate_only") {
+        let game_data = load_game_data_from(path, strict, &mut reporter)?;
+        return validate_game_data(&game_data, &mut reporter);
+    }
 
     let directory = app.value_of_os("directory").unwrap();
-    let paths = factorio_io::get_factorio_paths(&directory)?;
+    let paths = factorio_io::get_factorio_paths(&directory, app.value_of_os("user_data_dir"))?;
+    if let Some(directories) = app.values_of_os("merge") {
+        let directories = directories.map(Path::new).collect::<Vec<_>>();
+        return merge_data_sets(&paths, &directories, strict, &mut reporter);
+    }
 
-    let prune_level = match app.value_of("prune_level").unwrap() {
-        "0" => PruneLevel::NoPruning,
-        "1" => PruneLevel::BasicPruning,
-        "2" => PruneLevel::ExtensivePruning,
-        _ => unreachable!(),
+    let prune_level = app.value_of("prune_level").unwrap().parse::<PruneLevel>().unwrap();
+    let prune_keep = app
+        .value_of("prune_keep")
+        .map(|names| names.split(',').map(|name| name.trim().to_owned()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let transform_options = TransformOptions {
+        log_entries: !app.is_present("no_transform_log"),
+        untranslated_descriptions: match app.value_of("untranslated").unwrap() {
+            "key" => parsing::Untranslated::Key,
+            "drop" => parsing::Untranslated::Drop,
+            _ => unreachable!(),
+        },
+        max_records: app
+            .value_of("max_records")
+            .map(|value| value.parse::<usize>().unwrap()),
     };
-    let no_transform_log = app.is_present("no_transform_log");
     let extract_interval = app
         .value_of("extract_interval")
         .unwrap()
         .parse::<usize>()
         .unwrap();
+    let icon_scale = app.value_of("icon_scale").unwrap().parse::<u32>().unwrap();
+    let atlas_layout = AtlasLayout {
+        tile_size: (TILE_WIDTH * icon_scale, TILE_HEIGHT * icon_scale),
+        padding: app.value_of("atlas_padding").unwrap().parse::<u32>().unwrap(),
+        bleed: app.is_present("atlas_bleed"),
+    };
+    let resume = app.is_present("resume");
+    let icon_options = IconOptions {
+        mode: match app.value_of("icon_mode").unwrap() {
+            "reconstruct" => IconMode::Reconstruct,
+            "rgba" => IconMode::Rgba,
+            _ => unreachable!(),
+        },
+        output: match app.value_of("icon_output").unwrap() {
+            "atlas" => IconOutput::Atlas,
+            "individual" => IconOutput::Individual,
+            _ => unreachable!(),
+        },
+        write_map: app.is_present("icon_map"),
+        light_directory: app.value_of("icon_light_dir").unwrap().to_owned(),
+        dark_directory: app.value_of("icon_dark_dir").unwrap().to_owned(),
+    };
+    if let Some(directory) = app.value_of_os("dump_lua") {
+        return dump_lua(
+            &paths,
+            Path::new(directory),
+            prune_level,
+            &prune_keep,
+            extract_interval,
+            icon_scale,
+            strict,
+            &mut reporter,
+        );
+    }
+    let icons_source = app
+        .value_of_os("icons_from")
+        .map(|path| {
+            let path = Path::new(path);
+            let game_data = load_game_data_from(path, strict, &mut reporter)?;
+            Ok::<_, io::Error>((game_data, path.with_file_name("game_icons.png")))
+        })
+        .transpose()?;
+    let mut manifest = Manifest::new();
+    let factorio_args = app
+        .values_of("factorio_arg")
+        .map(|args| args.map(str::to_owned).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for arg in &factorio_args {
+        let name = arg.split('=').next().unwrap();
+        if CONFLICTING_FACTORIO_ARGS.contains(&name) {
+            reporter.warn(
+                format!("factorio argument {} conflicts with how the extractor runs factorio", arg).into(),
+            );
+        }
+    }
+    let drop_untranslated = app.is_present("drop_untranslated");
 
-    fn to_io_error(err: &'static str) -> io::Error {
+    fn to_io_error(err: parsing::ParseError) -> io::Error {
         io::Error::new(io::ErrorKind::InvalidData, err)
     }
 
-    match app.value_of("stage").unwrap() {
+    let factorio_log = app.value_of_os("factorio_log");
+    let expected_version = app
+        .value_of("expected_version")
+        .unwrap()
+        .parse::<VersionRange>()
+        .unwrap();
+    let extract = |reporter: &mut SummaryReporter| match factorio_log {
+        Some(path) => Ok((extract_data_from_log(Path::new(path), reporter)?, None)),
+        None => {
+            let version = check_version(&paths, expected_version, strict, reporter)?;
+            Ok((extract_data(&paths, prune_level, &prune_keep, &factorio_args, reporter)?, version))
+        }
+    };
+    let transform_cache = app.value_of_os("transform_cache").map(TransformCache::new);
+    let parse_prototypes = |reporter: &mut SummaryReporter, prototypes: Vec<String>| {
+        let parse = |reporter: &mut SummaryReporter, prototypes: Vec<String>| {
+            let mut game_data =
+                data::parse_prototypes_with_reporter(prototypes, transform_options, reporter).map_err(to_io_error)?;
+            if drop_untranslated {
+                remove_untranslated(&mut game_data, reporter);
+            }
+            Ok(game_data)
+        };
+        match &transform_cache {
+            Some(cache) => {
+                let options = format!(
+                    "{:?} {} {:?}",
+                    transform_options.untranslated_descriptions, drop_untranslated, transform_options.max_records
+                );
+                cache.parse_with(prototypes, &options, reporter, parse)
+            }
+            None => parse(reporter, prototypes),
+        }
+    };
+    let transform_data = |reporter: &mut SummaryReporter, prototypes: Vec<String>| {
+        let mut game_data = parse_prototypes(reporter, prototypes)?;
+        if let Some((icons_source, atlas_path)) = &icons_source {
+            reuse_icons(&mut game_data, icons_source, atlas_path, reporter)?;
+        }
+        Ok(game_data)
+    };
+
+    let stage = app.value_of("stage").unwrap();
+    check_script_output_writable(&paths, stage)?;
+    if app.is_present("check_determinism") {
+        let mut run = || -> io::Result<Vec<determinism::Output>> {
+            match stage {
+                "transform_data" => {
+                    let prototypes = load_prototypes(&paths, &mut reporter)?;
+                    let game_data = transform_data(&mut reporter, prototypes)?;
+                    Ok(vec![determinism::Output {
+                        name: "game_data.json".to_owned(),
+                        contents: serde_json::ser::to_string_pretty(&game_data)?.into_bytes(),
+                    }])
+                }
+                "transform_icons" => {
+                    let game_data = load_game_data(&paths, strict, &mut reporter)?;
+                    let icon_directory = paths.script_output_directory.join("graphio_extracted_icons");
+                    let (game_data, icon_files) =
+                        transform_icons(&paths, &game_data, icon_directory, &atlas_layout, &icon_options, false, false, &mut reporter)?;
+                    let mut outputs = vec![determinism::Output {
+                        name: "game_data.json".to_owned(),
+                        contents: serde_json::ser::to_string_pretty(&game_data)?.into_bytes(),
+                    }];
+                    // The files are removed after being read, so that the
+                    // second run stores its files under the same names.
+                    for icon_file in icon_files {
+                        let name = icon_file
+                            .strip_prefix(&paths.script_output_directory)
+                            .unwrap_or(&icon_file)
+                            .to_string_lossy()
+                            .into_owned();
+                        outputs.push(determinism::Output {
+                            name,
+                            contents: fs::read(&icon_file)?,
+                        });
+                        fs::remove_file(&icon_file)?;
+                        if let Some(parent) = icon_file.parent() {
+                            if parent != paths.script_output_directory {
+                                let _ = fs::remove_dir(parent);
+                            }
+                        }
+                    }
+                    Ok(outputs)
+                }
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "only transform_data and transform_icons can be checked for determinism",
+                )),
+            }
+        };
+        let first = run()?;
+        let second = run()?;
+        return determinism::compare(&first, &second, &mut reporter);
+    }
+    let game_data = match stage {
         "all" => {
-            let prototypes = extract_data(&paths, prune_level)?;
-            let game_data = transform_data(prototypes, !no_transform_log).map_err(to_io_error)?;
-            let icon_directory = extract_icons(&paths, &game_data, extract_interval)?;
+            let (prototypes, version) = reporter.stage("extract_data", extract)?;
+            let mut game_data = reporter.stage("transform_data", |reporter| parse_prototypes(reporter, prototypes))?;
+            game_data.factorio_version = version.map(|version| version.to_string());
+            let icon_directory = reporter.stage("extract_icons", |reporter| {
+                extract_icons(&paths, &game_data, extract_interval, icon_scale, resume, &factorio_args, reporter)
+            })?;
             let _icon_directory_temp = TempDirectory::new(&icon_directory);
-            let game_data = transform_icons(&paths, &game_data, icon_directory, true)?;
-            store_game_data(&paths, &game_data, false)?;
+            let (game_data, icon_files) = reporter.stage("transform_icons", |reporter| {
+                transform_icons(&paths, &game_data, icon_directory, &atlas_layout, &icon_options, true, false, reporter)
+            })?;
+            for icon_file in icon_files {
+                manifest.add(icon_file);
+            }
+            manifest.add(store_game_data(&paths, &game_data, false, &mut reporter)?);
+            Some(game_data)
         }
         "data" => {
-            let prototypes = extract_data(&paths, prune_level)?;
-            let game_data = transform_data(prototypes, !no_transform_log).map_err(to_io_error)?;
-            store_game_data(&paths, &game_data, false)?;
+            let (prototypes, version) = reporter.stage("extract_data", extract)?;
+            let mut game_data = reporter.stage("transform_data", |reporter| transform_data(reporter, prototypes))?;
+            game_data.factorio_version = version.map(|version| version.to_string());
+            manifest.add(store_game_data(&paths, &game_data, false, &mut reporter)?);
+            Some(game_data)
         }
         "icons" => {
-            let game_data = load_game_data(&paths)?;
-            let icon_directory = extract_icons(&paths, &game_data, extract_interval)?;
+            let game_data = load_game_data(&paths, strict, &mut reporter)?;
+            let icon_directory = reporter.stage("extract_icons", |reporter| {
+                extract_icons(&paths, &game_data, extract_interval, icon_scale, resume, &factorio_args, reporter)
+            })?;
             let _icon_directory_temp = TempDirectory::new(&icon_directory);
-            let game_data = transform_icons(&paths, &game_data, icon_directory, true)?;
-            store_game_data(&paths, &game_data, true)?;
+            let (game_data, icon_files) = reporter.stage("transform_icons", |reporter| {
+                transform_icons(&paths, &game_data, icon_directory, &atlas_layout, &icon_options, true, true, reporter)
+            })?;
+            for icon_file in icon_files {
+                manifest.add(icon_file);
+            }
+            manifest.add(store_game_data(&paths, &game_data, true, &mut reporter)?);
+            Some(game_data)
         }
         "extract_data" => {
-            let prototypes = extract_data(&paths, prune_level)?;
-            store_prototypes(&paths, &prototypes)?;
+            let (prototypes, _) = reporter.stage("extract_data", extract)?;
+            manifest.add(store_prototypes(&paths, &prototypes, &mut reporter)?);
+            None
         }
         "transform_data" => {
-            let prototypes = load_prototypes(&paths)?;
-            let game_data = transform_data(prototypes, !no_transform_log).map_err(to_io_error)?;
-            store_game_data(&paths, &game_data, false)?;
+            let prototypes = load_prototypes(&paths, &mut reporter)?;
+            let game_data = reporter.stage("transform_data", |reporter| transform_data(reporter, prototypes))?;
+            manifest.add(store_game_data(&paths, &game_data, false, &mut reporter)?);
+            Some(game_data)
         }
         "extract_icons" => {
-            let game_data = load_game_data(&paths)?;
-            let icon_directory = extract_icons(&paths, &game_data, extract_interval)?;
-            println!(
+            let game_data = load_game_data(&paths, strict, &mut reporter)?;
+            let icon_directory = reporter.stage("extract_icons", |reporter| {
+                extract_icons(&paths, &game_data, extract_interval, icon_scale, resume, &factorio_args, reporter)
+            })?;
+            reporter.info(&format!(
                 "extracted icons to: {}",
                 icon_directory.as_os_str().to_string_lossy()
-            );
+            ));
+            None
         }
         "transform_icons" => {
-            let game_data = load_game_data(&paths)?;
+            let game_data = load_game_data(&paths, strict, &mut reporter)?;
             let mut icon_directory = paths.script_output_directory.clone();
             icon_directory.push("graphio_extracted_icons");
-            let game_data = transform_icons(&paths, &game_data, icon_directory, false)?;
-            store_game_data(&paths, &game_data, true)?;
+            let (game_data, icon_files) = reporter.stage("transform_icons", |reporter| {
+                transform_icons(&paths, &game_data, icon_directory, &atlas_layout, &icon_options, false, true, reporter)
+            })?;
+            for icon_file in icon_files {
+                manifest.add(icon_file);
+            }
+            manifest.add(store_game_data(&paths, &game_data, true, &mut reporter)?);
+            Some(game_data)
+        }
+        "self_test" => {
+            let game_data = load_game_data(&paths, strict, &mut reporter)?;
+            reporter.stage("self_test", |reporter| self_test::run(&game_data, reporter))?;
+            Some(game_data)
         }
         _ => unreachable!(),
+    };
+
+    if let Some(game_data) = &game_data {
+        if app.is_present("icon_coverage") {
+            report_icon_coverage(game_data, &mut reporter);
+        }
+        if let Some(matrix_path) = app.value_of_os("export_matrix") {
+            let matrix_path = Path::new(matrix_path);
+            export_crafting_matrix(game_data, matrix_path, &mut reporter)?;
+            manifest.add(matrix_path);
+        }
+    }
+
+    if let Some(manifest_path) = app.value_of_os("manifest") {
+        manifest.write(manifest_path)?;
+        reporter.info(&format!("stored manifest to: {}", manifest_path.to_string_lossy()));
+    }
+    if let Some(summary_path) = app.value_of_os("summary_json") {
+        reporter.write_summary(summary_path, game_data.as_ref(), &manifest)?;
+        reporter.info(&format!("stored summary to: {}", summary_path.to_string_lossy()));
+    }
+
+    Ok(())
+}
+
+fn report_icon_coverage(game_data: &GameData, reporter: &mut dyn Reporter) {
+    let coverage = game_data.icon_coverage_report();
+    reporter.info("icon coverage:");
+    for (name, collection) in coverage.collections() {
+        reporter.info(&format!(
+            "    {}: {}/{}",
+            name,
+            collection.with_icon,
+            collection.with_icon + collection.missing.len()
+        ));
+        for id in &collection.missing {
+            reporter.info(&format!("        missing: {}", id.str()));
+        }
     }
+}
+
+/// Writes the scripts that the extract_data and extract_icons stages would
+/// run. The icon extraction script is only written if there's game data.
+#[allow(clippy::too_many_arguments)]
+fn dump_lua(
+    paths: &FactorioPaths,
+    directory: &Path,
+    prune_level: PruneLevel,
+    prune_keep: &[String],
+    extract_interval: usize,
+    icon_scale: u32,
+    strict: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+
+    let export_path = directory.join("export_prototypes.lua");
+    fs::write(&export_path, get_export_script(prune_level, prune_keep))?;
+    reporter.info(&format!("export script stored at: {}", export_path.to_string_lossy()));
+
+    let game_data = match load_game_data(paths, strict, reporter) {
+        Ok(game_data) => game_data,
+        Err(err) => {
+            reporter.info(&format!("skipping icon extraction script, game data couldn't be loaded: {}", err));
+            return Ok(());
+        }
+    };
+    let extract_script = get_icon_extract_script(
+        &game_data,
+        "graphio_extracted_icons",
+        &HashSet::new(),
+        extract_interval,
+        icon_scale,
+    )
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let extract_path = directory.join("extract_icons.lua");
+    fs::write(&extract_path, extract_script)?;
+    reporter.info(&format!("icon extraction script stored at: {}", extract_path.to_string_lossy()));
+    Ok(())
+}
+
+/// Writes the crafting matrix. As CSV, there's a column per machine, and a row
+/// per recipe, with an `x` marking which machines can craft the recipe.
+fn export_crafting_matrix(game_data: &GameData, path: &Path, reporter: &mut dyn Reporter) -> io::Result<()> {
+    let matrix = game_data.crafting_matrix();
+    let is_csv = path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("csv"))
+        .unwrap_or(false);
+    let serialized = if is_csv {
+        fn csv_field(s: &str) -> String {
+            if s.contains(&[',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", s.replace('"', "\"\""))
+            } else {
+                s.to_owned()
+            }
+        }
+        let machines = game_data
+            .sorted_views()
+            .machines
+            .into_iter()
+            .map(|machine| machine.id)
+            .collect::<Vec<_>>();
 
+        let mut csv = String::from("recipe");
+        for machine in &machines {
+            csv.push(',');
+            csv.push_str(&csv_field(machine.str()));
+        }
+        csv.push_str("\r\n");
+        for (recipe, crafted_in) in &matrix {
+            csv.push_str(&csv_field(recipe.str()));
+            for machine in &machines {
+                csv.push(',');
+                if crafted_in.contains(machine) {
+                    csv.push('x');
+                }
+            }
+            csv.push_str("\r\n");
+        }
+        csv
+    } else {
+        let rows = matrix
+            .iter()
+            .map(|(recipe, crafted_in)| {
+                serde_json::json!({
+                    "recipe": recipe.str(),
+                    "machines": crafted_in.iter().map(|machine| machine.str()).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::ser::to_string_pretty(&rows)?
+    };
+    fs::write(path, serialized.as_bytes())?;
+    reporter.info(&format!("stored crafting matrix to: {}", path.to_string_lossy()));
     Ok(())
 }
 
-fn store_prototypes(paths: &FactorioPaths, prototypes: &Vec<String>) -> io::Result<()> {
+fn store_prototypes(
+    paths: &FactorioPaths,
+    prototypes: &Vec<String>,
+    reporter: &mut dyn Reporter,
+) -> io::Result<PathBuf> {
     let serialized = serde_json::ser::to_string_pretty(&prototypes)?;
     let mut output_dir = TempDirectory::ensure(&paths.script_output_directory)?;
     let output_file = write_file_safely(
@@ -176,25 +959,30 @@ fn store_prototypes(paths: &FactorioPaths, prototypes: &Vec<String>) -> io::Resu
         serialized.as_bytes(),
     )?;
     output_dir.release();
-    println!(
+    reporter.info(&format!(
         "stored prototype data to: {}",
         output_file.as_os_str().to_string_lossy()
-    );
-    Ok(())
+    ));
+    Ok(output_file)
 }
 
-fn load_prototypes(paths: &FactorioPaths) -> io::Result<Vec<String>> {
+fn load_prototypes(paths: &FactorioPaths, reporter: &mut dyn Reporter) -> io::Result<Vec<String>> {
     let mut input_file_path = paths.script_output_directory.clone();
     input_file_path.push("prototypes.json");
-    println!(
+    reporter.info(&format!(
         "loading prototype data from: {}",
         input_file_path.as_os_str().to_string_lossy()
-    );
+    ));
     let input_file = fs::read(input_file_path)?;
     Ok(serde_json::de::from_slice(&input_file)?)
 }
 
-fn store_game_data(paths: &FactorioPaths, game_data: &GameData, overwrite: bool) -> io::Result<()> {
+fn store_game_data(
+    paths: &FactorioPaths,
+    game_data: &GameData,
+    overwrite: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<PathBuf> {
     let serialized = serde_json::ser::to_string_pretty(&game_data)?;
     let mut output_dir = TempDirectory::ensure(&paths.script_output_directory)?;
     let output_file = if overwrite {
@@ -211,25 +999,137 @@ fn store_game_data(paths: &FactorioPaths, game_data: &GameData, overwrite: bool)
         )?
     };
     output_dir.release();
-    println!(
+    reporter.info(&format!(
         "stored game data to: {}",
         output_file.as_os_str().to_string_lossy()
-    );
-    Ok(())
+    ));
+    Ok(output_file)
 }
 
-fn load_game_data(paths: &FactorioPaths) -> io::Result<GameData> {
+fn load_game_data(paths: &FactorioPaths, strict: bool, reporter: &mut dyn Reporter) -> io::Result<GameData> {
     let mut input_file_path = paths.script_output_directory.clone();
     input_file_path.push("game_data.json");
-    println!(
-        "loading prototype data from: {}",
+    load_game_data_from(input_file_path, strict, reporter)
+}
+
+/// Loads game data, ignoring any fields that aren't understood, unless
+/// `strict` is set, in which case they cause an error.
+fn load_game_data_from<P: AsRef<Path>>(
+    input_file_path: P,
+    strict: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<GameData> {
+    let input_file_path = input_file_path.as_ref();
+    reporter.info(&format!(
+        "loading game data from: {}",
         input_file_path.as_os_str().to_string_lossy()
-    );
+    ));
     let input_file = fs::read(input_file_path)?;
-    Ok(serde_json::de::from_slice(&input_file)?)
+    strict::parse_game_data(&input_file, strict, reporter)
+}
+
+/// Loads the game data and tile set stored in `directory`.
+fn load_data_set(
+    directory: &Path,
+    strict: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<(GameData, image::RgbaImage)> {
+    let game_data = load_game_data_from(directory.join("game_data.json"), strict, reporter)?;
+    let atlas = image::open(directory.join("game_icons.png"))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .to_rgba();
+    Ok((game_data, atlas))
+}
+
+/// Merges the game data and tile sets stored in `directories` into the ones
+/// in the script output directory, and stores the result next to them.
+fn merge_data_sets(
+    paths: &FactorioPaths,
+    directories: &[&Path],
+    strict: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<()> {
+    let base = load_data_set(&paths.script_output_directory, strict, reporter)?;
+    let others = directories
+        .iter()
+        .map(|directory| load_data_set(directory, strict, reporter))
+        .collect::<io::Result<Vec<_>>>()?;
+    let (game_data, atlas) = atlas::merge_with_atlases(base, others, reporter)?;
+    store_tile_set(paths, atlas.dimensions(), atlas.into_raw(), false, reporter)?;
+    store_game_data(paths, &game_data, false, reporter)?;
+    Ok(())
+}
+
+/// Takes over the tile set of `source`, stored at `atlas_path`, and assigns
+/// the icons like `reassign_icons`.
+fn reuse_icons(
+    game_data: &mut GameData,
+    source: &GameData,
+    atlas_path: &Path,
+    reporter: &mut dyn Reporter,
+) -> io::Result<()> {
+    let tile_metadata = source.tile_metadata.as_ref().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "the game data to reuse icons from has no tile set")
+    })?;
+    reassign_icons(game_data, tile_metadata, atlas_path)?;
+    reporter.info(&format!(
+        "reused {} icon(s) from: {}",
+        tile_metadata.tile_count,
+        atlas_path.to_string_lossy()
+    ));
+    Ok(())
+}
+
+fn remove_untranslated(game_data: &mut GameData, reporter: &mut dyn Reporter) {
+    let removed = game_data.remove_hidden(data::filters::is_untranslated);
+    reporter.info(&format!(
+        "removed {} untranslated object(s), including recipes referring to them",
+        removed.len()
+    ));
 }
 
-fn extract_data(paths: &FactorioPaths, prune_level: PruneLevel) -> io::Result<Vec<String>> {
+fn validate_game_data(game_data: &GameData, reporter: &mut dyn Reporter) -> io::Result<()> {
+    let (errors, warnings) = game_data.check_all_with_warnings();
+    for warning in warnings {
+        reporter.warn(warning.into());
+    }
+    if errors.is_empty() {
+        reporter.info("game data is valid");
+        return Ok(());
+    }
+    for error in &errors {
+        reporter.info(&error.to_string());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("game data failed validation with {} error(s)", errors.len()),
+    ))
+}
+
+/// Arguments that make Factorio do something other than what the extractor
+/// runs it for, which are warned about when passed with `--factorio_arg`.
+const CONFLICTING_FACTORIO_ARGS: &[&str] = &[
+    "--scenario2map",
+    "--map2scenario",
+    "--create",
+    "--start-server",
+    "--start-server-load-scenario",
+    "--start-server-load-latest",
+    "--load-game",
+    "--load-scenario",
+    "--mp-connect",
+    "--benchmark",
+    "--version",
+    "--help",
+];
+
+fn extract_data(
+    paths: &FactorioPaths,
+    prune_level: PruneLevel,
+    prune_keep: &[String],
+    factorio_args: &[String],
+    reporter: &mut dyn Reporter,
+) -> io::Result<Vec<String>> {
     let _scenarios_directory = TempDirectory::ensure(&paths.scenarios_directory)?;
 
     let scenario_directory = TempDirectory::new(create_dir_safely(
@@ -248,46 +1148,105 @@ fn extract_data(paths: &FactorioPaths, prune_level: PruneLevel) -> io::Result<Ve
     let mut control_lua_path = scenario_path;
     control_lua_path.push("control.lua");
 
-    let export_script = get_export_script(prune_level);
+    let export_script = get_export_script(prune_level, prune_keep);
     fs::write(&control_lua_path, export_script)?;
     let _control_lua_file = TempFile::new(control_lua_path);
 
-    println!("extracting prototypes by running factorio, this may take a while...");
+    reporter.info("extracting prototypes by running factorio, this may take a while...");
+
+    let output = scenario_command(paths.executable()?, &scenario_name, factorio_args).output()?;
 
-    let output = Command::new(&paths.executable)
+    read_records(output.stdout, reporter)
+}
+
+/// The command that runs the game with the export scenario, passing the
+/// extra arguments after the ones that load the scenario.
+fn scenario_command(executable: &Path, scenario_name: &str, factorio_args: &[String]) -> Command {
+    let mut command = Command::new(executable);
+    command
         .arg("--scenario2map")
-        .arg(&scenario_name)
+        .arg(scenario_name)
+        .args(factorio_args)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()?;
+        .stderr(Stdio::piped());
+    command
+}
 
-    let output = String::from_utf8(output.stdout)
+/// Detects the version of the game, warning if it isn't one of the `expected`
+/// versions, as the records might not be laid out the way the parser expects.
+/// If `strict` is set, this fails instead.
+fn check_version(
+    paths: &FactorioPaths,
+    expected: VersionRange,
+    strict: bool,
+    reporter: &mut SummaryReporter,
+) -> io::Result<Option<Version>> {
+    let check = version::check_version(|| version::run_version_command(paths), expected)?;
+    if let Some(version) = check.version() {
+        reporter.info(&format!("detected factorio version: {}", version));
+    }
+    let message = match check {
+        VersionCheck::Expected(version) => return Ok(Some(version)),
+        VersionCheck::Unexpected(version) => format!(
+            "factorio {} isn't one of the expected versions ({}), extraction might fail",
+            version, expected
+        ),
+        VersionCheck::Unrecognized => {
+            "couldn't find the version of factorio in the output of --version, extraction might fail".to_owned()
+        }
+    };
+    if strict {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    reporter.warn(message.into());
+    Ok(check.version())
+}
+
+/// Reads the records from the output of a previous run of the export script,
+/// such as the log file that Factorio writes.
+fn extract_data_from_log(path: &Path, reporter: &mut dyn Reporter) -> io::Result<Vec<String>> {
+    reporter.info(&format!("reading prototypes from: {}", path.to_string_lossy()));
+    read_records(fs::read(path)?, reporter)
+}
+
+/// Extracts the records from the output of the export script, which are
+/// framed by the delimiters, ignoring anything else that the game logged.
+fn read_records(output: Vec<u8>, reporter: &mut dyn Reporter) -> io::Result<Vec<String>> {
+    let output = String::from_utf8(output)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
         .replace("\r\n", "\n");
 
-    println!("stripping important information...");
+    reporter.info("stripping important information...");
 
-    let marker_start = output.find('\x01').ok_or(io::Error::new(
+    use crate::parsing::delimiters;
+    let marker_start = output.find(delimiters::START).ok_or(io::Error::new(
         io::ErrorKind::InvalidData,
         "no start marker in output",
     ))?;
-    let marker_end = output.rfind('\x04').ok_or(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "no end marker in output",
-    ))?;
+    // The end marker is only searched for after the start marker, so that a
+    // stray end marker logged before the output doesn't count.
+    let (before, output) = output.split_at(marker_start + delimiters::START.len_utf8());
+    let marker_end = output.rfind(delimiters::FINISH).ok_or_else(|| {
+        let message = if before.contains(delimiters::FINISH) {
+            "no end marker after the start marker in output"
+        } else {
+            "no end marker in output"
+        };
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    })?;
 
-    let output = &output[marker_start + 1..marker_end];
+    let output = &output[..marker_end];
     let lines: Vec<String> = output
         .chars()
         .batching(|it| {
             while let Some(x) = it.next() {
-                if x != '\x02' {
+                if x != delimiters::RECORD_START {
                     continue;
                 }
                 let mut res = String::new();
                 while let Some(y) = it.next() {
-                    if y == '\x03' {
+                    if y == delimiters::RECORD_END {
                         return Some(res);
                     }
                     res.push(y);
@@ -297,370 +1256,85 @@ fn extract_data(paths: &FactorioPaths, prune_level: PruneLevel) -> io::Result<Ve
             None
         })
         .collect();
+    if lines.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Factorio produced no prototype records, check that the scenario ran",
+        ));
+    }
 
-    println!("done");
+    reporter.info("done");
 
     Ok(lines)
 }
 
-fn get_export_script(prune_level: PruneLevel) -> String {
+fn get_export_script(prune_level: PruneLevel, prune_keep: &[String]) -> String {
     const EXPORT_SCRIPT: &'static str = include_str!("export_prototypes.lua");
-    let mut export_script = String::with_capacity(EXPORT_SCRIPT.len() + 22);
+    let mut export_script = String::with_capacity(EXPORT_SCRIPT.len() + 64);
     export_script.push_str("local prune_level = ");
-    export_script.push(match prune_level {
-        PruneLevel::NoPruning => '0',
-        PruneLevel::BasicPruning => '1',
-        PruneLevel::ExtensivePruning => '2',
-    });
-    export_script.push('\n');
+    export_script.push(prune_level.as_lua_digit());
+    export_script.push_str("\nlocal prune_keep = {");
+    for name in prune_keep {
+        export_script.push_str(" [");
+        push_lua_string(&mut export_script, name);
+        export_script.push_str("] = true,");
+    }
+    export_script.push_str(" }");
+    export_script.push_str("\nlocal record_format_version = ");
+    export_script.push_str(&parsing::RECORD_FORMAT_VERSION.to_string());
+    export_script.push_str("\nlocal delimiters = {");
+    {
+        use crate::parsing::delimiters::*;
+        let entries = [
+            ("start", START),
+            ("record_start", RECORD_START),
+            ("record_end", RECORD_END),
+            ("finish", FINISH),
+            ("escape", ESCAPE),
+            ("separator", SEPARATOR),
+        ];
+        for (name, delimiter) in entries.iter() {
+            export_script.push_str(&format!(" {} = '\\{}',", name, *delimiter as u32));
+        }
+        export_script.push_str(&format!(" escape_offset = {} }}\n", ESCAPE_OFFSET));
+    }
     export_script.push_str(EXPORT_SCRIPT);
     export_script
 }
 
-fn transform_data(lines: Vec<String>, log_entries: bool) -> Result<GameData, &'static str> {
-    let mut iter = lines.into_iter();
-
-    let (machine_count, beacon_count, recipe_count, item_count, fluid_count) = {
-        let lengths = iter.next().ok_or("unexpected end")?;
-        let lengths = lengths
-            .split('\x1f')
-            .map(|entry| entry.parse())
-            .collect::<Result<Vec<usize>, _>>()
-            .map_err(|_| "cannot read lengths from the first line")?;
-        if lengths.len() != 5 {
-            return Err("expected 5 lengths on the first line");
-        }
-
-        (lengths[0], lengths[1], lengths[2], lengths[3], lengths[4])
-    };
-
-    let (items, fluids, recipes, machines, beacons, modules) = {
-        use self::data::*;
-        use crate::num_traits::identities::Zero;
-        use crate::parsing::*;
-        let iter = &mut iter;
-
-        // Load primary data (machines, recipes, items, and fluids)
-
-        let mut machines = (0..machine_count)
-            .map(|_| {
-                let id = MachineID(read_str(iter)?);
-                let metadata = read_metadata(iter)?;
-                let crafting_speed = read_ratio(iter)?;
-                let energy_consumption = read_ratio(iter)?;
-                let energy_drain = read_ratio(iter)?;
-                let module_slots = read_int(iter)?;
-
-                let allowed_effects = read_allowed_effects(iter)?;
-
-                if log_entries {
-                    println!(
-                        "machine {} (\"{}\")",
-                        id.0.str(),
-                        metadata.localised_name.str()
-                    );
-                }
-
-                Ok((
-                    id,
-                    (
-                        Machine {
-                            id: id,
-                            metadata,
-                            crafting_speed,
-                            energy_consumption,
-                            energy_drain,
-                            module_slots,
-                            supported_modules: HashSet::new(),
-                        },
-                        allowed_effects,
-                    ),
-                ))
-            })
-            .collect::<Result<HashMap<_, _>>>()?;
-        if machines.len() != machine_count {
-            return Err("duplicate machines in exported data set");
-        }
-
-        let mut beacons = (0..beacon_count)
-            .map(|_| {
-                let id = BeaconID(read_str(iter)?);
-                let metadata = read_metadata(iter)?;
-                let distribution_effectivity = read_ratio(iter)?;
-                let allowed_effects = read_allowed_effects(iter)?;
-
-                if log_entries {
-                    println!(
-                        "beacon {} (\"{}\")",
-                        id.0.str(),
-                        metadata.localised_name.str()
-                    );
-                }
-
-                Ok((
-                    id,
-                    (
-                        Beacon {
-                            id,
-                            metadata,
-                            distribution_effectivity,
-                            supported_modules: HashSet::new(),
-                        },
-                        allowed_effects,
-                    ),
-                ))
-            })
-            .collect::<Result<HashMap<_, _>>>()?;
-
-        let mut recipes = (0..recipe_count).map(|_| {
-            let id = RecipeID(read_str(iter)?);
-            let metadata = read_metadata(iter)?;
-            let time = read_ratio(iter)?;
-
-            let ingredient_count = read_usize(iter)?;
-            let ingredients = (0..ingredient_count).map(|_| {
-
-                let kind = read_line(iter)?;
-                let id = read_str(iter)?;
-                let amount = read_ratio(iter)?;
-                let catalyst_amount = read_ratio(iter)?;
-
-                let resource = match kind.as_str() {
-                    "item" => IngredientResource::Item {
-                            id: ItemID(id),
-                        },
-                    "fluid" => {
-                        let flags = read_line(iter)?;
-                        let flags = flags.as_bytes();
-                        if flags.len() != 2 {
-                            return Err("expected optional field flags in ingredient fluid to be 2 bits")
-                        }
-                        let minimum_temperature = match flags[0] {
-                            b'0' => None,
-                            b'1' => Some(read_ratio(iter)?),
-                            _ => return Err("expected optional field flags in ingredient fluid to be 0 or 1"),
-                        };
-                        let maximum_temperature = match flags[1] {
-                            b'0' => None,
-                            b'1' => Some(read_ratio(iter)?),
-                            _ => return Err("expected optional field flags in ingredient fluid to be 0 or 1"),
-                        };
-                        IngredientResource::Fluid {
-                            id: FluidID(id),
-                            minimum_temperature,
-                            maximum_temperature,
-                        }
-                    },
-                    _ => return Err("unknown recipe ingredient kind")
-                };
-
-                Ok(Ingredient {
-                    resource,
-                    amount,
-                    catalyst_amount,
-                })
-            })
-                .collect::<Result<Vec<_>>>()?;
-
-            let product_count = read_usize(iter)?;
-            let products = (0..product_count).map(|_| {
-                let kind = read_line(iter)?;
-                let id = read_str(iter)?;
-                let resource = match kind.as_str() {
-                    "item" => ProductResource::Item{ 
-                        id: ItemID(id),
-                    },
-                    "fluid" => ProductResource::Fluid {
-                        id: FluidID(id),
-                        temperature: read_ratio(iter)?,
-                    },
-                    _ => return Err("unknown recipe product kind"),
-                };
-
-                let kind = read_line(iter)?;
-                let amount = match kind.as_str() {
-                    "fixed" =>{
-                        let amount = read_ratio(iter)?;
-                        let catalyst_amount = read_ratio(iter)?;
-                        ProductAmount::Fixed {
-                            amount,
-                            catalyst_amount,
-                        }
-                    },
-                    "probability" => {
-                        let amount_min = read_ratio(iter)?;
-                        let amount_max = read_ratio(iter)?;
-                        let probability = read_ratio(iter)?;
-                        ProductAmount::Probability {
-                            amount_min,
-                            amount_max,
-                            probability,
-                        }
-                    },
-                    _ => return Err("unknown recipe product amount kind"),
-                };
-
-                Ok(Product {
-                    resource,
-                    amount,
-                })
-            }).collect::<Result<Vec<_>>>()?;
-
-            let crafted_in_count = read_usize(iter)?;
-            let crafted_in = (0..crafted_in_count)
-                .map(|_| Ok(MachineID(read_str(iter)?)))
-                .collect::<Result<HashSet<_>>>()?;
-
-            if log_entries {
-                println!("recipe {} (\"{}\")",
-                    id.str(),
-                    metadata.localised_name.str()
-                );
-            }
-
-            Ok(Recipe {
-                id,
-                metadata,
-                time,
-                ingredients,
-                products,
-                crafted_in,
-                supported_modules: HashSet::new(),
-            })
-        }).collect::<Result<HashSet<Recipe>>>()?;
-        if recipes.len() != recipe_count {
-            return Err("duplicate recipes in exported data set");
-        }
-
-        let mut modules = HashSet::new();
-
-        let items = (0..item_count)
-            .map(|_| {
-                let id = ItemID(read_str(iter)?);
-                let metadata = read_metadata(iter)?;
-
-                let is_module = read_line(iter)?;
-                let is_module = match is_module.as_str() {
-                    "0" => false,
-                    "1" => true,
-                    _ => return Err("expected module flag on item to be 0 or 1"),
-                };
-                if is_module {
-                    let modifier_energy = read_ratio(iter)?;
-                    let modifier_speed = read_ratio(iter)?;
-                    let modifier_productivity = read_ratio(iter)?;
-                    let modifier_pollution = read_ratio(iter)?;
-                    modules.insert(Module {
-                        id,
-                        modifier_energy,
-                        modifier_speed,
-                        modifier_productivity,
-                        modifier_pollution,
-                    });
-
-                    let has_limitations = read_line(iter)?;
-                    let has_limitations = match has_limitations.as_str() {
-                        "0" => false,
-                        "1" => true,
-                        _ => return Err("expected limitations flag on item to be 0 or 1"),
-                    };
-
-                    let limitations: HashSet<RecipeID> = if has_limitations {
-                        let limitation_count = read_usize(iter)?;
-                        (0..limitation_count)
-                            .map(|_| Ok(RecipeID(read_str(iter)?)))
-                            .collect::<Result<_>>()?
-                    } else {
-                        recipes.iter().map(|recipe| recipe.id).collect()
-                    };
-
-                    for limitation in limitations {
-                        let mut recipe = recipes
-                            .take(&limitation)
-                            .ok_or("module limitation contains non-existent recipe")?;
-                        recipe.supported_modules.insert(id);
-                        recipes.insert(recipe);
-                    }
-                }
-
-                if log_entries {
-                    println!("item {} (\"{}\")", id.str(), metadata.localised_name.str());
-                }
-
-                Ok(Item { id, metadata })
-            })
-            .collect::<Result<HashSet<_>>>()?;
-        if items.len() != item_count {
-            return Err("duplicate items in exported data set");
-        }
-
-        let fluids = (0..fluid_count)
-            .map(|_| {
-                let id = FluidID(read_str(iter)?);
-                let metadata = read_metadata(iter)?;
-
-                if log_entries {
-                    println!("fluid {} (\"{}\")", id.str(), metadata.localised_name.str());
-                }
-
-                Ok(Fluid { id, metadata })
-            })
-            .collect::<Result<HashSet<_>>>()?;
-        if fluids.len() != fluid_count {
-            return Err("duplicate fluids in exported data set");
-        }
-
-        // Combine data
-        fn get_allowed_modules(
-            modules: &HashSet<Module>,
-            allowed_effects: &AllowedEffects,
-        ) -> HashSet<ItemID> {
-            modules
-                .iter()
-                .filter(|module| {
-                    (allowed_effects.energy || module.modifier_energy.is_zero())
-                        && (allowed_effects.speed || module.modifier_speed.is_zero())
-                        && (allowed_effects.productivity || module.modifier_productivity.is_zero())
-                        && (allowed_effects.pollution || module.modifier_pollution.is_zero())
-                })
-                .map(|module| module.id)
-                .collect()
-        }
-
-        for (_, (machine, allowed_effects)) in machines.iter_mut() {
-            machine.supported_modules = get_allowed_modules(&modules, allowed_effects);
-        }
-        for (_, (beacon, allowed_effects)) in beacons.iter_mut() {
-            beacon.supported_modules = get_allowed_modules(&modules, allowed_effects);
-        }
-        let machines = machines
-            .into_iter()
-            .map(|(_, (machine, _))| machine)
-            .collect::<HashSet<Machine>>();
-        let beacons = beacons
-            .into_iter()
-            .map(|(_, (beacon, _))| beacon)
-            .collect::<HashSet<Beacon>>();
-
-        (items, fluids, recipes, machines, beacons, modules)
-    };
-
-    Ok(GameData {
-        tile_metadata: None,
-        items,
-        fluids,
-        recipes,
-        machines,
-        beacons,
-        modules,
-    })
-}
-
 fn extract_icons(
     paths: &FactorioPaths,
     game_data: &GameData,
     extract_interval: usize,
+    icon_scale: u32,
+    resume: bool,
+    factorio_args: &[String],
+    reporter: &mut dyn Reporter,
 ) -> io::Result<PathBuf> {
+    let mut script_output_directory = TempDirectory::ensure(&paths.script_output_directory)?;
+    // When resuming, the icons are written to the same directory as before,
+    // which is also where transform_icons looks for them.
+    let icon_directory = if resume {
+        let mut path = paths.script_output_directory.clone();
+        path.push("graphio_extracted_icons");
+        TempDirectory::ensure(path)?
+    } else {
+        TempDirectory::new(create_dir_safely(
+            &paths.script_output_directory,
+            "graphio_extracted_icons",
+        )?)
+    };
+    let captured = if resume {
+        find_captured_icons(game_data, icon_directory.path())
+    } else {
+        HashSet::new()
+    };
+    if resume && game_data.all_ids().all(|id| captured.contains(&id)) {
+        reporter.info("all icons were already extracted");
+        script_output_directory.release();
+        return Ok(icon_directory.release_into());
+    }
+
     let _scenarios_directory = TempDirectory::ensure(&paths.scenarios_directory)?;
     let scenario_directory = TempDirectory::new(create_dir_safely(
         &paths.scenarios_directory,
@@ -668,7 +1342,7 @@ fn extract_icons(
     )?);
 
     let scenario_path = scenario_directory.path().clone();
-    println!(
+    reporter.info(&format!(
         "please start a new game with scenario {}",
         scenario_path
             .iter()
@@ -676,13 +1350,8 @@ fn extract_icons(
             .unwrap()
             .to_os_string()
             .to_string_lossy()
-    );
+    ));
 
-    let mut script_output_directory = TempDirectory::ensure(&paths.script_output_directory)?;
-    let icon_directory = TempDirectory::new(create_dir_safely(
-        &paths.script_output_directory,
-        "graphio_extracted_icons",
-    )?);
     let icon_directory_name = icon_directory
         .path()
         .iter()
@@ -692,16 +1361,22 @@ fn extract_icons(
         .to_string_lossy()
         .into_owned();
 
-    let extraction_script =
-        get_icon_extract_script(&game_data, &icon_directory_name, extract_interval)
-            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let extraction_script = get_icon_extract_script(
+        &game_data,
+        &icon_directory_name,
+        &captured,
+        extract_interval,
+        icon_scale,
+    )
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
 
     let mut control_lua_path = scenario_path;
     control_lua_path.push("control.lua");
     fs::write(&control_lua_path, extraction_script.as_bytes())?;
     let _control_lua_file = TempFile::new(control_lua_path);
 
-    let output = Command::new(&paths.executable)
+    let output = Command::new(paths.executable()?)
+        .args(factorio_args)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -722,10 +1397,151 @@ fn extract_icons(
     Ok(icon_directory.release_into())
 }
 
+/// The directories that the icons are extracted to, within both the light
+/// and dark directory.
+const ICON_CATEGORIES: &[&str] = &["items", "fluids", "recipes", "entities"];
+
+/// The directory that the icon of an object is extracted to.
+fn icon_category(id: ID) -> &'static str {
+    match id {
+        ID::Item(_) => "items",
+        ID::Fluid(_) => "fluids",
+        ID::Recipe(_) => "recipes",
+        ID::Machine(_) | ID::Beacon(_) => "entities",
+    }
+}
+
+/// The objects in `ids` in the order in which transform_icons assigns their
+/// tiles, paired with their icon category and ID string, which they're
+/// sorted by.
+///
+/// This is a canonical order, so that identical images always share the
+/// same index, regardless of the order in which the objects or categories
+/// are listed. Machines and beacons share a category, so a machine comes
+/// before a beacon with the same name, which also share their icon.
+fn icon_packing_order(ids: impl IntoIterator<Item = ID>) -> Vec<(&'static str, &'static str, ID)> {
+    let mut sorted = ids
+        .into_iter()
+        .map(|id| (icon_category(id), id.str(), id))
+        .collect::<Vec<_>>();
+    sorted.sort_by_key(|&(category, s, id)| (category, s, id.sort_key()));
+    sorted
+}
+
+/// Assigns every object the icon that transform_icons gave it when packing
+/// the tile set at `atlas_path`, described by `tile_metadata`, without
+/// loading the icons again. The tiles are assigned in `icon_packing_order`.
+///
+/// transform_icons also packs identical images that belong to different
+/// names into a single tile, which can't be told apart without the images.
+/// So this requires a tile for every distinct category and name, and the
+/// tile set to be as large as `tile_metadata` says.
+fn reassign_icons(game_data: &mut GameData, tile_metadata: &data::TileMetadata, atlas_path: &Path) -> io::Result<()> {
+    let atlas_size = image::open(atlas_path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+        .to_rgba()
+        .dimensions();
+    if atlas_size != tile_metadata.image_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "expected the tile set to be {}x{}, but it's {}x{}",
+                tile_metadata.image_size.0, tile_metadata.image_size.1, atlas_size.0, atlas_size.1
+            ),
+        ));
+    }
+
+    let mut icons: HashMap<ID, usize> = HashMap::new();
+    let mut distinct = 0;
+    let mut previous: Option<(&str, &str)> = None;
+    for (category, s, id) in icon_packing_order(game_data.all_ids()) {
+        if previous != Some((category, s)) {
+            previous = Some((category, s));
+            distinct += 1;
+        }
+        icons.insert(id, distinct - 1);
+    }
+    if distinct != tile_metadata.tile_count as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the tile set has {} tile(s), but there are {} distinct icon(s), run transform_icons instead",
+                tile_metadata.tile_count, distinct
+            ),
+        ));
+    }
+
+    game_data.tile_metadata = Some(tile_metadata.clone());
+    game_data
+        .modify_metadata::<(), _>(|id, meta| {
+            let icon = Some(data::Icon::new(icons[&id]));
+            Ok(data::Metadata { icon, ..*meta })
+        })
+        .unwrap();
+    Ok(())
+}
+
+/// Finds the objects of which both the light and dark icon are already
+/// present in `icon_directory`, from a previous extract_icons.
+fn find_captured_icons(game_data: &GameData, icon_directory: &Path) -> HashSet<ID> {
+    let is_captured = |category: &str, name: &str| {
+        let file_name = format!("{}.png", name);
+        ["light", "dark"].iter().all(|background| {
+            icon_directory
+                .join(background)
+                .join(category)
+                .join(&file_name)
+                .is_file()
+        })
+    };
+    game_data
+        .all_ids()
+        .filter(|&id| is_captured(icon_category(id), id.str()))
+        .collect()
+}
+
+/// The most names that are written in a single function of a generated
+/// script. Lua refers to at most 2^18 constants in a function directly,
+/// which this stays well below.
+const LUA_NAMES_PER_FUNCTION: usize = 10_000;
+
+/// Writes `s` as a quoted Lua string literal.
+fn push_lua_string(out: &mut String, s: &str) {
+    fn bits_4_to_hex_char(b: u8) -> char {
+        let b = b & 0x0f;
+        (if b < 0xa { b + b'0' } else { b - 0xa + b'a' }) as char
+    }
+    out.push('\'');
+    for b in s.bytes() {
+        match b {
+            b'\x07' => out.push_str("\\a"),
+            b'\x08' => out.push_str("\\b"),
+            b'\x0C' => out.push_str("\\f"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b'\x0B' => out.push_str("\\v"),
+            b'\\' => out.push_str("\\\\"),
+            b'\'' => out.push_str("\\'"),
+            x if x >= 0x20 && x < 0x7f => out.push(x as char),
+            x => {
+                out.push_str("\\x");
+                out.push(bits_4_to_hex_char(x >> 4));
+                out.push(bits_4_to_hex_char(x));
+            }
+        }
+    }
+    out.push('\'');
+}
+
+/// Generates the icon extraction script, which captures the icons of every
+/// object in `game_data`, except for those in `captured`.
 fn get_icon_extract_script(
     game_data: &GameData,
     output_directory_name: &str,
+    captured: &HashSet<ID>,
     extract_interval: usize,
+    icon_scale: u32,
 ) -> Result<String, &'static str> {
     const EXTRACT_IMAGES: &'static str = include_str!("extract_icons.lua");
     let mut extract_script = String::new();
@@ -734,295 +1550,164 @@ fn get_icon_extract_script(
     extract_script.push_str(output_directory_name);
     extract_script.push_str("'\nlocal extract_interval = ");
     extract_script.push_str(&extract_interval.to_string());
+    extract_script.push_str("\nlocal icon_scale = ");
+    extract_script.push_str(&icon_scale.to_string());
     extract_script.push_str("\n\n");
 
-    fn bits_4_to_hex_char(b: u8) -> char {
-        let b = b & 0x0f;
-        (if b < 0xa { b + b'0' } else { b - 0xa + b'a' }) as char
-    }
     fn write(out: &mut String, line: &str) -> () {
-        out.push_str("        '");
-        for b in line.bytes() {
-            match b {
-                b'\x07' => out.push_str("\\a"),
-                b'\x08' => out.push_str("\\b"),
-                b'\x0C' => out.push_str("\\f"),
-                b'\n' => out.push_str("\\n"),
-                b'\r' => out.push_str("\\r"),
-                b'\t' => out.push_str("\\t"),
-                b'\x0B' => out.push_str("\\v"),
-                b'\\' => out.push_str("\\\\"),
-                b'\'' => out.push_str("\\'"),
-                x if x >= 0x20 && x < 0x7f => out.push(x as char),
-                x => {
-                    out.push_str("\\x");
-                    out.push(bits_4_to_hex_char(x >> 4));
-                    out.push(bits_4_to_hex_char(x));
-                }
-            }
-        }
-        out.push_str("',\n");
+        out.push_str("    ");
+        push_lua_string(out, line);
+        out.push_str(",\n");
     }
 
-    {
-        let extract_script = &mut extract_script;
-        extract_script.push_str("local extract_data = {\n    items = {\n");
-        let mut any = false;
-        for item in &game_data.items {
-            any = true;
-            write(extract_script, item.id.str());
-        }
-        extract_script.push_str("    },\n    fluids = {\n");
-        for fluid in &game_data.fluids {
-            any = true;
-            write(extract_script, fluid.id.str());
-        }
-        extract_script.push_str("    },\n    recipes = {\n");
-        for recipe in &game_data.recipes {
-            any = true;
-            write(extract_script, recipe.id.str());
-        }
-        extract_script.push_str("    },\n    entities = {\n");
-        for id in itertools::chain(
-            game_data.machines.iter().map(|machine| machine.id.0),
-            game_data.beacons.iter().map(|beacon| beacon.id.0),
-        )
-        .unique()
-        {
-            any = true;
-            write(extract_script, id.str());
-        }
-        extract_script.push_str("    },\n}\n\n");
-        if !any {
-            return Err("game data is empty");
+    let not_captured = |id: &ID| !captured.contains(id);
+    let items = game_data.items.iter().map(|item| ID::from(item.id)).filter(not_captured);
+    let fluids = game_data.fluids.iter().map(|fluid| ID::from(fluid.id)).filter(not_captured);
+    let recipes = game_data.recipes.iter().map(|recipe| ID::from(recipe.id)).filter(not_captured);
+    // Machines and beacons are both entities, which share a single
+    // namespace in the game, so their icons are the same sprite. A game
+    // data set with a machine and a beacon of the same name (which the
+    // game itself can't export, as a prototype has one type) captures
+    // that sprite once, and transform_icons assigns it to both. Items
+    // and fluids (and recipes) each have their own namespace, and are
+    // written to separate directories, so a name shared between them is
+    // fine, and must not be deduplicated.
+    let entities = itertools::chain(
+        game_data.machines.iter().map(|machine| ID::from(machine.id)),
+        game_data.beacons.iter().map(|beacon| ID::from(beacon.id)),
+    )
+    .filter(not_captured)
+    .map(|id| id.str())
+    .unique();
+    let collections: [(&str, Vec<&str>); 4] = [
+        ("items", items.map(|id| id.str()).collect()),
+        ("fluids", fluids.map(|id| id.str()).collect()),
+        ("recipes", recipes.map(|id| id.str()).collect()),
+        ("entities", entities.collect()),
+    ];
+    if collections.iter().all(|(_, names)| names.is_empty()) {
+        return Err("game data is empty");
+    }
+
+    // The names are split over several functions, as a single function can
+    // only refer to a limited amount of constants, which large mod packs
+    // would otherwise exceed.
+    extract_script.push_str(
+        "local extract_data = { items = {}, fluids = {}, recipes = {}, entities = {} }\n\
+         local function append(list, names)\n    \
+             for _, name in ipairs(names) do\n        \
+                 list[#list + 1] = name\n    \
+             end\n\
+         end\n",
+    );
+    for (collection, names) in collections.iter() {
+        for chunk in names.chunks(LUA_NAMES_PER_FUNCTION) {
+            extract_script.push_str(&format!(
+                "append(extract_data.{}, (function () return {{\n",
+                collection
+            ));
+            for name in chunk {
+                write(&mut extract_script, name);
+            }
+            extract_script.push_str("} end)())\n");
         }
     }
+    extract_script.push('\n');
 
     extract_script.push_str(EXTRACT_IMAGES);
     Ok(extract_script)
 }
 
+/// The size of an icon at a scale of 1.
 const TILE_WIDTH: u32 = 32;
 const TILE_HEIGHT: u32 = 32;
 
-fn load_image(path: &PathBuf) -> io::Result<image::RgbImage> {
-    let image = image::open(path)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-        .to_rgb();
-    if image.width() != TILE_WIDTH || image.height() != TILE_HEIGHT {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "expected image to be 32x32",
-        ));
-    }
-    Ok(image)
+/// How the icons are stored.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IconOutput {
+    /// Packed into a single tile set, laid out as described by `AtlasLayout`.
+    Atlas,
+    /// Stored as a file per distinct icon.
+    Individual,
 }
 
-fn combine_image(dark: image::RgbImage, light: image::RgbImage) -> image::RgbaImage {
-    use image::RgbaImage;
-
-    let mut combined = RgbaImage::new(dark.width(), dark.height());
-    combined.enumerate_pixels_mut().for_each(|(x, y, pixel)| {
-        let d = dark.get_pixel(x, y);
-        let l = light.get_pixel(x, y);
-        // d = a * rgb
-        // l = a * rgb + (1 - a)
-        // l - d = 1 - a
-        // d - l = a - 1
-        // a = d - l + 1
-        let d = [
-            d.data[0] as f64 / 255f64,
-            d.data[1] as f64 / 255f64,
-            d.data[2] as f64 / 255f64,
-        ];
-        let l = [
-            l.data[0] as f64 / 255f64,
-            l.data[1] as f64 / 255f64,
-            l.data[2] as f64 / 255f64,
-        ];
-
-        let dr = d[0] - l[0] + 1f64;
-        let dg = d[1] - l[1] + 1f64;
-        let db = d[2] - l[2] + 1f64;
-
-        // Average the alpha based on the 3 channels
-        let a = (dr + dg + db) / 3f64;
-
-        // d = a * rgb
-        // rgb = d / a
-        let r1 = d[0] / a;
-        let g1 = d[1] / a;
-        let b1 = d[2] / a;
-
-        // l = a * rgb + (1 - a)
-        // l - 1 + a = a * rgb
-        // rgb = (l - 1 + a) / a
-        //     = (l - 1) / a + 1
-        let r2 = (l[0] - 1f64) / a + 1f64;
-        let g2 = (l[1] - 1f64) / a + 1f64;
-        let b2 = (l[2] - 1f64) / a + 1f64;
-
-        // Average color based on both images
-        let r = (r1 + r2) / 2f64;
-        let g = (g1 + g2) / 2f64;
-        let b = (b1 + b2) / 2f64;
-
-        pixel.data = [
-            f64::max(0f64, f64::min(255f64, r * 255f64)).round() as u8,
-            f64::max(0f64, f64::min(255f64, g * 255f64)).round() as u8,
-            f64::max(0f64, f64::min(255f64, b * 255f64)).round() as u8,
-            f64::max(0f64, f64::min(255f64, a * 255f64)).round() as u8,
-        ];
-    });
-
-    combined
+/// How transform_icons reads and stores the icons, besides their layout.
+struct IconOptions {
+    mode: IconMode,
+    output: IconOutput,
+    /// Whether the index of the icon of every object is written as well.
+    write_map: bool,
+    /// The directories within the icon directory holding the renders on
+    /// the light and dark background, which extract_icons names `light` and
+    /// `dark`.
+    light_directory: String,
+    dark_directory: String,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn transform_icons(
     paths: &FactorioPaths,
     game_data: &GameData,
     icon_directory: PathBuf,
+    atlas_layout: &AtlasLayout,
+    icon_options: &IconOptions,
     delete_icons: bool,
-) -> io::Result<GameData> {
+    overwrite: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<(GameData, Vec<PathBuf>)> {
     use self::data::*;
 
-    fn resolve_image<'a, ID: AsRef<Str> + ::std::hash::Hash + Eq>(
-        temp_str: &'a mut String,
-        dark_path: &'a mut PathBuf,
-        light_path: &'a mut PathBuf,
-        images: &'a mut HashMap<Vec<u8>, usize>,
-        delete_icons: bool,
-        iter: impl Iterator<Item = ID>,
-    ) -> io::Result<HashMap<ID, usize>> {
-        let mut sorted = iter
-            .map(|id| {
-                let s = id.as_ref().str();
-                (id, s)
-            })
-            .collect::<Vec<(ID, &'static str)>>();
-        sorted.sort_by_key(|&(_, s)| s);
-        sorted
-            .into_iter()
-            .map(|(id, s)| {
-                temp_str.push_str(s);
-                temp_str.push_str(".png");
-                light_path.push(&temp_str);
-                dark_path.push(&temp_str);
-                temp_str.clear();
-
-                let dark_img = load_image(&dark_path)?;
-                let light_img = load_image(&light_path)?;
-
-                if delete_icons {
-                    let _ = fs::remove_file(&dark_path);
-                    let _ = fs::remove_file(&light_path);
-                }
+    reporter.info("loading exported images...");
+    let tile_size = atlas_layout.tile_size;
 
-                light_path.pop();
-                dark_path.pop();
+    // Handle all the image manipulation
+    let (output_file, tile_metadata, icons, icon_map_file) = {
+        let mut images: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut icons: HashMap<ID, usize> = HashMap::new();
 
-                let image = combine_image(dark_img, light_img);
-                let image = image.into_raw();
+        let mut previous: Option<(&str, &str, usize)> = None;
+        for (category, s, id) in icon_packing_order(game_data.all_ids()) {
+            if let Some((previous_category, previous_s, index)) = previous {
+                if (previous_category, previous_s) == (category, s) {
+                    icons.insert(id, index);
+                    continue;
+                }
+            }
 
-                let image_count = images.len();
-                let index = *images.entry(image).or_insert(image_count);
-                Ok((id, index))
-            })
-            .collect::<io::Result<HashMap<ID, usize>>>()
-    }
+            let file_name = format!("{}.png", s);
+            let light_path = icon_directory
+                .join(&icon_options.light_directory)
+                .join(category)
+                .join(&file_name);
+            let dark_path = icon_directory
+                .join(&icon_options.dark_directory)
+                .join(category)
+                .join(&file_name);
+
+            let image = load_icon(&light_path, &dark_path, tile_size, icon_options.mode)?;
+
+            if delete_icons {
+                let _ = fs::remove_file(&dark_path);
+                let _ = fs::remove_file(&light_path);
+            }
 
-    println!("loading exported images...");
+            let image = image.into_raw();
 
-    // Handle all the image manipulation
-    let (tile_metadata, item_icons, fluid_icons, recipe_icons, machine_icons, beacon_icons) = {
-        let mut images: HashMap<Vec<u8>, usize> = HashMap::new();
-        let mut temp_str = String::new();
-
-        let mut light_path = icon_directory.clone();
-        light_path.push("light");
-        let mut dark_path = icon_directory;
-        dark_path.push("dark");
-
-        light_path.push("items");
-        dark_path.push("items");
-        let item_icons = resolve_image(
-            &mut temp_str,
-            &mut dark_path,
-            &mut light_path,
-            &mut images,
-            delete_icons,
-            game_data.items.iter().map(|item| item.id),
-        )?;
-        if delete_icons {
-            let _ = fs::remove_dir(&light_path);
-            let _ = fs::remove_dir(&dark_path);
+            let image_count = images.len();
+            let index = *images.entry(image).or_insert(image_count);
+            icons.insert(id, index);
+            previous = Some((category, s, index));
         }
-        light_path.pop();
-        dark_path.pop();
-
-        light_path.push("fluids");
-        dark_path.push("fluids");
-        let fluid_icons = resolve_image(
-            &mut temp_str,
-            &mut dark_path,
-            &mut light_path,
-            &mut images,
-            delete_icons,
-            game_data.fluids.iter().map(|fluid| fluid.id),
-        )?;
-        if delete_icons {
-            let _ = fs::remove_dir(&light_path);
-            let _ = fs::remove_dir(&dark_path);
-        }
-        light_path.pop();
-        dark_path.pop();
-
-        light_path.push("recipes");
-        dark_path.push("recipes");
-        let recipe_icons = resolve_image(
-            &mut temp_str,
-            &mut dark_path,
-            &mut light_path,
-            &mut images,
-            delete_icons,
-            game_data.recipes.iter().map(|recipe| recipe.id),
-        )?;
-        if delete_icons {
-            let _ = fs::remove_dir(&light_path);
-            let _ = fs::remove_dir(&dark_path);
-        }
-        light_path.pop();
-        dark_path.pop();
-
-        light_path.push("entities");
-        dark_path.push("entities");
-        let machine_icons = resolve_image(
-            &mut temp_str,
-            &mut dark_path,
-            &mut light_path,
-            &mut images,
-            delete_icons,
-            game_data.machines.iter().map(|machine| machine.id),
-        )?;
-        let beacon_icons = resolve_image(
-            &mut temp_str,
-            &mut dark_path,
-            &mut light_path,
-            &mut images,
-            delete_icons,
-            game_data.beacons.iter().map(|beacon| beacon.id),
-        )?;
-        if delete_icons {
-            let _ = fs::remove_dir(&light_path);
-            let _ = fs::remove_dir(&dark_path);
-        }
-        light_path.pop();
-        dark_path.pop();
+
         if delete_icons {
-            let _ = fs::remove_dir(&light_path);
-            let _ = fs::remove_dir(&dark_path);
-            light_path.pop();
-            let _ = fs::remove_dir(light_path);
+            for background in &[&icon_options.light_directory, &icon_options.dark_directory] {
+                let background_path = icon_directory.join(background);
+                for category in ICON_CATEGORIES {
+                    let _ = fs::remove_dir(background_path.join(category));
+                }
+                let _ = fs::remove_dir(background_path);
+            }
+            let _ = fs::remove_dir(&icon_directory);
         }
 
         let images = {
@@ -1035,79 +1720,927 @@ fn transform_icons(
         };
 
         assert!(images.len() != 0);
-        println!("combining {} images", images.len());
-
-        let columns = ((images.len() as f64).sqrt().ceil()) as u32;
-        let rows = (images.len() as u32 + columns - 1) / columns;
-
-        let target_width = columns * TILE_WIDTH;
-        let target_height = rows * TILE_HEIGHT;
-        let mut tileset = Vec::new();
-        tileset.resize((4 * target_width * target_height) as usize, 0);
-
-        for (index, image) in images.iter().enumerate() {
-            let index = index as u32;
-            let bx = (index % columns) * TILE_WIDTH;
-            let by = (index / columns) * TILE_HEIGHT;
-            for y in 0..TILE_HEIGHT {
-                for x in 0..TILE_WIDTH {
-                    for b in 0..4 {
-                        let src = image[((y * TILE_WIDTH + x) * 4 + b) as usize];
-                        tileset[(((y + by) * target_width + x + bx) * 4 + b) as usize] = src;
-                    }
-                }
-            }
-        }
-
-        use image::*;
-        let mut tileset_image = Vec::new();
-        DynamicImage::ImageRgba8(
-            RgbaImage::from_raw(target_width, target_height, tileset).ok_or(io::Error::new(
-                io::ErrorKind::Other,
-                "failed to encode image",
-            ))?,
-        )
-        .write_to(&mut tileset_image, ImageFormat::PNG)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-        let output_file = write_file_safely(
-            &paths.script_output_directory,
-            "game_icons",
-            "png",
-            &tileset_image,
-        )?;
-        println!("output image stored at: {}", output_file.to_string_lossy());
-
-        let tile_metadata = TileMetadata {
-            tile_size: (TILE_WIDTH, TILE_HEIGHT),
-            tile_count: images.len() as u32,
-            image_size: (target_width, target_height),
+        let icon_map_file = if icon_options.write_map {
+            Some(store_icon_map(paths, &icons, overwrite, reporter)?)
+        } else {
+            None
         };
+        if icon_options.output == IconOutput::Individual {
+            let (game_data, mut output_files) =
+                store_individual_icons(paths, game_data, &images, tile_size, &icons, overwrite, reporter)?;
+            output_files.extend(icon_map_file);
+            return Ok((game_data, output_files));
+        }
+        reporter.info(&format!("combining {} images", images.len()));
 
-        (
-            tile_metadata,
-            item_icons,
-            fluid_icons,
-            recipe_icons,
-            machine_icons,
-            beacon_icons,
-        )
+        let (tile_metadata, tileset) = atlas::pack_tiles(images, atlas_layout);
+        let output_file = store_tile_set(paths, tile_metadata.image_size, tileset, overwrite, reporter)?;
+        (output_file, tile_metadata, icons, icon_map_file)
     };
 
     let mut game_data = game_data.clone();
     game_data.tile_metadata = Some(tile_metadata);
     game_data
         .modify_metadata::<(), _>(|id, meta| {
-            let icon = Some(Icon::new(*match id {
-                ID::Item(id) => item_icons.get(&id).unwrap(),
-                ID::Fluid(id) => fluid_icons.get(&id).unwrap(),
-                ID::Recipe(id) => recipe_icons.get(&id).unwrap(),
-                ID::Machine(id) => machine_icons.get(&id).unwrap(),
-                ID::Beacon(id) => beacon_icons.get(&id).unwrap(),
-            }));
+            let icon = Some(Icon::new(icons[&id]));
             Ok(Metadata { icon, ..*meta })
         })
         .unwrap();
 
-    Ok(game_data)
+    let mut output_files = vec![output_file];
+    output_files.extend(icon_map_file);
+    Ok((game_data, output_files))
+}
+
+/// Writes a tile set to `game_icons.png`, encoding it straight into the file,
+/// rather than into memory first, as it's only dropped once it has been
+/// encoded.
+fn store_tile_set(
+    paths: &FactorioPaths,
+    size: (u32, u32),
+    tileset: Vec<u8>,
+    overwrite: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<PathBuf> {
+    let (output_file, file) = create_output_file(&paths.script_output_directory, "game_icons", "png", overwrite)?;
+    let mut writer = io::BufWriter::new(file);
+    let written = write_png(&mut writer, size, tileset).and_then(|_| io::Write::flush(&mut writer));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&output_file);
+        return Err(err);
+    }
+    reporter.info(&format!("output image stored at: {}", output_file.to_string_lossy()));
+    Ok(output_file)
+}
+
+/// Writes the index of the tile of every object, grouped by collection, such
+/// as `{ "items": { "iron-plate": 3 } }`. These are the same as `Icon::index`
+/// of the icons that are assigned to the objects, which count from 0, unlike
+/// the icons as they're stored in the game data.
+fn store_icon_map(
+    paths: &FactorioPaths,
+    icons: &HashMap<ID, usize>,
+    overwrite: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<PathBuf> {
+    let mut icon_map: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+    for (id, &index) in icons {
+        let collection = match id {
+            ID::Item(_) => "items",
+            ID::Fluid(_) => "fluids",
+            ID::Recipe(_) => "recipes",
+            ID::Machine(_) => "machines",
+            ID::Beacon(_) => "beacons",
+        };
+        icon_map.entry(collection).or_default().insert(id.str(), index);
+    }
+    let serialized = serde_json::ser::to_string_pretty(&icon_map)?;
+    let (output_file, mut file) =
+        create_output_file(&paths.script_output_directory, "icon_map", "json", overwrite)?;
+    io::Write::write_all(&mut file, serialized.as_bytes())?;
+    reporter.info(&format!("icon map stored at: {}", output_file.to_string_lossy()));
+    Ok(output_file)
+}
+
+/// Creates `<name>.<extension>` in `parent`, replacing an existing file if
+/// `overwrite` is set, and otherwise picking a name that's not taken yet,
+/// like `create_file_safely`. This way the icons written next to the game
+/// data follow the same naming as it does.
+fn create_output_file(
+    parent: &Path,
+    name: &str,
+    extension: &str,
+    overwrite: bool,
+) -> io::Result<(PathBuf, fs::File)> {
+    if !overwrite {
+        return create_file_safely(parent, name, extension);
+    }
+    let path = parent.join(format!("{}.{}", name, extension));
+    let file = fs::File::create(&path)?;
+    Ok((path, file))
+}
+
+/// Writes every distinct icon to its own file, named after its index, along
+/// with an index mapping every object to the file of its icon. As there's no
+/// tile set, the game data is left without tile metadata or icons.
+fn store_individual_icons(
+    paths: &FactorioPaths,
+    game_data: &GameData,
+    images: &[Vec<u8>],
+    tile_size: (u32, u32),
+    icons: &HashMap<ID, usize>,
+    overwrite: bool,
+    reporter: &mut dyn Reporter,
+) -> io::Result<(GameData, Vec<PathBuf>)> {
+    use self::data::Metadata;
+    let mut output_dir = TempDirectory::ensure(&paths.script_output_directory)?;
+    let icon_directory = if overwrite {
+        let path = paths.script_output_directory.join("game_icons");
+        fs::create_dir_all(&path)?;
+        path
+    } else {
+        create_dir_safely(&paths.script_output_directory, "game_icons")?
+    };
+    output_dir.release();
+
+    let mut output_files = Vec::with_capacity(images.len() + 1);
+    for (index, image) in images.iter().enumerate() {
+        let path = icon_directory.join(format!("{}.png", index));
+        fs::write(&path, encode_png(tile_size, image.clone())?)?;
+        output_files.push(path);
+    }
+    if overwrite {
+        remove_stale_icons(&icon_directory, images.len())?;
+    }
+
+    let index = icons
+        .iter()
+        .map(|(id, index)| (id.to_string(), serde_json::Value::from(format!("{}.png", index))))
+        .collect::<serde_json::Map<_, _>>();
+    let index_path = icon_directory.join("index.json");
+    fs::write(&index_path, serde_json::ser::to_string_pretty(&index)?.as_bytes())?;
+    output_files.push(index_path);
+    reporter.info(&format!(
+        "stored {} icons to: {}",
+        images.len(),
+        icon_directory.to_string_lossy()
+    ));
+
+    let mut game_data = game_data.clone();
+    game_data.tile_metadata = None;
+    game_data
+        .modify_metadata::<(), _>(|_, meta| Ok(Metadata { icon: None, ..*meta }))
+        .unwrap();
+    Ok((game_data, output_files))
+}
+
+/// Deletes the icons named `<index>.png` in `directory` whose index is at
+/// least `count`, which a previous run with more icons left behind.
+fn remove_stale_icons(directory: &Path, count: usize) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension() != Some("png".as_ref()) {
+            continue;
+        }
+        let index = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<usize>().ok());
+        match index {
+            Some(index) if index >= count => fs::remove_file(&path)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, RecordingReporter};
+    use graphio_rs_data::parsing::delimiters;
+    use graphio_rs_data::{MachineID, Str};
+
+    /// Frames the records like the export script does, surrounded by what
+    /// the game logs before and after them.
+    fn canned_log(records: &[String]) -> Vec<u8> {
+        let mut log = String::new();
+        log.push_str("   0.000 2019-05-11 12:00:00; Factorio 0.17.79 (build 49270, win64, steam)\r\n");
+        log.push_str("   1.234 Loading mod core 0.0.0 (data.lua)\r\n");
+        log.push(delimiters::START);
+        for record in records {
+            log.push(delimiters::RECORD_START);
+            log.push_str(record);
+            log.push(delimiters::RECORD_END);
+        }
+        log.push(delimiters::FINISH);
+        log.push_str("\r\n   5.678 Error MainLoop.cpp:1131: done\r\n   5.679 Goodbye\r\n");
+        log.into_bytes()
+    }
+
+    fn atlas_layout(padding: u32) -> AtlasLayout {
+        AtlasLayout {
+            tile_size: (TILE_WIDTH, TILE_HEIGHT),
+            padding,
+            bleed: false,
+        }
+    }
+
+    fn icon_options(output: IconOutput) -> IconOptions {
+        IconOptions {
+            mode: IconMode::Rgba,
+            output,
+            write_map: false,
+            light_directory: "light".to_owned(),
+            dark_directory: "dark".to_owned(),
+        }
+    }
+
+    #[test]
+    fn individual_icons_share_files_between_duplicates() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+
+        let options = icon_options(IconOutput::Individual);
+        let (stored, output_files) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &options, false, true, &mut RecordingReporter::default()).unwrap();
+
+        // Recipes named after their product share its icon, as does the
+        // fluid that's named like the sulfuric acid recipe.
+        let distinct = test_support::distinct_icon_count(&game_data);
+        assert_eq!(distinct, 18);
+        assert!(distinct < game_data.all_ids().count());
+        let output_directory = paths.script_output_directory.join("game_icons");
+        let png_count = fs::read_dir(&output_directory)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("png".as_ref()))
+            .count();
+        assert_eq!(png_count, distinct);
+        assert_eq!(output_files.len(), distinct + 1);
+
+        let index: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(&fs::read(output_directory.join("index.json")).unwrap()).unwrap();
+        assert_eq!(index.len(), game_data.all_ids().count());
+        assert_eq!(index["item:iron-gear-wheel"], index["recipe:iron-gear-wheel"]);
+        assert_ne!(index["item:iron-gear-wheel"], index["item:iron-plate"]);
+
+        assert!(stored.tile_metadata.is_none());
+        assert!(stored.all_ids().all(|id| stored.metadata_for(id).unwrap().icon.is_none()));
+    }
+
+    #[test]
+    fn overwritten_individual_icons_replace_the_previous_run() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+        let options = icon_options(IconOutput::Individual);
+        let output_directory = paths.script_output_directory.join("game_icons");
+        let png_files = || {
+            let mut names = fs::read_dir(&output_directory)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| name.ends_with(".png"))
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        let (_, output_files) =
+            transform_icons(&paths, &game_data, icon_directory.clone(), &atlas_layout(0), &options, false, true, &mut RecordingReporter::default())
+                .unwrap();
+        assert!(output_files.iter().all(|path| path.parent() == Some(&*output_directory)));
+        assert_eq!(png_files().len(), test_support::distinct_icon_count(&game_data));
+        fs::write(output_directory.join("notes.txt"), "kept").unwrap();
+
+        // A second run with fewer icons writes to the same directory, and
+        // doesn't leave the icons of the first run behind.
+        let mut smaller = game_data.clone();
+        smaller.remove_hidden(|id, _| id.str() != "copper-plate" && id.str() != "water");
+        let (_, output_files) =
+            transform_icons(&paths, &smaller, icon_directory, &atlas_layout(0), &options, false, true, &mut RecordingReporter::default()).unwrap();
+        assert!(output_files.iter().all(|path| path.parent() == Some(&*output_directory)));
+        assert_eq!(png_files(), vec!["0.png", "1.png"]);
+        assert!(output_directory.join("notes.txt").is_file());
+
+        let index: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(&fs::read(output_directory.join("index.json")).unwrap()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert!(index.values().all(|file| output_directory.join(file.as_str().unwrap()).is_file()));
+        let siblings = fs::read_dir(&paths.script_output_directory).unwrap().count();
+        assert_eq!(siblings, 1);
+    }
+
+    #[test]
+    fn icons_resolve_from_custom_directory_names() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "white", &game_data);
+        test_support::write_icons(&icon_directory, "black", &game_data);
+
+        let mut options = icon_options(IconOutput::Atlas);
+        options.mode = IconMode::Reconstruct;
+        // There are no icons in the default directories.
+        let result = transform_icons(&paths, &game_data, icon_directory.clone(), &atlas_layout(0), &options, false, true, &mut RecordingReporter::default());
+        assert!(result.is_err());
+
+        options.light_directory = "white".to_owned();
+        options.dark_directory = "black".to_owned();
+        let (stored, _) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &options, false, true, &mut RecordingReporter::default()).unwrap();
+        let tile_metadata = stored.tile_metadata.clone().unwrap();
+        assert_eq!(tile_metadata.tile_count as usize, test_support::distinct_icon_count(&game_data));
+        let atlas = image::open(paths.script_output_directory.join("game_icons.png")).unwrap().to_rgba();
+        for id in stored.all_ids() {
+            let icon = stored.metadata_for(id).unwrap().icon.unwrap();
+            let (x, y) = icon.position(&tile_metadata);
+            assert_eq!(atlas.get_pixel(x, y).data, test_support::icon_color(id.str()));
+        }
+    }
+
+    #[test]
+    fn transform_icons_reports_to_the_given_reporter() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+
+        let mut options = icon_options(IconOutput::Atlas);
+        options.write_map = true;
+        let mut reporter = RecordingReporter::default();
+        transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &options, false, true, &mut reporter)
+            .unwrap();
+
+        let output_directory = &paths.script_output_directory;
+        assert_eq!(
+            reporter.infos,
+            vec![
+                "loading exported images...".to_owned(),
+                format!("icon map stored at: {}", output_directory.join("icon_map.json").to_string_lossy()),
+                format!("combining {} images", test_support::distinct_icon_count(&game_data)),
+                format!("output image stored at: {}", output_directory.join("game_icons.png").to_string_lossy()),
+            ]
+        );
+        assert!(reporter.warnings.is_empty());
+    }
+
+    #[test]
+    fn icon_map_matches_the_icons_in_the_game_data() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+
+        let mut options = icon_options(IconOutput::Atlas);
+        options.write_map = true;
+        let (stored, output_files) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &options, false, true, &mut RecordingReporter::default()).unwrap();
+        let map_file = paths.script_output_directory.join("icon_map.json");
+        assert!(output_files.contains(&map_file));
+
+        let icon_map: BTreeMap<String, BTreeMap<String, usize>> =
+            serde_json::from_slice(&fs::read(&map_file).unwrap()).unwrap();
+        assert_eq!(icon_map.values().map(BTreeMap::len).sum::<usize>(), stored.all_ids().count());
+        for id in stored.all_ids() {
+            let icon = stored.metadata_for(id).unwrap().icon.unwrap();
+            let collection = match id {
+                ID::Item(_) => "items",
+                ID::Fluid(_) => "fluids",
+                ID::Recipe(_) => "recipes",
+                ID::Machine(_) => "machines",
+                ID::Beacon(_) => "beacons",
+            };
+            // The map counts from 0, whilst game_data.json counts from 1.
+            assert_eq!(icon_map[collection][id.str()], icon.index(), "{}", id);
+            assert_eq!(serde_json::to_value(icon).unwrap(), icon.index() + 1);
+        }
+    }
+
+    /// The names that an icon extract script lists for `collection`, one
+    /// `Vec` per function that they're split over.
+    fn script_chunks(script: &str, collection: &str) -> Vec<Vec<String>> {
+        let start = format!("append(extract_data.{}, (function () return {{", collection);
+        let mut chunks = Vec::new();
+        let mut lines = script.lines();
+        while let Some(line) = lines.next() {
+            if line == start {
+                let chunk = lines
+                    .by_ref()
+                    .take_while(|line| *line != "} end)())")
+                    .map(|line| line.trim().trim_end_matches(',').trim_matches('\'').to_owned())
+                    .collect();
+                chunks.push(chunk);
+            }
+        }
+        chunks
+    }
+
+    #[test]
+    fn many_names_are_split_over_functions() {
+        const SYNTHETIC_COUNT: usize = 70_000;
+        let mut game_data = test_support::game_data();
+        let plate = game_data.item_by_str("iron-plate").unwrap().clone();
+        for index in 0..SYNTHETIC_COUNT {
+            let mut item = plate.clone();
+            item.id = data::ItemID(data::Str::new(&format!("synthetic-item-{}", index)));
+            game_data.items.insert(item);
+        }
+        let item_count = game_data.items.len();
+        assert_eq!(item_count, SYNTHETIC_COUNT + 11);
+
+        let script = get_icon_extract_script(&game_data, "icons", &HashSet::new(), 5, 1).unwrap();
+        let chunks = script_chunks(&script, "items");
+        // 70,011 names, in chunks of 10,000.
+        assert_eq!(LUA_NAMES_PER_FUNCTION, 10_000);
+        assert_eq!(chunks.len(), 8);
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty() && chunk.len() <= LUA_NAMES_PER_FUNCTION));
+
+        let mut names = chunks.concat();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), item_count);
+        assert!(names.iter().any(|name| name == "synthetic-item-69999"));
+
+        // The small collections fit in a single function.
+        assert_eq!(script_chunks(&script, "fluids").len(), 1);
+    }
+
+    /// Adds a beacon that has the same name as the machine `name`.
+    fn add_beacon_named_like_machine(game_data: &mut GameData, name: &str) {
+        let mut beacon = game_data.beacon_by_str("beacon").unwrap().clone();
+        beacon.id = data::BeaconID(data::Str::new(name));
+        beacon.metadata.localised_name = data::Str::new("Shared beacon");
+        game_data.beacons.insert(beacon);
+    }
+
+    #[test]
+    fn machine_and_beacon_with_the_same_name_share_their_icon() {
+        let mut game_data = test_support::game_data();
+        add_beacon_named_like_machine(&mut game_data, "assembling-machine-2");
+
+        // The sprite of the entity is captured once.
+        let script = get_icon_extract_script(&game_data, "icons", &HashSet::new(), 5, 1).unwrap();
+        let mut entities = script_chunks(&script, "entities").concat();
+        entities.sort();
+        assert_eq!(
+            entities,
+            vec!["assembling-machine-1", "assembling-machine-2", "beacon", "chemical-plant"]
+        );
+
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+        let options = icon_options(IconOutput::Atlas);
+        let (stored, _) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &options, false, true, &mut RecordingReporter::default()).unwrap();
+
+        // Both keep their own data, and are assigned the same icon.
+        let machine = stored.machine_by_str("assembling-machine-2").unwrap();
+        let beacon = stored.beacon_by_str("assembling-machine-2").unwrap();
+        assert_eq!(machine.metadata.localised_name.str(), "Assembling machine 2");
+        assert_eq!(machine.module_slots, data::Int::from(2));
+        assert_eq!(beacon.metadata.localised_name.str(), "Shared beacon");
+        assert_eq!(beacon.supported_modules.len(), 1);
+        assert!(machine.metadata.icon.is_some());
+        assert_eq!(machine.metadata.icon, beacon.metadata.icon);
+        assert_ne!(
+            machine.metadata.icon,
+            stored.beacon_by_str("beacon").unwrap().metadata.icon
+        );
+    }
+
+    #[test]
+    fn atlas_tiles_wrap_into_rows() {
+        let mut game_data = test_support::game_data();
+        let kept = ["iron-plate", "copper-plate", "water", "assembling-machine-1", "beacon"];
+        game_data.remove_hidden(|id, _| !kept.contains(&id.str()));
+        assert_eq!(test_support::distinct_icon_count(&game_data), 5);
+
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+        let options = icon_options(IconOutput::Atlas);
+        let (stored, output_files) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(2), &options, false, true, &mut RecordingReporter::default()).unwrap();
+
+        // 5 tiles are packed into 3 columns and 2 rows, of 36x36 pixels each.
+        let tile_metadata = stored.tile_metadata.as_ref().unwrap();
+        assert_eq!(tile_metadata.tile_count, 5);
+        assert_eq!(tile_metadata.image_size, (108, 72));
+        let atlas = image::open(&output_files[0]).unwrap().to_rgba();
+        assert_eq!(atlas.dimensions(), tile_metadata.image_size);
+
+        let by_index = stored
+            .all_ids()
+            .map(|id| (stored.metadata_for(id).unwrap().icon.unwrap().index(), id))
+            .collect::<BTreeMap<_, _>>();
+        // The first tile, the last one in the first row, and the first one
+        // in the second row.
+        for &(index, position) in &[(0, (2, 2)), (2, (74, 2)), (3, (2, 38))] {
+            let id = by_index[&index];
+            let icon = stored.metadata_for(id).unwrap().icon.unwrap();
+            assert_eq!(icon.position(tile_metadata), position, "{}", id);
+            let tile = image::imageops::crop(&mut atlas.clone(), position.0, position.1, 32, 32).to_image();
+            let color = image::Rgba(test_support::icon_color(id.str()));
+            assert!(tile.pixels().all(|pixel| *pixel == color), "{}", id);
+            // The padding around it is left transparent.
+            assert_eq!(atlas.get_pixel(position.0 - 1, position.1 - 1).data[3], 0);
+        }
+    }
+
+    #[test]
+    fn scaled_icons_are_packed_into_larger_tiles() {
+        let mut game_data = test_support::game_data();
+        let kept = ["iron-plate", "copper-plate", "water", "assembling-machine-1", "beacon"];
+        game_data.remove_hidden(|id, _| !kept.contains(&id.str()));
+
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let icon_directory = root.path().join("icons");
+        test_support::write_scaled_icons(&icon_directory, "light", &game_data, 2);
+        let options = icon_options(IconOutput::Atlas);
+        let layout = AtlasLayout {
+            tile_size: (TILE_WIDTH * 2, TILE_HEIGHT * 2),
+            ..atlas_layout(0)
+        };
+        let (stored, output_files) =
+            transform_icons(&paths, &game_data, icon_directory.clone(), &layout, &options, false, true, &mut RecordingReporter::default()).unwrap();
+
+        // 5 tiles are packed into 3 columns and 2 rows, of 64x64 pixels each.
+        let tile_metadata = stored.tile_metadata.as_ref().unwrap();
+        assert_eq!(tile_metadata.tile_size, (64, 64));
+        assert_eq!(tile_metadata.image_size, (192, 128));
+        let atlas = image::open(&output_files[0]).unwrap().to_rgba();
+        assert_eq!(atlas.dimensions(), tile_metadata.image_size);
+        for id in stored.all_ids() {
+            let (x, y) = stored.metadata_for(id).unwrap().icon.unwrap().position(tile_metadata);
+            let color = image::Rgba(test_support::icon_color(id.str()));
+            assert_eq!(*atlas.get_pixel(x + 63, y + 63), color, "{}", id);
+        }
+
+        // Icons extracted at another scale are rejected.
+        let error = transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &options, false, true, &mut RecordingReporter::default())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "expected image to be 32x32, was icon_scale the same during extract_icons?");
+    }
+
+    /// Writes an icon of a distinct color for every category and name, so
+    /// that transform_icons packs each of them into a tile of its own.
+    fn write_distinct_icons(icon_directory: &Path, game_data: &GameData) {
+        for (category, s, _) in icon_packing_order(game_data.all_ids()) {
+            let path = icon_directory.join("light").join(category).join(format!("{}.png", s));
+            test_support::write_icon(&path, test_support::icon_color(&format!("{}/{}", category, s)), 32);
+        }
+    }
+
+    #[test]
+    fn icons_are_packed_in_the_same_order_whichever_order_the_categories_are_in() {
+        let game_data = test_support::game_data();
+        let by_category = |kinds: &[&str]| {
+            let mut ids = Vec::new();
+            for &kind in kinds {
+                ids.extend(game_data.all_ids().filter(|id| id.kind() == kind));
+            }
+            icon_packing_order(ids)
+        };
+        let forwards = by_category(&["item", "fluid", "recipe", "machine", "beacon"]);
+        let backwards = by_category(&["beacon", "machine", "recipe", "fluid", "item"]);
+        assert_eq!(forwards.len(), game_data.all_ids().count());
+        assert_eq!(forwards, backwards);
+
+        // The icons are packed by category, and then by name.
+        let strings = forwards.iter().map(|&(category, s, _)| format!("{}/{}", category, s)).collect::<Vec<_>>();
+        assert_eq!(&strings[..2], &["entities/assembling-machine-1", "entities/assembling-machine-2"]);
+        assert_eq!(strings.last().unwrap(), "recipes/uranium-processing");
+
+        // So an item and a recipe sharing their image get the same tile, being
+        // the item's, as items come before recipes.
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let icon_directory = root.path().join("icons");
+        test_support::write_icons(&icon_directory, "light", &game_data);
+        let (packed, _) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &icon_options(IconOutput::Atlas), false, true, &mut RecordingReporter::default()).unwrap();
+        let icon = |id: ID| packed.metadata_for(id).unwrap().icon.unwrap();
+        let gear_item = ID::from(packed.item_by_str("iron-gear-wheel").unwrap().id);
+        let gear_recipe = ID::from(packed.recipe_by_str("iron-gear-wheel").unwrap().id);
+        assert_eq!(icon(gear_item), icon(gear_recipe));
+        // Objects with the same name share their image, so only the first of
+        // them takes a tile.
+        let images_before_gear = forwards
+            .iter()
+            .take_while(|&&(_, _, id)| id != gear_item)
+            .map(|&(_, s, _)| s)
+            .unique()
+            .count();
+        assert_eq!(icon(gear_item), data::Icon::new(images_before_gear));
+    }
+
+    #[test]
+    fn reassigned_icons_match_the_packed_icons() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        write_distinct_icons(&icon_directory, &game_data);
+        let (packed, output_files) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(2), &icon_options(IconOutput::Atlas), false, true, &mut RecordingReporter::default()).unwrap();
+        let atlas_path = &output_files[0];
+
+        let mut reassigned = game_data.clone();
+        let mut reporter = RecordingReporter::default();
+        reuse_icons(&mut reassigned, &packed, atlas_path, &mut reporter).unwrap();
+        let reused = reassigned.tile_metadata.as_ref().unwrap();
+        let tile_metadata = packed.tile_metadata.as_ref().unwrap();
+        assert_eq!(
+            (reused.tile_size, reused.tile_count, reused.image_size, reused.padding),
+            (tile_metadata.tile_size, tile_metadata.tile_count, tile_metadata.image_size, tile_metadata.padding)
+        );
+        for id in packed.all_ids() {
+            let icon = reassigned.metadata_for(id).unwrap().icon;
+            assert!(icon.is_some(), "{}", id);
+            assert_eq!(icon, packed.metadata_for(id).unwrap().icon, "{}", id);
+        }
+        assert_eq!(
+            reporter.infos,
+            vec![format!(
+                "reused {} icon(s) from: {}",
+                packed.tile_metadata.as_ref().unwrap().tile_count,
+                atlas_path.to_string_lossy()
+            )]
+        );
+    }
+
+    #[test]
+    fn icons_that_were_packed_together_cant_be_reassigned() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let game_data = test_support::game_data();
+        let icon_directory = root.path().join("icons");
+        // Recipes share the icon of the item they're named after.
+        test_support::write_icons(&icon_directory, "light", &game_data);
+        let (packed, output_files) =
+            transform_icons(&paths, &game_data, icon_directory, &atlas_layout(0), &icon_options(IconOutput::Atlas), false, true, &mut RecordingReporter::default()).unwrap();
+        let tile_metadata = packed.tile_metadata.as_ref().unwrap();
+        let distinct = icon_packing_order(game_data.all_ids())
+            .iter()
+            .map(|&(category, s, _)| (category, s))
+            .collect::<HashSet<_>>()
+            .len();
+        assert!((tile_metadata.tile_count as usize) < distinct);
+
+        let mut reassigned = game_data.clone();
+        let error = reassign_icons(&mut reassigned, tile_metadata, &output_files[0]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the tile set has {} tile(s), but there are {} distinct icon(s), run transform_icons instead",
+                tile_metadata.tile_count, distinct
+            )
+        );
+
+        // Neither can a tile set that doesn't match its tile metadata.
+        let mut larger = tile_metadata.clone();
+        larger.image_size.0 += 1;
+        assert!(reassign_icons(&mut reassigned, &larger, &output_files[0]).is_err());
+        assert!(reassigned.tile_metadata.is_none());
+        assert!(reuse_icons(&mut reassigned, &game_data, &output_files[0], &mut RecordingReporter::default()).is_err());
+    }
+
+    #[test]
+    fn read_records_from_a_canned_log() {
+        let prototypes = test_support::prototypes();
+        let mut reporter = RecordingReporter::default();
+        let records = read_records(canned_log(&prototypes), &mut reporter).unwrap();
+        assert_eq!(records, prototypes);
+        assert_eq!(reporter.infos, vec!["stripping important information...", "done"]);
+        assert_eq!(records[0], "3\x1f1\x1f5\x1f11\x1f2");
+    }
+
+    #[test]
+    fn read_records_ignores_text_between_records() {
+        let mut log = vec![delimiters::START as u8];
+        log.extend(b"noise\x02first\x03\r\nmore noise\x02\x03\x02third\r\nline\x03");
+        log.push(delimiters::FINISH as u8);
+        let records = read_records(log, &mut RecordingReporter::default()).unwrap();
+        assert_eq!(records, vec!["first", "", "third\nline"]);
+    }
+
+    fn read_records_error(log: &[u8]) -> String {
+        let error = read_records(log.to_vec(), &mut RecordingReporter::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        error.to_string()
+    }
+
+    #[test]
+    fn read_records_rejects_an_empty_export() {
+        let log = [delimiters::START as u8, delimiters::FINISH as u8];
+        assert_eq!(
+            read_records_error(&log),
+            "Factorio produced no prototype records, check that the scenario ran"
+        );
+        let log = [&[delimiters::START as u8], &b"no records\n"[..], &[delimiters::FINISH as u8]].concat();
+        assert_eq!(
+            read_records_error(&log),
+            "Factorio produced no prototype records, check that the scenario ran"
+        );
+    }
+
+    #[test]
+    fn read_records_rejects_misplaced_markers() {
+        let log = [delimiters::FINISH as u8, b'\x02', b'x', b'\x03', delimiters::START as u8];
+        assert_eq!(read_records_error(&log), "no end marker after the start marker in output");
+        let log = [delimiters::START as u8, b'\x02', b'x', b'\x03'];
+        assert_eq!(read_records_error(&log), "no end marker in output");
+        assert_eq!(read_records_error(b"nothing was exported"), "no start marker in output");
+    }
+
+    #[test]
+    fn listed_stages_are_the_stages_the_command_line_accepts() {
+        let with_stage = |stage: &str| {
+            app_definition().get_matches_from_safe(vec!["graphio_rs_extractor", "factorio", "--stage", stage])
+        };
+        for stage in STAGES {
+            assert_eq!(with_stage(stage.name).unwrap().value_of("stage"), Some(stage.name));
+            for implied in stage.implies {
+                assert!(STAGES.iter().any(|stage| stage.name == *implied), "{} isn't a stage", implied);
+            }
+        }
+
+        // The values that clap lists are the listed stages, and nothing else.
+        let error = with_stage("not_a_stage").unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::InvalidValue);
+        let mut names = STAGES.iter().map(|stage| stage.name).collect::<Vec<_>>();
+        names.sort();
+        let possible_values = format!("[possible values: {}]", names.join(", "));
+        assert!(error.message.contains(&possible_values), "{}", error.message);
+    }
+
+    #[test]
+    fn validate_only_rejects_broken_game_data_without_writing_anything() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("game_data.json");
+        let validate_only = |game_data: &GameData| {
+            fs::write(&input, serde_json::to_vec(game_data).unwrap()).unwrap();
+            main_io(vec![
+                "graphio_rs_extractor".into(),
+                dir.path().into(),
+                "--validate_only".into(),
+                input.clone().into(),
+            ])
+        };
+
+        let mut game_data = test_support::game_data();
+        assert!(validate_only(&game_data).is_ok());
+
+        let mut gear = game_data.recipe_by_str("iron-gear-wheel").unwrap().clone();
+        gear.crafted_in.insert(MachineID(Str::new("missing-machine")));
+        game_data.recipes.replace(gear);
+        let error = validate_only(&game_data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "game data failed validation with 1 error(s)");
+
+        let files = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec![OsString::from("game_data.json")]);
+    }
+
+    #[test]
+    fn prune_level_accepts_digits_and_names() {
+        let digits = PruneLevel::SPELLINGS
+            .iter()
+            .map(|spelling| PruneLevel::from_str(spelling).unwrap().as_lua_digit())
+            .collect::<String>();
+        assert_eq!(digits, "012012");
+    }
+
+    #[test]
+    fn prune_level_is_in_the_export_script_header() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = test_support::factorio_paths(root.path());
+        let directory = root.path().join("lua");
+        let levels = [
+            (PruneLevel::NoPruning, "local prune_level = 0"),
+            (PruneLevel::BasicPruning, "local prune_level = 1"),
+            (PruneLevel::ExtensivePruning, "local prune_level = 2"),
+        ];
+        for &(prune_level, header) in &levels {
+            assert_eq!(get_export_script(prune_level, &[]).lines().next(), Some(header));
+
+            let mut reporter = RecordingReporter::default();
+            dump_lua(&paths, &directory, prune_level, &[], 1, 1, false, &mut reporter).unwrap();
+            let script = fs::read_to_string(directory.join("export_prototypes.lua")).unwrap();
+            assert_eq!(script.lines().next(), Some(header));
+            // There's no game data, so there are no icons to extract.
+            assert!(!directory.join("extract_icons.lua").exists());
+            assert!(reporter.infos.last().unwrap().starts_with("skipping icon extraction script"));
+        }
+    }
+
+    #[test]
+    fn prune_level_rejects_unknown_spellings() {
+        for spelling in &["3", "full", "None", ""] {
+            match PruneLevel::try_from(*spelling) {
+                Ok(_) => panic!("{:?} should be rejected", spelling),
+                Err(err) => assert_eq!(err, format!("unknown prune level: {}", spelling)),
+            }
+        }
+    }
+
+    #[test]
+    fn extra_factorio_args_follow_the_scenario() {
+        let executable = Path::new("factorio").join("bin").join("x64").join("factorio");
+        let factorio_args = vec!["--mod-directory".to_owned(), "mods dir".to_owned()];
+        let command = scenario_command(&executable, "graphio_exporter", &factorio_args);
+
+        assert_eq!(command.get_program(), executable.as_os_str());
+        let args = command.get_args().map(|arg| arg.to_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(args, vec!["--scenario2map", "graphio_exporter", "--mod-directory", "mods dir"]);
+
+        let command = scenario_command(&executable, "graphio_exporter", &[]);
+        assert_eq!(command.get_args().count(), 2);
+    }
+
+    #[test]
+    fn prune_keep_names_are_in_the_export_script_header() {
+        let keep = vec!["iron-plate".to_owned(), "it's-kept".to_owned()];
+        let script = get_export_script(PruneLevel::ExtensivePruning, &keep);
+        let mut lines = script.lines();
+        assert_eq!(lines.next(), Some("local prune_level = 2"));
+        assert_eq!(
+            lines.next(),
+            Some("local prune_keep = { ['iron-plate'] = true, ['it\\'s-kept'] = true, }")
+        );
+
+        let script = get_export_script(PruneLevel::NoPruning, &[]);
+        assert_eq!(script.lines().nth(1), Some("local prune_keep = { }"));
+    }
+
+    #[test]
+    fn crafting_matrix_is_exported_as_csv_or_json() {
+        let directory = tempfile::tempdir().unwrap();
+        let game_data = test_support::game_data();
+
+        let csv_path = directory.path().join("matrix.CSV");
+        export_crafting_matrix(&game_data, &csv_path, &mut RecordingReporter::default()).unwrap();
+        assert_eq!(
+            fs::read_to_string(&csv_path).unwrap(),
+            "recipe,assembling-machine-1,assembling-machine-2,chemical-plant\r\n\
+             copper-cable,x,x,\r\n\
+             electronic-circuit,x,x,\r\n\
+             iron-gear-wheel,x,x,\r\n\
+             sulfuric-acid,,,x\r\n\
+             uranium-processing,,,\r\n"
+        );
+
+        let json_path = directory.path().join("matrix.json");
+        export_crafting_matrix(&game_data, &json_path, &mut RecordingReporter::default()).unwrap();
+        let rows: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        let rows = rows.as_array().unwrap();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(
+            rows[2],
+            serde_json::json!({
+                "recipe": "iron-gear-wheel",
+                "machines": ["assembling-machine-1", "assembling-machine-2"],
+            })
+        );
+        // Hand crafted recipes are explicitly listed.
+        assert_eq!(rows[4], serde_json::json!({ "recipe": "uranium-processing", "machines": [] }));
+    }
+
+    #[test]
+    fn resuming_only_extracts_the_missing_icons() {
+        let game_data = test_support::game_data();
+        let root = tempfile::tempdir().unwrap();
+        let icon_directory = root.path().join("icons");
+        // Items and fluids are captured on both backgrounds, the recipes only
+        // on the light one, and the entities not at all.
+        for id in game_data.all_ids() {
+            let backgrounds: &[&str] = match id {
+                ID::Item(_) | ID::Fluid(_) => &["light", "dark"],
+                ID::Recipe(_) => &["light"],
+                ID::Machine(_) | ID::Beacon(_) => &[],
+            };
+            for background in backgrounds {
+                let path = icon_directory
+                    .join(background)
+                    .join(icon_category(id))
+                    .join(format!("{}.png", id.str()));
+                test_support::write_icon(&path, test_support::icon_color(id.str()), 32);
+            }
+        }
+
+        let captured = find_captured_icons(&game_data, &icon_directory);
+        let items = game_data.items.iter().map(|item| ID::from(item.id));
+        let fluids = game_data.fluids.iter().map(|fluid| ID::from(fluid.id));
+        let expected = items.chain(fluids).collect::<HashSet<_>>();
+        assert_eq!(captured, expected);
+
+        let script = get_icon_extract_script(&game_data, "icons", &captured, 5, 1).unwrap();
+        assert!(script_chunks(&script, "items").is_empty());
+        assert!(script_chunks(&script, "fluids").is_empty());
+        let mut recipes = script_chunks(&script, "recipes").concat();
+        recipes.sort();
+        let mut expected = game_data.recipes.iter().map(|recipe| recipe.id.str()).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(recipes, expected);
+        assert_eq!(script_chunks(&script, "entities").concat().len(), 4);
+
+        // Once everything is captured, there's nothing left to extract.
+        let everything = game_data.all_ids().collect::<HashSet<_>>();
+        assert!(get_icon_extract_script(&game_data, "icons", &everything, 5, 1).is_err());
+    }
 }
@@ -0,0 +1,100 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Keeps track of the files that were written during a run, so that
+/// they can be listed in a manifest at the end.
+#[derive(Default)]
+pub struct Manifest {
+    files: Vec<PathBuf>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest::default()
+    }
+
+    /// Records a file that was written. If the same file is added
+    /// multiple times, it'll only be listed once.
+    pub fn add<P: Into<PathBuf>>(&mut self, path: P) {
+        let path = path.into();
+        if !self.files.contains(&path) {
+            self.files.push(path);
+        }
+    }
+
+    /// The recorded files, in the order they were first added.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Writes the manifest as JSON, listing each recorded file
+    /// together with its size in bytes and its SHA-256 hash.
+    ///
+    /// The files are read back from disk, so the hashes reflect
+    /// their final contents.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                let contents = fs::read(file)?;
+                Ok(serde_json::json!({
+                    "path": file.to_string_lossy(),
+                    "size": contents.len(),
+                    "sha256": to_hex(&Sha256::digest(&contents)),
+                }))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let serialized = serde_json::ser::to_string_pretty(&serde_json::json!({ "files": files }))?;
+        fs::write(path, serialized.as_bytes())
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push(HEX[(b >> 4) as usize] as char);
+        s.push(HEX[(b & 0x0f) as usize] as char);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_lists_every_file_with_its_size_and_hash() {
+        let directory = tempfile::tempdir().unwrap();
+        let game_data = directory.path().join("game_data.json");
+        let icons = directory.path().join("game_icons.png");
+        fs::write(&game_data, b"{\"items\": []}").unwrap();
+        fs::write(&icons, [0x89, b'P', b'N', b'G', 0, 1, 2]).unwrap();
+
+        let mut manifest = Manifest::new();
+        manifest.add(&game_data);
+        manifest.add(&icons);
+        manifest.add(&game_data);
+        assert_eq!(manifest.files(), &[game_data.clone(), icons.clone()]);
+
+        let manifest_path = directory.path().join("manifest.json");
+        manifest.write(&manifest_path).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&fs::read(&manifest_path).unwrap()).unwrap();
+        let files = written["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        for (entry, path) in files.iter().zip(&[game_data, icons]) {
+            let contents = fs::read(path).unwrap();
+            assert_eq!(entry["path"], serde_json::Value::from(path.to_string_lossy()));
+            assert_eq!(entry["size"], serde_json::Value::from(contents.len()));
+            assert_eq!(entry["sha256"], serde_json::Value::from(to_hex(&Sha256::digest(&contents))));
+        }
+        assert_eq!(
+            files[0]["sha256"],
+            "eeb85c2675888473ec64b7580aa0c76c6fd6b2bd51828870286ef202ad89dae2"
+        );
+    }
+}
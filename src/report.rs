@@ -0,0 +1,177 @@
+use crate::manifest::Manifest;
+use graphio_rs_data::{ConsoleReporter, GameData, Reporter, Warning};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Keeps track of what happened during a run, being the stages that were
+/// performed and how long they took, and the warnings that didn't cause
+/// the run to fail, so that they can be written as a summary at the end.
+///
+/// Everything that's reported is printed as well, by a `ConsoleReporter`.
+#[derive(Default)]
+pub struct SummaryReporter {
+    console: ConsoleReporter,
+    stages: Vec<(&'static str, Duration)>,
+    warnings: Vec<Warning>,
+}
+
+impl SummaryReporter {
+    pub fn new() -> SummaryReporter {
+        SummaryReporter::default()
+    }
+
+    /// Performs a stage, recording how long it took if it succeeds.
+    pub fn stage<T, F>(&mut self, name: &'static str, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut SummaryReporter) -> io::Result<T>,
+    {
+        let start = Instant::now();
+        let result = f(self)?;
+        self.stages.push((name, start.elapsed()));
+        Ok(result)
+    }
+
+    /// The warnings that have been reported so far.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Writes the summary as JSON, listing the stages, the files in
+    /// `manifest`, the warnings, and if there's game data, the amount
+    /// of objects in each collection, the fraction of them of which the
+    /// name is translated, the amount of icons, and the version of the game
+    /// that it was extracted from.
+    pub fn write_summary<P: AsRef<Path>>(
+        &self,
+        path: P,
+        game_data: Option<&GameData>,
+        manifest: &Manifest,
+    ) -> io::Result<()> {
+        let stages = self
+            .stages
+            .iter()
+            .map(|(name, elapsed)| {
+                serde_json::json!({
+                    "name": name,
+                    "elapsed_seconds": elapsed.as_secs_f64(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let statistics = game_data.map(GameData::statistics);
+        let counts = statistics.map(|statistics| {
+            serde_json::json!({
+                "items": statistics.items,
+                "fluids": statistics.fluids,
+                "recipes": statistics.recipes,
+                "machines": statistics.machines,
+                "beacons": statistics.beacons,
+                "modules": statistics.modules,
+            })
+        });
+        let translation_coverage = statistics.map(|statistics| statistics.translation_coverage());
+        let icon_count = game_data
+            .and_then(|game_data| game_data.tile_metadata.as_ref())
+            .map(|tile_metadata| tile_metadata.tile_count);
+        let factorio_version = game_data.and_then(|game_data| game_data.factorio_version.as_ref());
+        let outputs = manifest
+            .files()
+            .iter()
+            .map(|file| file.to_string_lossy())
+            .collect::<Vec<_>>();
+        let warnings = self.warnings.iter().map(Warning::to_string).collect::<Vec<_>>();
+
+        let serialized = serde_json::ser::to_string_pretty(&serde_json::json!({
+            "stages": stages,
+            "counts": counts,
+            "translation_coverage": translation_coverage,
+            "icon_count": icon_count,
+            "factorio_version": factorio_version,
+            "outputs": outputs,
+            "warnings": warnings,
+        }))?;
+        fs::write(path, serialized.as_bytes())
+    }
+}
+
+impl Reporter for SummaryReporter {
+    fn info(&mut self, message: &str) {
+        self.console.info(message);
+    }
+
+    /// Prints a warning, and records it for the summary.
+    fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning.clone());
+        self.console.warn(warning);
+    }
+
+    fn progress(&mut self, stage: &str, done: usize, total: usize) {
+        self.console.progress(stage, done, total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use graphio_rs_data::{parse_prototypes_with_reporter, TransformOptions};
+    use serde_json::Value;
+
+    #[test]
+    fn summary_lists_stages_counts_outputs_and_warnings() {
+        let directory = tempfile::tempdir().unwrap();
+        let game_data_path = directory.path().join("game_data.json");
+        let summary_path = directory.path().join("summary.json");
+
+        let mut reporter = SummaryReporter::new();
+        let mut game_data = reporter
+            .stage("transform_data", |reporter| {
+                let prototypes = test_support::prototypes();
+                parse_prototypes_with_reporter(prototypes, TransformOptions::default(), reporter)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .unwrap();
+        game_data.factorio_version = Some("0.17.79".to_owned());
+        reporter.warn(Warning::from("something odd"));
+        fs::write(&game_data_path, serde_json::to_vec(&game_data).unwrap()).unwrap();
+        let mut manifest = Manifest::new();
+        manifest.add(&game_data_path);
+
+        reporter
+            .write_summary(&summary_path, Some(&game_data), &manifest)
+            .unwrap();
+        let summary: Value = serde_json::from_slice(&fs::read(&summary_path).unwrap()).unwrap();
+
+        assert_eq!(summary["stages"].as_array().unwrap().len(), 1);
+        assert_eq!(summary["stages"][0]["name"], "transform_data");
+        assert!(summary["stages"][0]["elapsed_seconds"].as_f64().unwrap() >= 0.0);
+        assert_eq!(summary["counts"]["items"], 11);
+        assert_eq!(summary["counts"]["fluids"], 2);
+        assert_eq!(summary["counts"]["recipes"], 5);
+        assert_eq!(summary["counts"]["machines"], 3);
+        assert_eq!(summary["counts"]["beacons"], 1);
+        assert_eq!(summary["counts"]["modules"], 2);
+        assert_eq!(summary["translation_coverage"], 1.0);
+        assert_eq!(summary["icon_count"], Value::Null);
+        assert_eq!(summary["factorio_version"], "0.17.79");
+        assert_eq!(summary["outputs"][0], game_data_path.to_string_lossy().as_ref());
+        assert_eq!(summary["warnings"], serde_json::json!(["something odd"]));
+    }
+
+    #[test]
+    fn summary_without_game_data() {
+        let directory = tempfile::tempdir().unwrap();
+        let summary_path = directory.path().join("summary.json");
+
+        SummaryReporter::new()
+            .write_summary(&summary_path, None, &Manifest::new())
+            .unwrap();
+        let summary: Value = serde_json::from_slice(&fs::read(&summary_path).unwrap()).unwrap();
+
+        assert_eq!(summary["stages"], serde_json::json!([]));
+        assert_eq!(summary["counts"], Value::Null);
+        assert_eq!(summary["outputs"], serde_json::json!([]));
+        assert_eq!(summary["warnings"], serde_json::json!([]));
+    }
+}
@@ -0,0 +1,144 @@
+use graphio_rs_data::{GameData, Reporter};
+use serde_json::Value;
+use std::io;
+
+type Serialize = fn(&GameData) -> io::Result<Vec<u8>>;
+type Deserialize = fn(&[u8]) -> io::Result<GameData>;
+
+/// The formats that game data can be stored in, which are checked by `run`.
+const FORMATS: &[(&str, Serialize, Deserialize)] = &[
+    ("json", to_json, from_json),
+    ("json_pretty", to_json_pretty, from_json),
+];
+
+fn to_json(game_data: &GameData) -> io::Result<Vec<u8>> {
+    Ok(serde_json::to_vec(game_data)?)
+}
+
+fn to_json_pretty(game_data: &GameData) -> io::Result<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(game_data)?)
+}
+
+fn from_json(bytes: &[u8]) -> io::Result<GameData> {
+    Ok(serde_json::from_slice(bytes)?)
+}
+
+/// Stores `game_data` in every format, and loads it back, checking that
+/// nothing changed. Reports whether each format passed, and fails if any
+/// of them didn't.
+pub fn run(game_data: &GameData, reporter: &mut dyn Reporter) -> io::Result<()> {
+    let expected = canonical_value(game_data)?;
+    let mut failures = 0;
+    for (name, serialize, deserialize) in FORMATS {
+        let result = serialize(game_data)
+            .and_then(|bytes| deserialize(&bytes))
+            .and_then(|round_tripped| canonical_value(&round_tripped))
+            .and_then(|actual| {
+                if actual == expected {
+                    Ok(())
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "differs from the original",
+                    ))
+                }
+            });
+        match result {
+            Ok(()) => reporter.info(&format!("{}: pass", name)),
+            Err(err) => {
+                reporter.info(&format!("{}: fail, {}", name, err));
+                failures += 1;
+            }
+        }
+    }
+
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} format(s) failed to round-trip the game data", failures),
+        ))
+    }
+}
+
+/// The fields holding hash sets, whose order differs between instances.
+const UNORDERED_FIELDS: &[&str] = &[
+    "items",
+    "fluids",
+    "recipes",
+    "machines",
+    "beacons",
+    "modules",
+    "crafted_in",
+    "supported_modules",
+];
+
+/// Converts game data into a JSON value, in which the fields holding hash
+/// sets are sorted, so that equal game data results in equal values.
+fn canonical_value(game_data: &GameData) -> io::Result<Value> {
+    let mut value = serde_json::to_value(game_data)?;
+    sort_unordered_fields(&mut value);
+    Ok(value)
+}
+
+fn sort_unordered_fields(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                sort_unordered_fields(value);
+                if let Value::Array(array) = value {
+                    if UNORDERED_FIELDS.contains(&key.as_str()) {
+                        array.sort_by_cached_key(|entry| entry.to_string());
+                    }
+                }
+            }
+        }
+        Value::Array(array) => {
+            for entry in array {
+                sort_unordered_fields(entry);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, RecordingReporter};
+    use graphio_rs_data::{Icon, TileMetadata};
+
+    #[test]
+    fn fixture_round_trips_through_every_format() {
+        let mut game_data = test_support::game_data();
+        game_data.factorio_version = Some("0.17.79".to_owned());
+        game_data.tile_metadata = Some(TileMetadata {
+            tile_size: (32, 32),
+            tile_count: 1,
+            image_size: (32, 32),
+            padding: 0,
+        });
+        game_data
+            .modify_metadata::<(), _>(|_, metadata| {
+                let mut metadata = metadata.clone();
+                metadata.icon = Some(Icon::new(0));
+                Ok(metadata)
+            })
+            .unwrap();
+        let mut reporter = RecordingReporter::default();
+        run(&game_data, &mut reporter).unwrap();
+        assert_eq!(reporter.infos, vec!["json: pass", "json_pretty: pass"]);
+    }
+
+    #[test]
+    fn canonical_value_ignores_the_order_of_hash_sets() {
+        let first = canonical_value(&test_support::game_data()).unwrap();
+        let second = canonical_value(&test_support::game_data()).unwrap();
+        assert_eq!(first, second);
+
+        let mut changed = test_support::game_data();
+        changed.factorio_version = Some("0.18.0".to_owned());
+        assert_ne!(canonical_value(&changed).unwrap(), first);
+    }
+}
@@ -0,0 +1,187 @@
+use graphio_rs_data::{GameData, Reporter};
+use serde_json::Value;
+use std::io;
+
+/// Parses game data, ignoring any fields that aren't understood, unless
+/// `strict` is set, in which case they're reported, and cause an error.
+pub fn parse_game_data(input: &[u8], strict: bool, reporter: &mut dyn Reporter) -> io::Result<GameData> {
+    if !strict {
+        return Ok(serde_json::de::from_slice(input)?);
+    }
+
+    let input: Value = serde_json::de::from_slice(input)?;
+    let game_data: GameData = serde_json::from_value(input.clone())?;
+    let unknown = unknown_fields(&input, &serde_json::to_value(&game_data)?);
+    if !unknown.is_empty() {
+        for field in &unknown {
+            reporter.info(&format!("unknown field: {}", field));
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("game data contains {} unknown field(s)", unknown.len()),
+        ));
+    }
+    Ok(game_data)
+}
+
+/// Finds the fields in `input` that aren't understood, by comparing it with
+/// `output`, being the result of deserializing `input` and serializing it
+/// again. Every field that was read is written back, so any field that is
+/// missing from `output` was ignored whilst deserializing.
+///
+/// Objects in arrays are matched up by their `id`, as the collections of game
+/// data are unordered, and otherwise by their position.
+///
+/// # Remark
+/// Fields that hold a default value (`null`, `false`, `0`, `[]` or `{}`)
+/// aren't reported, because known fields with those values are left out when
+/// serializing.
+pub fn unknown_fields(input: &Value, output: &Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    collect_unknown_fields(input, output, "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown_fields(input: &Value, output: &Value, path: &str, unknown: &mut Vec<String>) {
+    match (input, output) {
+        (Value::Object(input), Value::Object(output)) => {
+            for (key, input_value) in input {
+                let path = format!("{}/{}", path, key);
+                match output.get(key) {
+                    Some(output_value) => {
+                        collect_unknown_fields(input_value, output_value, &path, unknown)
+                    }
+                    None if is_default(input_value) => {}
+                    None => unknown.push(path),
+                }
+            }
+        }
+        (Value::Array(input), Value::Array(output)) => {
+            for (index, input_value) in input.iter().enumerate() {
+                let output_value = match input_value.get("id") {
+                    Some(id) => output.iter().find(|value| value.get("id") == Some(id)),
+                    None => output.get(index),
+                };
+                if let Some(output_value) = output_value {
+                    let path = format!("{}/{}", path, index);
+                    collect_unknown_fields(input_value, output_value, &path, unknown);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `value` is what a field that's skipped when serializing holds.
+fn is_default(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(value) => !value,
+        Value::Number(number) => number.as_f64() == Some(0.0),
+        Value::Array(array) => array.is_empty(),
+        Value::Object(object) => object.is_empty(),
+        Value::String(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::RecordingReporter;
+    use serde_json::json;
+
+    fn game_data(extra: Value) -> Value {
+        let mut game_data = json!({
+            "items": [{ "id": "iron-plate", "localised_name": "Iron plate" }],
+            "fluids": [],
+            "recipes": [],
+            "machines": [],
+            "beacons": [],
+            "modules": [],
+        });
+        if let (Value::Object(game_data), Value::Object(extra)) = (&mut game_data, extra) {
+            game_data.extend(extra);
+        }
+        game_data
+    }
+
+    fn parse(input: &Value, strict: bool) -> io::Result<GameData> {
+        parse_game_data(&serde_json::to_vec(input).unwrap(), strict, &mut RecordingReporter::default())
+    }
+
+    #[test]
+    fn extra_top_level_key_is_only_rejected_when_strict() {
+        let input = game_data(json!({ "schema": "from the future" }));
+        let game_data = parse(&input, false).unwrap();
+        assert_eq!(game_data.items.len(), 1);
+
+        let mut reporter = RecordingReporter::default();
+        let error = parse_game_data(&serde_json::to_vec(&input).unwrap(), true, &mut reporter).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "game data contains 1 unknown field(s)");
+        assert_eq!(reporter.infos, vec!["unknown field: /schema"]);
+    }
+
+    #[test]
+    fn explicit_defaults_are_not_unknown() {
+        let input = game_data(json!({ "factorio_version": null }));
+        let output = serde_json::to_value(parse(&input, true).unwrap()).unwrap();
+        assert!(unknown_fields(&input, &output).is_empty());
+
+        let input = json!({ "flag": false, "count": 0, "ratio": 0.0, "list": [], "map": {}, "value": null });
+        assert!(unknown_fields(&input, &json!({})).is_empty());
+    }
+
+    #[test]
+    fn explicit_zero_padding_is_not_unknown() {
+        let input = game_data(json!({
+            "tile_metadata": {
+                "tile_size": [32, 32],
+                "tile_count": 4,
+                "image_size": [64, 64],
+                "padding": 0,
+            },
+        }));
+        let game_data = parse(&input, true).unwrap();
+        assert_eq!(game_data.tile_metadata.unwrap().padding, 0);
+    }
+
+    #[test]
+    fn explicit_translated_name_is_not_unknown() {
+        let mut input = game_data(json!({}));
+        input["items"][0]["untranslated_name"] = json!(false);
+        let game_data = parse(&input, true).unwrap();
+        assert!(game_data.items.iter().all(|item| item.metadata.is_translated()));
+    }
+
+    #[test]
+    fn non_default_values_of_unknown_fields_are_reported() {
+        let input = json!({
+            "flag": true,
+            "count": 1,
+            "name": "",
+            "list": [0],
+            "map": { "key": null },
+        });
+        let mut unknown = unknown_fields(&input, &json!({}));
+        unknown.sort();
+        assert_eq!(unknown, vec!["/count", "/flag", "/list", "/map", "/name"]);
+    }
+
+    #[test]
+    fn nested_unknown_fields_are_matched_by_id() {
+        let input = json!({
+            "items": [
+                { "id": "b", "known": 1 },
+                { "id": "a", "known": 2, "extra": 3 },
+            ],
+        });
+        let output = json!({
+            "items": [
+                { "id": "a", "known": 2 },
+                { "id": "b", "known": 1 },
+            ],
+        });
+        assert_eq!(unknown_fields(&input, &output), vec!["/items/1/extra"]);
+    }
+}
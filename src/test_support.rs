@@ -0,0 +1,92 @@
+//! Data shared by the tests of the different stages.
+
+use crate::factorio_io::FactorioPaths;
+use graphio_rs_data::{parse_prototypes, GameData, Reporter, TransformOptions, Warning, ID};
+use image::{ColorType, RgbaImage};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// The records of a small export, as stored in `prototypes.json`, which the
+/// data crate's integration tests use as well.
+pub const PROTOTYPES: &str = include_str!("../data/tests/fixtures/prototypes.json");
+
+pub fn prototypes() -> Vec<String> {
+    serde_json::from_str(PROTOTYPES).unwrap()
+}
+
+/// The game data that the fixture transforms into.
+pub fn game_data() -> GameData {
+    parse_prototypes(prototypes(), TransformOptions::default()).unwrap()
+}
+
+/// Paths to a Factorio installation in `root`, with an empty script output
+/// directory.
+pub fn factorio_paths(root: &Path) -> FactorioPaths {
+    let script_output_directory = root.join("script-output");
+    fs::create_dir_all(&script_output_directory).unwrap();
+    FactorioPaths {
+        executable_candidates: vec![root.join("bin").join("x64").join("factorio")],
+        scenarios_directory: root.join("scenarios"),
+        script_output_directory,
+    }
+}
+
+/// The color of the icon of an object named `name`. Objects with the same
+/// name share their color, like a recipe that's named after its product
+/// usually shares the product's icon.
+pub fn icon_color(name: &str) -> [u8; 4] {
+    let hash = Sha256::digest(name.as_bytes());
+    [hash[0], hash[1], hash[2], 255]
+}
+
+/// Writes a square RGBA icon of a single color, creating its directory.
+pub fn write_icon(path: &Path, color: [u8; 4], size: u32) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let image = RgbaImage::from_pixel(size, size, image::Rgba(color));
+    image::save_buffer(path, &image.into_raw(), size, size, ColorType::RGBA(8)).unwrap();
+}
+
+/// Writes the 32x32 icon of every object in `game_data` to
+/// `light_directory` within `icon_directory`, as `IconMode::Rgba` reads
+/// them, in the color given by `icon_color`.
+pub fn write_icons(icon_directory: &Path, light_directory: &str, game_data: &GameData) {
+    write_scaled_icons(icon_directory, light_directory, game_data, 1);
+}
+
+/// Like `write_icons`, but as extracted with `--icon_scale scale`.
+pub fn write_scaled_icons(icon_directory: &Path, light_directory: &str, game_data: &GameData, scale: u32) {
+    for id in game_data.all_ids() {
+        let path = icon_directory
+            .join(light_directory)
+            .join(crate::icon_category(id))
+            .join(format!("{}.png", id.str()));
+        write_icon(&path, icon_color(id.str()), 32 * scale);
+    }
+}
+
+/// The amount of distinct icons that `write_icons` writes for `game_data`.
+pub fn distinct_icon_count(game_data: &GameData) -> usize {
+    let mut names = game_data.all_ids().map(|id: ID| id.str()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names.len()
+}
+
+/// Keeps everything that's reported, so that tests can check what a stage
+/// told the user.
+#[derive(Default)]
+pub struct RecordingReporter {
+    pub infos: Vec<String>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Reporter for RecordingReporter {
+    fn info(&mut self, message: &str) {
+        self.infos.push(message.to_owned());
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+}
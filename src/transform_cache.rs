@@ -0,0 +1,167 @@
+use crate::manifest::to_hex;
+use crate::report::SummaryReporter;
+use graphio_rs_data::{GameData, Reporter, Warning};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Stores the game data parsed from the exported prototypes, so that
+/// transforming the same prototypes again can skip parsing them.
+pub struct TransformCache {
+    directory: PathBuf,
+}
+
+/// The game data parsed from a set of prototypes, together with the
+/// warnings encountered, so that they can be reported again.
+pub struct CacheEntry {
+    pub game_data: GameData,
+    pub warnings: Vec<Warning>,
+}
+
+impl TransformCache {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> TransformCache {
+        TransformCache {
+            directory: directory.into(),
+        }
+    }
+
+    /// The key under which the game data parsed from `prototypes` is
+    /// stored. `options` describes anything else that affects the game
+    /// data, so that changing it doesn't reuse an outdated entry.
+    fn key(prototypes: &[String], options: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.input([0]);
+        hasher.input(options.as_bytes());
+        for prototype in prototypes {
+            hasher.input((prototype.len() as u64).to_le_bytes());
+            hasher.input(prototype.as_bytes());
+        }
+        to_hex(&hasher.result())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+
+    /// Loads the entry stored under `key`. An entry that can't be read is
+    /// treated as missing, as it'll be replaced once the game data has been
+    /// parsed again.
+    fn load(&self, key: &str) -> Option<CacheEntry> {
+        let contents = fs::read(self.path(key)).ok()?;
+        let mut entry: serde_json::Value = serde_json::de::from_slice(&contents).ok()?;
+        let game_data = serde_json::from_value(entry["game_data"].take()).ok()?;
+        let warnings = serde_json::from_value(entry["warnings"].take()).ok()?;
+        Some(CacheEntry { game_data, warnings })
+    }
+
+    /// Stores an entry under `key`, replacing any existing one.
+    fn store(&self, key: &str, game_data: &GameData, warnings: &[Warning]) -> io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let serialized = serde_json::ser::to_string_pretty(&serde_json::json!({
+            "game_data": game_data,
+            "warnings": warnings,
+        }))?;
+        fs::write(self.path(key), serialized.as_bytes())
+    }
+
+    /// Gets the game data parsed from `prototypes` with `options`. If it's
+    /// stored, the warnings that were encountered whilst parsing are
+    /// reported again. Otherwise `parse` is called, and its game data is
+    /// stored, along with the warnings that it reported.
+    pub fn parse_with<F>(
+        &self,
+        prototypes: Vec<String>,
+        options: &str,
+        reporter: &mut SummaryReporter,
+        parse: F,
+    ) -> io::Result<GameData>
+    where
+        F: FnOnce(&mut SummaryReporter, Vec<String>) -> io::Result<GameData>,
+    {
+        let key = TransformCache::key(&prototypes, options);
+        if let Some(entry) = self.load(&key) {
+            reporter.info(&format!("reusing the cached game data for these prototypes: {}", key));
+            for warning in entry.warnings {
+                reporter.warn(warning);
+            }
+            return Ok(entry.game_data);
+        }
+
+        let previous_warnings = reporter.warnings().len();
+        let game_data = parse(reporter, prototypes)?;
+        self.store(&key, &game_data, &reporter.warnings()[previous_warnings..])?;
+        Ok(game_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use graphio_rs_data::{ParseWarning, RecipeID, Str};
+    use std::cell::Cell;
+
+    fn duplicate_crafted_in() -> Warning {
+        Warning::Parse(ParseWarning::DuplicateCraftedIn {
+            recipe: RecipeID(Str::new("iron-gear-wheel")),
+            read: 3,
+            stored: 2,
+        })
+    }
+
+    #[test]
+    fn identical_prototypes_are_parsed_once() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = TransformCache::new(directory.path());
+        let parsed = Cell::new(0);
+        let parse = |reporter: &mut SummaryReporter, _: Vec<String>| {
+            parsed.set(parsed.get() + 1);
+            reporter.warn("something looks off".to_owned().into());
+            reporter.warn(duplicate_crafted_in());
+            Ok(test_support::game_data())
+        };
+
+        let mut reporter = SummaryReporter::new();
+        let first = cache
+            .parse_with(test_support::prototypes(), "options", &mut reporter, parse)
+            .unwrap();
+        let mut reporter = SummaryReporter::new();
+        let second = cache
+            .parse_with(test_support::prototypes(), "options", &mut reporter, parse)
+            .unwrap();
+
+        assert_eq!(parsed.get(), 1);
+        assert_eq!(
+            serde_json::to_value(&first).unwrap(),
+            serde_json::to_value(&second).unwrap()
+        );
+        // The warnings are reported again, without parsing, and keep their
+        // kind.
+        assert_eq!(
+            reporter.warnings(),
+            [Warning::from("something looks off"), duplicate_crafted_in()]
+        );
+
+        // Different options are a different entry.
+        cache
+            .parse_with(test_support::prototypes(), "other options", &mut reporter, parse)
+            .unwrap();
+        assert_eq!(parsed.get(), 2);
+    }
+
+    #[test]
+    fn failed_parse_isnt_stored() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = TransformCache::new(directory.path());
+        let mut reporter = SummaryReporter::new();
+        let fail = |_: &mut SummaryReporter, _: Vec<String>| Err(io::Error::new(io::ErrorKind::InvalidData, "bad"));
+        assert!(cache
+            .parse_with(test_support::prototypes(), "options", &mut reporter, fail)
+            .is_err());
+        assert!(cache
+            .parse_with(test_support::prototypes(), "options", &mut reporter, |_, _| Ok(test_support::game_data()))
+            .is_ok());
+    }
+}
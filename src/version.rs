@@ -0,0 +1,193 @@
+use crate::factorio_io::FactorioPaths;
+use std::fmt;
+use std::io;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// A version of the game, such as `0.16.51`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Parses a version from up to 3 numbers separated by dots, where the
+    /// numbers that are left out are 0, so that `0.16` is `0.16.0`.
+    fn from_str(s: &str) -> Result<Version, String> {
+        let invalid = || format!("invalid version: {}", s);
+        let mut numbers = [0u32; 3];
+        let mut parts = s.split('.');
+        for number in numbers.iter_mut() {
+            match parts.next() {
+                Some(part) => *number = part.parse().map_err(|_| invalid())?,
+                None => break,
+            }
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Version {
+            major: numbers[0],
+            minor: numbers[1],
+            patch: numbers[2],
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The versions of the game that are expected, from `min` up to, but not
+/// including, `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: Version,
+    pub max: Option<Version>,
+}
+
+/// The versions of the game that the extraction scripts were written for.
+pub const SUPPORTED_VERSIONS: &str = "0.16..0.18";
+
+impl VersionRange {
+    pub fn contains(&self, version: Version) -> bool {
+        version >= self.min && self.max.map(|max| version < max).unwrap_or(true)
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = String;
+
+    /// Parses either `<min>..<max>`, or `<min>..` for a range without an
+    /// upper bound.
+    fn from_str(s: &str) -> Result<VersionRange, String> {
+        let mut bounds = s.splitn(2, "..");
+        let min = bounds.next().unwrap().parse()?;
+        let max = match bounds.next() {
+            Some("") => None,
+            Some(max) => Some(max.parse()?),
+            None => return Err(format!("expected a range such as {}, got: {}", SUPPORTED_VERSIONS, s)),
+        };
+        Ok(VersionRange { min, max })
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            Some(max) => write!(f, "{}..{}", self.min, max),
+            None => write!(f, "{}..", self.min),
+        }
+    }
+}
+
+/// How the version of the game compares to the expected versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionCheck {
+    Expected(Version),
+    Unexpected(Version),
+    /// The output of `factorio --version` didn't contain a version.
+    Unrecognized,
+}
+
+impl VersionCheck {
+    pub fn version(&self) -> Option<Version> {
+        match *self {
+            VersionCheck::Expected(version) | VersionCheck::Unexpected(version) => Some(version),
+            VersionCheck::Unrecognized => None,
+        }
+    }
+}
+
+/// Runs `factorio --version`, and returns what it wrote to stdout.
+pub fn run_version_command(paths: &FactorioPaths) -> io::Result<String> {
+    let output = Command::new(paths.executable()?)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Detects the version of the game from the output of `run`, which runs
+/// `factorio --version`, such as `run_version_command`, and compares it to
+/// `expected`. Only failing to run the command is an error.
+pub fn check_version<F>(run: F, expected: VersionRange) -> io::Result<VersionCheck>
+where
+    F: FnOnce() -> io::Result<String>,
+{
+    Ok(match parse_version_output(&run()?) {
+        Some(version) if expected.contains(version) => VersionCheck::Expected(version),
+        Some(version) => VersionCheck::Unexpected(version),
+        None => VersionCheck::Unrecognized,
+    })
+}
+
+/// Reads the version from the output of `factorio --version`, of which the
+/// first line looks like `Version: 0.16.51 (build 36654, win64, steam)`.
+pub fn parse_version_output(output: &str) -> Option<Version> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Version:"))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .filter_map(|version| version.parse().ok())
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u32, minor: u32, patch: u32) -> Version {
+        Version { major, minor, patch }
+    }
+
+    fn check(output: &'static str) -> VersionCheck {
+        check_version(|| Ok(output.to_owned()), SUPPORTED_VERSIONS.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn version_is_read_from_the_output() {
+        let output = "Version: 0.16.51 (build 36654, win64, steam)\nBinary version: 64\n";
+        assert_eq!(parse_version_output(output), Some(version(0, 16, 51)));
+        // Anything that the game logs before it is skipped.
+        let output = "   0.001 Running in headless mode\n  Version: 0.17.79 (build 49782, linux64, headless)\n";
+        assert_eq!(parse_version_output(output), Some(version(0, 17, 79)));
+    }
+
+    #[test]
+    fn output_without_a_version_is_unrecognized() {
+        assert_eq!(parse_version_output(""), None);
+        assert_eq!(parse_version_output("Binary version: 64"), None);
+        assert_eq!(parse_version_output("Version: unknown"), None);
+        assert_eq!(parse_version_output("Version: 0.16.51.1"), None);
+    }
+
+    #[test]
+    fn detected_version_is_compared_to_the_range() {
+        assert_eq!(
+            check("Version: 0.16.51 (build 36654, win64, steam)"),
+            VersionCheck::Expected(version(0, 16, 51))
+        );
+        assert_eq!(check("Version: 0.17.0"), VersionCheck::Expected(version(0, 17, 0)));
+        // The upper bound is exclusive.
+        assert_eq!(check("Version: 0.18.0"), VersionCheck::Unexpected(version(0, 18, 0)));
+        assert_eq!(check("Version: 0.15.40"), VersionCheck::Unexpected(version(0, 15, 40)));
+        assert_eq!(check("factorio: unrecognized option"), VersionCheck::Unrecognized);
+        assert_eq!(VersionCheck::Unrecognized.version(), None);
+    }
+
+    #[test]
+    fn failing_to_run_the_command_is_an_error() {
+        let run = || Err(io::Error::new(io::ErrorKind::NotFound, "no factorio"));
+        let error = check_version(run, SUPPORTED_VERSIONS.parse().unwrap()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}